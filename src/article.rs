@@ -1,14 +1,297 @@
+use clap::ValueEnum;
 use roxmltree::Node;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Optional parts of the article metadata. Identifiers, title and abstract are always extracted.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArticleField {
+    Authors,
+    Journal,
+    /// The publication year.
+    Date,
+    Language,
+    Mesh,
+}
+
+/// How much of every record is read. The streaming backend skips the subtrees a profile leaves
+/// out without parsing them, which matters most for the large AuthorList and ReferenceList
+/// elements. roxmltree has parsed the whole record by then, so the binary only accepts profiles
+/// other than standard together with quick-xml.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractionProfile {
+    /// Only ids, title and the main abstract, e.g. to collect the PMIDs and DOIs of a topic.
+    Minimal,
+    /// Ids, title, all abstracts and the optional fields that were asked for.
+    Standard,
+    /// Everything, as if all optional fields were asked for.
+    Full,
+}
+
+impl ExtractionProfile {
+    pub fn extraction(&self, fields: &[ArticleField]) -> Extraction {
+        match self {
+            ExtractionProfile::Minimal => Extraction {
+                fields: vec![],
+                other_abstracts: false,
+                keep_invalid: false,
+                max_record_bytes: None,
+                oversized_records: OversizedPolicy::Truncate,
+            },
+            ExtractionProfile::Standard => Extraction {
+                fields: fields.to_vec(),
+                other_abstracts: true,
+                keep_invalid: false,
+                max_record_bytes: None,
+                oversized_records: OversizedPolicy::Truncate,
+            },
+            ExtractionProfile::Full => Extraction {
+                fields: ArticleField::value_variants().to_vec(),
+                other_abstracts: true,
+                keep_invalid: false,
+                max_record_bytes: None,
+                oversized_records: OversizedPolicy::Truncate,
+            },
+        }
+    }
+}
+
+/// The parts of a record a backend extracts, see ExtractionProfile. Ids, title and the main
+/// abstract are always extracted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Extraction {
+    pub fields: Vec<ArticleField>,
+    pub other_abstracts: bool,
+    /// Also return the records without a title or DOI, which the pipeline leaves out.
+    pub keep_invalid: bool,
+    /// The most bytes of text a record may have, see Article::text_size. Records above it are
+    /// handled by `oversized_records` as soon as they are parsed, before they are collected.
+    pub max_record_bytes: Option<usize>,
+    pub oversized_records: OversizedPolicy,
+}
+
+impl Extraction {
+    pub fn has(&self, field: ArticleField) -> bool {
+        self.fields.contains(&field)
+    }
+}
+
+/// Which versions of a versioned citation (`<PMID Version="2">`) to keep.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionPolicy {
+    /// Keep every version as a record of its own.
+    All,
+    /// Keep only the highest version of every PMID.
+    Latest,
+}
+
+/// What happens to articles above the size limit of a record.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizedPolicy {
+    /// Shorten the abstract, then drop authors and MeSH terms from the end until it fits.
+    Truncate,
+    /// Leave the article out.
+    Skip,
+}
+
+/// Which abstract of an article is used, for filtering or for the output.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AbstractSource {
+    /// The Abstract element, which is in English.
+    Main,
+    /// The first OtherAbstract, usually a translation provided by the publisher.
+    Other,
+    /// All abstracts, one after the other.
+    All,
+}
+
+/// Something unexpected in a record that did not stop it from being parsed.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// The Article element has more than one ArticleTitle, the last one is kept.
+    MultipleTitles,
+    /// The record has a title but no DOI, so it is left out.
+    MissingDoi,
+    /// The record was above --max-record-bytes and was truncated or left out.
+    OversizedRecord,
+    /// The abstract was shortened to fit into --max-record-bytes.
+    TruncatedAbstract,
+    /// The PubmedArticle has a child element the parser does not know, which may hold data that
+    /// is silently lost.
+    UnknownElement,
+}
+
+impl WarningKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            WarningKind::MultipleTitles => "multiple article titles found",
+            WarningKind::MissingDoi => "records without a DOI were left out",
+            WarningKind::OversizedRecord => {
+                "records above the size limit were truncated or left out"
+            }
+            WarningKind::TruncatedAbstract => "abstracts were truncated to the record size limit",
+            WarningKind::UnknownElement => "unknown elements in PubmedArticle were ignored",
+        }
+    }
+}
+
+/// An OtherAbstract element, e.g. the abstract in the language of a non-English journal.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OtherAbstract {
+    /// The language code of the abstract, e.g. `ger`.
+    pub language: String,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Author {
+    pub last_name: String,
+    pub fore_name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub affiliations: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Journal {
+    pub title: String,
+    pub issn: String,
+    pub volume: String,
+    pub issue: String,
+    pub pages: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Article {
     pub title: String,
     pub pmid: String,
+    /// Versioned citations, e.g. updated preprints, have several records with the same PMID.
+    #[serde(default = "first_version")]
+    pub pmid_version: u32,
     pub doi: String,
     pub pmc: String,
     pub pii: String,
+    #[serde(rename = "abstract", alias = "paper_abstract")]
     pub paper_abstract: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<Author>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub journal: Option<Journal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publication_year: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mesh_terms: Vec<String>,
+    /// Only used to choose the abstract, the chosen text is written as `abstract`.
+    #[serde(skip)]
+    pub other_abstracts: Vec<OtherAbstract>,
+    /// The warnings found while parsing the record, reported by the backend once it is complete.
+    #[serde(skip)]
+    pub warnings: Vec<WarningKind>,
+    /// The bytes of the PubmedArticle element in the decompressed source file, if the backend
+    /// read the record from one.
+    #[serde(skip)]
+    pub source_range: Option<std::ops::Range<usize>>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// The version of a PMID element. Citations without a version attribute are version 1.
+pub fn pmid_version(attribute: Option<&str>) -> u32 {
+    attribute.and_then(|v| v.parse().ok()).unwrap_or(1)
+}
+
+/// Removes duplicate records. Records are identified by PMID and version, so the versions of a
+/// versioned citation are not collapsed into one unless only the latest version is kept. A later
+/// record replaces an earlier one with the same key in place. Only the articles of one input file
+/// are compared, duplicates in different files are all kept.
+pub fn dedup_versions(articles: Vec<Article>, policy: VersionPolicy) -> Vec<Article> {
+    let mut kept: Vec<Article> = Vec::with_capacity(articles.len());
+    let mut positions: HashMap<(String, u32), usize> = HashMap::new();
+    for article in articles {
+        if article.pmid.is_empty() {
+            kept.push(article);
+            continue;
+        }
+        let key = match policy {
+            VersionPolicy::All => (article.pmid.clone(), article.pmid_version),
+            VersionPolicy::Latest => (article.pmid.clone(), 0),
+        };
+        match positions.get(&key) {
+            Some(&position) => {
+                if article.pmid_version >= kept[position].pmid_version {
+                    kept[position] = article;
+                }
+            }
+            None => {
+                positions.insert(key, kept.len());
+                kept.push(article);
+            }
+        }
+    }
+    kept
+}
+
+/// One section of a structured abstract, e.g. `RESULTS: ...`.
+pub fn abstract_section(label: Option<&str>, text: String) -> String {
+    match label {
+        Some(label) => format!("{}: {}", label, text),
+        None => text,
+    }
+}
+
+/// The year of a PubDate, which is either a plain year or a MedlineDate like `1998 Dec-1999 Jan`.
+pub fn year_from_date(date: &str) -> Option<u32> {
+    date.get(0..4).and_then(|y| y.parse().ok())
+}
+
+/// Structured abstracts consist of several labeled sections, which are joined into one text
+/// as `LABEL: text`, one section per line.
+fn abstract_sections(abstract_node: Node) -> String {
+    let sections: Vec<String> = abstract_node
+        .children()
+        .filter(|c| c.tag_name().name() == "AbstractText")
+        .map(|section| abstract_section(section.attribute("Label"), text_of(section)))
+        .collect();
+    sections.join("\n")
+}
+
+/// The text of a node including the text of inline markup like <i> or <sup>.
+fn text_of(node: Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect()
+}
+
+fn child_text(node: Node, tag_name: &str) -> String {
+    node.children()
+        .find(|c| c.tag_name().name() == tag_name)
+        .map(text_of)
+        .unwrap_or_default()
+}
+
+impl Author {
+    fn text_size(&self) -> usize {
+        self.last_name.len()
+            + self.fore_name.len()
+            + self.affiliations.iter().map(|a| a.len()).sum::<usize>()
+    }
+}
+
+impl Default for Article {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Article {
@@ -17,68 +300,258 @@ impl Article {
             title: String::new(),
             doi: String::new(),
             pmid: String::new(),
+            pmid_version: 1,
             pii: String::new(),
             pmc: String::new(),
             paper_abstract: String::new(),
+            authors: vec![],
+            journal: None,
+            publication_year: None,
+            languages: vec![],
+            mesh_terms: vec![],
+            other_abstracts: vec![],
+            warnings: vec![],
+            source_range: None,
         }
     }
 
-    pub fn set_from_article_data(&mut self, node: Node) {
+    /// The bytes of text in the record, which is roughly the size of its output line.
+    pub fn text_size(&self) -> usize {
+        let journal = self.journal.as_ref().map_or(0, |j| {
+            j.title.len() + j.issn.len() + j.volume.len() + j.issue.len() + j.pages.len()
+        });
+        self.title.len()
+            + self.pmid.len()
+            + self.doi.len()
+            + self.pmc.len()
+            + self.pii.len()
+            + self.paper_abstract.len()
+            + self.authors.iter().map(Author::text_size).sum::<usize>()
+            + journal
+            + self.languages.iter().map(|l| l.len()).sum::<usize>()
+            + self.mesh_terms.iter().map(|m| m.len()).sum::<usize>()
+            + self
+                .other_abstracts
+                .iter()
+                .map(|o| o.language.len() + o.text.len())
+                .sum::<usize>()
+    }
+
+    /// Shortens the abstract, then drops authors, MeSH terms and other abstracts from the end,
+    /// then the languages and the journal, and finally shortens the title, until the record has
+    /// at most `limit` bytes of text. Identifiers are never cut, so returns false if they alone
+    /// are above the limit.
+    pub fn truncate_to(&mut self, limit: usize) -> bool {
+        let mut size = self.text_size();
+        if size <= limit {
+            return true;
+        }
+        let mut keep = self.paper_abstract.len().saturating_sub(size - limit);
+        while !self.paper_abstract.is_char_boundary(keep) {
+            keep -= 1;
+        }
+        size -= self.paper_abstract.len() - keep;
+        self.paper_abstract.truncate(keep);
+        while size > limit {
+            match self.authors.pop() {
+                Some(author) => size -= author.text_size(),
+                None => break,
+            }
+        }
+        while size > limit {
+            match self.mesh_terms.pop() {
+                Some(term) => size -= term.len(),
+                None => break,
+            }
+        }
+        while size > limit {
+            match self.other_abstracts.pop() {
+                Some(other) => size -= other.language.len() + other.text.len(),
+                None => break,
+            }
+        }
+        if size > limit {
+            size -= self.languages.drain(..).map(|l| l.len()).sum::<usize>();
+        }
+        if size > limit {
+            if let Some(j) = self.journal.take() {
+                size -=
+                    j.title.len() + j.issn.len() + j.volume.len() + j.issue.len() + j.pages.len();
+            }
+        }
+        if size > limit {
+            let mut keep = self.title.len().saturating_sub(size - limit);
+            while !self.title.is_char_boundary(keep) {
+                keep -= 1;
+            }
+            size -= self.title.len() - keep;
+            self.title.truncate(keep);
+        }
+        size <= limit
+    }
+
+    pub fn set_from_article_data(&mut self, node: Node, extraction: &Extraction) {
         for child in node.children() {
             match child.tag_name().name() {
                 "ArticleTitle" => {
-                    if self.title != "".to_string() {
-                        println!("multiple article titles found.");
+                    if !self.title.is_empty() {
+                        self.warnings.push(WarningKind::MultipleTitles);
                     }
-                    self.title = child.text().unwrap_or("").to_string()
+                    self.title = text_of(child)
                 }
-                "Abstract" => {
-                    for abstract_node in child.children() {
-                        if abstract_node.tag_name().name() == "AbstractText" {
-                            self.paper_abstract = abstract_node.text().unwrap_or("").to_string();
-                        }
+                "Abstract" => self.set_abstract(child),
+                "AuthorList" if extraction.has(ArticleField::Authors) => self.set_authors(child),
+                "Journal" => {
+                    if extraction.has(ArticleField::Journal) {
+                        self.set_journal(child);
                     }
+                    if extraction.has(ArticleField::Date) {
+                        self.set_publication_year(child);
+                    }
+                }
+                "Pagination" if extraction.has(ArticleField::Journal) => {
+                    let pages = child_text(child, "MedlinePgn");
+                    self.journal.get_or_insert_with(Journal::default).pages = pages;
+                }
+                "Language" if extraction.has(ArticleField::Language) => {
+                    self.languages.push(text_of(child))
                 }
                 _ => {}
             }
         }
     }
 
+    fn set_abstract(&mut self, abstract_node: Node) {
+        self.paper_abstract = abstract_sections(abstract_node);
+    }
+
+    pub fn add_other_abstract(&mut self, other_abstract_node: Node) {
+        self.other_abstracts.push(OtherAbstract {
+            language: other_abstract_node
+                .attribute("Language")
+                .unwrap_or_default()
+                .to_string(),
+            text: abstract_sections(other_abstract_node),
+        });
+    }
+
+    /// The abstract text from a source. With a language, only OtherAbstracts in that language
+    /// count. Articles without such an abstract fall back to the main abstract.
+    pub fn abstract_text(&self, source: AbstractSource, language: Option<&str>) -> Cow<'_, str> {
+        let mut others = self.other_abstracts.iter().filter(|other| match language {
+            Some(language) => other.language == language,
+            None => true,
+        });
+        match source {
+            AbstractSource::Main => Cow::Borrowed(&self.paper_abstract),
+            AbstractSource::Other => match others.next() {
+                Some(other) => Cow::Borrowed(&other.text),
+                None => Cow::Borrowed(&self.paper_abstract),
+            },
+            AbstractSource::All => {
+                let mut texts = vec![self.paper_abstract.as_str()];
+                texts.extend(others.map(|other| other.text.as_str()));
+                texts.retain(|text| !text.is_empty());
+                Cow::Owned(texts.join("\n"))
+            }
+        }
+    }
+
+    fn set_authors(&mut self, author_list: Node) {
+        for author in author_list
+            .children()
+            .filter(|c| c.tag_name().name() == "Author")
+        {
+            let mut last_name = child_text(author, "LastName");
+            if last_name.is_empty() {
+                last_name = child_text(author, "CollectiveName");
+            }
+            let affiliations = author
+                .children()
+                .filter(|c| c.tag_name().name() == "AffiliationInfo")
+                .map(|info| child_text(info, "Affiliation"))
+                .collect();
+            self.authors.push(Author {
+                last_name,
+                fore_name: child_text(author, "ForeName"),
+                affiliations,
+            });
+        }
+    }
+
+    fn set_journal(&mut self, journal_node: Node) {
+        let journal = self.journal.get_or_insert_with(Journal::default);
+        journal.title = child_text(journal_node, "Title");
+        journal.issn = child_text(journal_node, "ISSN");
+        if let Some(issue) = journal_node
+            .children()
+            .find(|c| c.tag_name().name() == "JournalIssue")
+        {
+            journal.volume = child_text(issue, "Volume");
+            journal.issue = child_text(issue, "Issue");
+        }
+    }
+
+    fn set_publication_year(&mut self, journal_node: Node) {
+        let pub_date = journal_node
+            .descendants()
+            .find(|c| c.tag_name().name() == "PubDate");
+        if let Some(pub_date) = pub_date {
+            let mut year = child_text(pub_date, "Year");
+            if year.is_empty() {
+                year = child_text(pub_date, "MedlineDate");
+            }
+            self.publication_year = year_from_date(&year);
+        }
+    }
+
+    pub fn set_mesh_terms(&mut self, mesh_heading_list: Node) {
+        for heading in mesh_heading_list
+            .children()
+            .filter(|c| c.tag_name().name() == "MeshHeading")
+        {
+            let descriptor = child_text(heading, "DescriptorName");
+            if !descriptor.is_empty() {
+                self.mesh_terms.push(descriptor);
+            }
+        }
+    }
+
+    pub fn set_pmid_version(&mut self, medline_citation: Node) {
+        if let Some(pmid) = medline_citation
+            .children()
+            .find(|c| c.tag_name().name() == "PMID")
+        {
+            self.pmid_version = pmid_version(pmid.attribute("Version"));
+        }
+    }
+
     pub fn set_from_pubmed_data(&mut self, node: Node) {
         for child in node.children() {
-            match child.tag_name().name() {
-                "ArticleIdList" => self.set_doi_for_id_list(child),
-                _ => {}
+            if child.tag_name().name() == "ArticleIdList" {
+                self.set_doi_for_id_list(child);
             }
         }
     }
 
     pub fn set_doi_for_id_list(&mut self, article_id_list: Node) {
         for child in article_id_list.children() {
-            match child.tag_name().name() {
-                "ArticleId" => {
-                    if child.has_attribute("IdType") {
-                        if child.attribute("IdType").unwrap_or_default() == "doi".to_string() {
-                            self.doi = child.text().unwrap_or("").to_string();
-                        }
-                        if child.attribute("IdType").unwrap_or_default() == "pubmed".to_string() {
-                            self.pmid = child.text().unwrap_or("").to_string();
-                        }
-                        if child.attribute("IdType").unwrap_or_default() == "pmc".to_string() {
-                            self.pmc = child.text().unwrap_or("").to_string();
-                        }
-                        if child.attribute("IdType").unwrap_or_default() == "pii".to_string() {
-                            self.pii = child.text().unwrap_or("").to_string();
-                        }
-                    }
-                }
+            if child.tag_name().name() != "ArticleId" {
+                continue;
+            }
+            let value = child.text().unwrap_or("").to_string();
+            match child.attribute("IdType").unwrap_or_default() {
+                "doi" => self.doi = value,
+                "pubmed" => self.pmid = value,
+                "pmc" => self.pmc = value,
+                "pii" => self.pii = value,
                 _ => {}
             }
         }
     }
 
     pub fn is_valid(&self) -> bool {
-        self.title != "".to_string() && self.doi != "".to_string()
+        !self.title.is_empty() && !self.doi.is_empty()
     }
 
     /// We will suppress the dead code warning for this function because it is useful for
@@ -91,22 +564,125 @@ impl Article {
         println!("{}", self.paper_abstract);
         println!("----------------");
     }
+}
 
-    pub fn is_article_relevant(&self) -> bool {
-        Article::is_string_relevant(&self.title)
-            && Article::is_string_relevant(&self.paper_abstract)
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn is_string_relevant(some_text: &String) -> bool {
-        if some_text.contains("cancer") {
-            return true;
-        }
-        if some_text.contains("oncology") {
-            return true;
+    fn record(pmid: &str, version: u32, title: &str) -> Article {
+        Article {
+            pmid: pmid.to_string(),
+            pmid_version: version,
+            title: title.to_string(),
+            ..Article::new()
         }
-        if some_text.contains("tumor") {
-            return true;
+    }
+
+    /// The pmid, version and title of every article.
+    fn keys(articles: &[Article]) -> Vec<(&str, u32, &str)> {
+        articles
+            .iter()
+            .map(|a| (a.pmid.as_str(), a.pmid_version, a.title.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn dedup_versions_keeps_one_record_per_key() {
+        let articles = || {
+            vec![
+                record("1", 1, "first"),
+                record("2", 1, "other"),
+                record("1", 2, "second"),
+                record("1", 1, "first again"),
+                record("", 1, "no pmid"),
+                record("", 1, "no pmid"),
+            ]
+        };
+        let all = dedup_versions(articles(), VersionPolicy::All);
+        assert_eq!(
+            keys(&all),
+            [
+                ("1", 1, "first again"),
+                ("2", 1, "other"),
+                ("1", 2, "second"),
+                ("", 1, "no pmid"),
+                ("", 1, "no pmid"),
+            ]
+        );
+        let latest = dedup_versions(articles(), VersionPolicy::Latest);
+        assert_eq!(
+            keys(&latest),
+            [
+                ("1", 2, "second"),
+                ("2", 1, "other"),
+                ("", 1, "no pmid"),
+                ("", 1, "no pmid"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_versions_does_not_replace_a_newer_version_with_an_older_one() {
+        let articles = vec![record("1", 3, "third"), record("1", 2, "second")];
+        let latest = dedup_versions(articles, VersionPolicy::Latest);
+        assert_eq!(keys(&latest), [("1", 3, "third")]);
+    }
+
+    #[test]
+    fn truncate_to_cuts_the_abstract_on_a_character_boundary() {
+        // The abstract is `abc` and a two byte `é`, the record has 7 bytes of text.
+        for (limit, expected) in [(7, "abcé"), (6, "abc"), (5, "abc"), (4, "ab"), (2, "")] {
+            let mut article = Article {
+                pmid: "1".to_string(),
+                title: "T".to_string(),
+                paper_abstract: "abcé".to_string(),
+                ..Article::new()
+            };
+            assert!(article.truncate_to(limit));
+            assert_eq!(article.paper_abstract, expected, "limit {}", limit);
+            assert!(article.text_size() <= limit);
         }
-        return false;
+    }
+
+    #[test]
+    fn truncate_to_drops_lists_from_the_end_before_the_title() {
+        let author = |name: &str| Author {
+            last_name: name.to_string(),
+            ..Author::default()
+        };
+        let mut article = Article {
+            pmid: "1".to_string(),
+            title: "éé title".to_string(),
+            paper_abstract: "abstract".to_string(),
+            authors: vec![author("first"), author("second")],
+            mesh_terms: vec!["mesh".to_string()],
+            languages: vec!["eng".to_string()],
+            ..Article::new()
+        };
+        // Without the abstract and the last author, the record has 1 + 10 + 5 + 4 + 3 bytes.
+        assert!(article.truncate_to(23));
+        assert_eq!(article.paper_abstract, "");
+        assert_eq!(article.authors, [author("first")]);
+        assert_eq!(article.mesh_terms, ["mesh"]);
+        assert_eq!(article.title, "éé title");
+        // Only the identifier and a title cut inside the second `é` are left.
+        assert!(article.truncate_to(4));
+        assert!(article.authors.is_empty() && article.languages.is_empty());
+        assert_eq!(article.title, "é");
+    }
+
+    #[test]
+    fn truncate_to_fails_when_the_identifiers_alone_are_too_long() {
+        let mut article = Article {
+            pmid: "12345".to_string(),
+            doi: "10.1/12345".to_string(),
+            title: "title".to_string(),
+            ..Article::new()
+        };
+        assert!(!article.truncate_to(10));
+        assert_eq!(article.pmid, "12345");
+        assert_eq!(article.doi, "10.1/12345");
+        assert_eq!(article.title, "");
     }
 }
@@ -0,0 +1,230 @@
+use crate::article::{Article, Author, Journal};
+use crate::run_info::fnv1a;
+
+/// The schema of the serialized Article. It has to be extended together with Article, in the
+/// same order as the fields are encoded in `encode_article`.
+pub const SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "Article",
+  "namespace": "org.hcse.parser",
+  "fields": [
+    {"name": "title", "type": "string"},
+    {"name": "pmid", "type": "string"},
+    {"name": "pmid_version", "type": "int"},
+    {"name": "doi", "type": "string"},
+    {"name": "pmc", "type": "string"},
+    {"name": "pii", "type": "string"},
+    {"name": "abstract", "type": "string"},
+    {"name": "authors", "type": {"type": "array", "items": {
+      "type": "record",
+      "name": "Author",
+      "fields": [
+        {"name": "last_name", "type": "string"},
+        {"name": "fore_name", "type": "string"},
+        {"name": "affiliations", "type": {"type": "array", "items": "string"}}
+      ]
+    }}},
+    {"name": "journal", "type": ["null", {
+      "type": "record",
+      "name": "Journal",
+      "fields": [
+        {"name": "title", "type": "string"},
+        {"name": "issn", "type": "string"},
+        {"name": "volume", "type": "string"},
+        {"name": "issue", "type": "string"},
+        {"name": "pages", "type": "string"}
+      ]
+    }], "default": null},
+    {"name": "publication_year", "type": ["null", "int"], "default": null},
+    {"name": "languages", "type": {"type": "array", "items": "string"}},
+    {"name": "mesh_terms", "type": {"type": "array", "items": "string"}}
+  ]
+}"#;
+
+const MAGIC: &[u8; 4] = b"Obj\x01";
+
+/// An Avro object container file with the schema in its header, the articles in one block and
+/// no compression. An empty list of articles gives a file without blocks.
+pub fn container(articles: &[Article], name: &str) -> Vec<u8> {
+    let sync = sync_marker(name);
+    let mut output = MAGIC.to_vec();
+    // The metadata is a map of bytes, written as one block of two entries.
+    write_long(&mut output, 2);
+    write_bytes(&mut output, b"avro.schema");
+    write_bytes(&mut output, SCHEMA.as_bytes());
+    write_bytes(&mut output, b"avro.codec");
+    write_bytes(&mut output, b"null");
+    write_long(&mut output, 0);
+    output.extend_from_slice(&sync);
+    if !articles.is_empty() {
+        let mut block = vec![];
+        for article in articles {
+            encode_article(&mut block, article);
+        }
+        write_long(&mut output, articles.len() as i64);
+        write_long(&mut output, block.len() as i64);
+        output.extend_from_slice(&block);
+        output.extend_from_slice(&sync);
+    }
+    output
+}
+
+/// Walks through the header and the blocks of a container file and checks that every block is
+/// complete and ends with the sync marker.
+pub fn validate(data: &[u8]) -> Result<(), String> {
+    let mut reader = Reader { data, position: 0 };
+    if reader.take(4)? != MAGIC {
+        return Err("not an avro container file".to_string());
+    }
+    loop {
+        let entries = reader.long()?;
+        if entries == 0 {
+            break;
+        }
+        if entries < 0 {
+            return Err("metadata blocks with a size are not supported".to_string());
+        }
+        for _ in 0..entries {
+            reader.bytes()?;
+            reader.bytes()?;
+        }
+    }
+    let sync = reader.take(16)?.to_vec();
+    let mut block = 0;
+    while reader.position < data.len() {
+        block += 1;
+        reader
+            .long()
+            .map_err(|e| format!("block {}: {}", block, e))?;
+        let size = reader
+            .long()
+            .map_err(|e| format!("block {}: {}", block, e))?;
+        reader
+            .take(size.max(0) as usize)
+            .map_err(|_| format!("block {} is truncated", block))?;
+        if reader.take(16).ok() != Some(sync.as_slice()) {
+            return Err(format!("block {} does not end with the sync marker", block));
+        }
+    }
+    Ok(())
+}
+
+/// The 16 byte marker between the blocks. It only has to be unlikely to appear in the data, so
+/// it is derived from the file name instead of being random.
+fn sync_marker(name: &str) -> [u8; 16] {
+    let mut sync = [0; 16];
+    sync[..8].copy_from_slice(&fnv1a(name.as_bytes()).to_le_bytes());
+    sync[8..].copy_from_slice(&fnv1a(format!("{}.avro", name).as_bytes()).to_le_bytes());
+    sync
+}
+
+fn encode_article(output: &mut Vec<u8>, article: &Article) {
+    write_string(output, &article.title);
+    write_string(output, &article.pmid);
+    write_long(output, article.pmid_version as i64);
+    write_string(output, &article.doi);
+    write_string(output, &article.pmc);
+    write_string(output, &article.pii);
+    write_string(output, &article.paper_abstract);
+    write_array(output, &article.authors, encode_author);
+    match &article.journal {
+        Some(journal) => {
+            write_long(output, 1);
+            encode_journal(output, journal);
+        }
+        None => write_long(output, 0),
+    }
+    match article.publication_year {
+        Some(year) => {
+            write_long(output, 1);
+            write_long(output, year as i64);
+        }
+        None => write_long(output, 0),
+    }
+    write_array(output, &article.languages, |o, l| write_string(o, l));
+    write_array(output, &article.mesh_terms, |o, m| write_string(o, m));
+}
+
+fn encode_author(output: &mut Vec<u8>, author: &Author) {
+    write_string(output, &author.last_name);
+    write_string(output, &author.fore_name);
+    write_array(output, &author.affiliations, |o, a| write_string(o, a));
+}
+
+fn encode_journal(output: &mut Vec<u8>, journal: &Journal) {
+    write_string(output, &journal.title);
+    write_string(output, &journal.issn);
+    write_string(output, &journal.volume);
+    write_string(output, &journal.issue);
+    write_string(output, &journal.pages);
+}
+
+/// Ints and longs are zigzag encoded variable length integers.
+fn write_long(output: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        output.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    output.push(n as u8);
+}
+
+fn write_bytes(output: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(output, bytes.len() as i64);
+    output.extend_from_slice(bytes);
+}
+
+fn write_string(output: &mut Vec<u8>, string: &str) {
+    write_bytes(output, string.as_bytes());
+}
+
+/// Arrays are written as one block with all items, followed by the empty block that ends them.
+fn write_array<T>(output: &mut Vec<u8>, items: &[T], encode: impl Fn(&mut Vec<u8>, &T)) {
+    if !items.is_empty() {
+        write_long(output, items.len() as i64);
+        for item in items {
+            encode(output, item);
+        }
+    }
+    write_long(output, 0);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .position
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len());
+        let Some(end) = end else {
+            return Err("unexpected end of file".to_string());
+        };
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn long(&mut self) -> Result<i64, String> {
+        let mut n: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((n >> 1) as i64 ^ -((n & 1) as i64));
+            }
+        }
+        Err("invalid variable length integer".to_string())
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let length = self.long()?;
+        if length < 0 {
+            return Err("negative length".to_string());
+        }
+        self.take(length as usize)
+    }
+}
@@ -0,0 +1,213 @@
+use crate::article::Article;
+
+/// Sentences that carry no content but end up in many abstracts: copyright lines of the
+/// publishers, the marker of abstracts that MEDLINE cut at 250 or 400 words, and trial
+/// registrations.
+const BUILTIN_PATTERNS: [&str; 8] = [
+    "copyright*",
+    "©*",
+    "*all rights reserved*",
+    "*abstract truncated at * words*",
+    "trial registration*",
+    "*clinicaltrials.gov identifier*",
+    "*registered * clinicaltrials.gov*",
+    "registration number*",
+];
+
+/// Removes boilerplate sentences from the abstracts before they are filtered and written.
+///
+/// Patterns are matched against whole sentences without regard to case, and `*` matches any
+/// text. A structured abstract section whose first sentence matches, e.g.
+/// `TRIAL REGISTRATION: NCT01234567.`, is removed with all of its sentences.
+pub struct BoilerplateFilter {
+    patterns: Vec<Vec<char>>,
+}
+
+impl BoilerplateFilter {
+    /// None if there is nothing to remove, so runs without the option do not touch the text.
+    pub fn new(builtin: bool, patterns: &[String]) -> Option<Self> {
+        let builtin_patterns = BUILTIN_PATTERNS.iter().filter(|_| builtin);
+        let patterns: Vec<Vec<char>> = builtin_patterns
+            .map(|p| p.to_string())
+            .chain(patterns.iter().map(|p| p.trim().to_string()))
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_lowercase().chars().collect())
+            .collect();
+        match patterns.is_empty() {
+            true => None,
+            false => Some(Self { patterns }),
+        }
+    }
+
+    /// Strips the main and all other abstracts of an article.
+    pub fn strip(&self, article: &mut Article) {
+        if let Some(text) = self.stripped(&article.paper_abstract) {
+            article.paper_abstract = text;
+        }
+        for other in &mut article.other_abstracts {
+            if let Some(text) = self.stripped(&other.text) {
+                other.text = text;
+            }
+        }
+    }
+
+    /// The text without boilerplate, or None if there was none.
+    fn stripped(&self, text: &str) -> Option<String> {
+        let mut changed = false;
+        let mut lines = vec![];
+        for line in text.lines() {
+            let sentences = sentences(line);
+            let kept: Vec<&str> = sentences
+                .iter()
+                .copied()
+                .filter(|sentence| !self.matches(sentence))
+                .collect();
+            if kept.len() == sentences.len() {
+                lines.push(line.to_string());
+                continue;
+            }
+            changed = true;
+            let section_is_boilerplate = has_label(line) && kept.first() != sentences.first();
+            if !kept.is_empty() && !section_is_boilerplate {
+                lines.push(kept.join(" "));
+            }
+        }
+        changed.then(|| lines.join("\n"))
+    }
+
+    fn matches(&self, sentence: &str) -> bool {
+        let sentence: Vec<char> = sentence.to_lowercase().chars().collect();
+        self.patterns
+            .iter()
+            .any(|pattern| glob_matches(pattern, &sentence))
+    }
+}
+
+/// Splits a line after every `.`, `!` or `?` that is followed by whitespace, so abbreviations
+/// like `ClinicalTrials.gov` stay in one piece.
+fn sentences(line: &str) -> Vec<&str> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let at_end = matches!(c, '.' | '!' | '?')
+            && chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+        if at_end {
+            let end = index + c.len_utf8();
+            sentences.push(line[start..end].trim());
+            start = end;
+        }
+    }
+    sentences.push(line[start..].trim());
+    sentences.retain(|s| !s.is_empty());
+    sentences
+}
+
+/// Whether the line is a section of a structured abstract, see article::abstract_section.
+fn has_label(line: &str) -> bool {
+    match line.split_once(": ") {
+        Some((label, _)) => {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_uppercase() || c.is_whitespace() || c == '/' || c == '&')
+        }
+        None => false,
+    }
+}
+
+/// Matches a whole text against a pattern where `*` stands for any text, including none.
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The position after the last `*` and the text position it was tried at, to backtrack to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((after_star, tried)) = star {
+            p = after_star;
+            t = tried + 1;
+            star = Some((after_star, tried + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_whole_texts() {
+        for (pattern, text, expected) in [
+            ("copyright*", "copyright 2024 elsevier.", true),
+            ("copyright*", "the copyright holder", false),
+            (
+                "*all rights reserved*",
+                "© 2024. all rights reserved.",
+                true,
+            ),
+            (
+                "*abstract truncated at * words*",
+                "abstract truncated at 400 words",
+                true,
+            ),
+            ("a*b*c", "abxbxc", true),
+            ("a*b*c", "abxbx", false),
+            ("*", "", true),
+            ("", "", true),
+            ("", "x", false),
+        ] {
+            let pattern: Vec<char> = pattern.chars().collect();
+            let text: Vec<char> = text.chars().collect();
+            assert_eq!(glob_matches(&pattern, &text), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn sentences_split_after_punctuation_followed_by_whitespace() {
+        assert_eq!(
+            sentences("Registered at ClinicalTrials.gov. Done!  Really? yes"),
+            [
+                "Registered at ClinicalTrials.gov.",
+                "Done!",
+                "Really?",
+                "yes"
+            ]
+        );
+        assert!(sentences("   ").is_empty());
+    }
+
+    #[test]
+    fn stripped_removes_boilerplate_sentences_and_sections() {
+        let filter = BoilerplateFilter::new(true, &["funded by *".to_string()]).unwrap();
+        for (text, expected) in [
+            ("Results were good.", None),
+            (
+                "Results were good. Copyright © 2024 Elsevier Ltd. All rights reserved.",
+                Some("Results were good."),
+            ),
+            (
+                "RESULTS: Good.\nTRIAL REGISTRATION: NCT01234567. Registered 2020.",
+                Some("RESULTS: Good."),
+            ),
+            ("Funded by the agency.", Some("")),
+            ("Tumors grew. funded BY nobody.", Some("Tumors grew.")),
+        ] {
+            assert_eq!(filter.stripped(text).as_deref(), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn no_patterns_means_no_filter() {
+        assert!(BoilerplateFilter::new(false, &[]).is_none());
+        assert!(BoilerplateFilter::new(false, &[" ".to_string()]).is_none());
+        assert!(BoilerplateFilter::new(false, &["x*".to_string()]).is_some());
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts the bytes downloaded by all parsers, for connections where transfer is metered. Once
+/// the limit is crossed, running downloads are aborted and no new files are started. The
+/// manifest only records finished files, so a later --resume run continues where this one
+/// stopped.
+pub struct DownloadBudget {
+    downloaded: AtomicU64,
+    limit: Option<u64>,
+}
+
+impl DownloadBudget {
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            downloaded: AtomicU64::new(0),
+            limit,
+        }
+    }
+
+    /// Counts a downloaded chunk. Returns false once the total exceeds the limit.
+    pub fn add(&self, bytes: u64) -> bool {
+        let total = self.downloaded.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        match self.limit {
+            Some(limit) => total <= limit,
+            None => true,
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.limit.is_some_and(|limit| self.downloaded() > limit)
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded.load(Ordering::SeqCst)
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+}
+
+/// Bytes as gigabytes with two decimals, the unit of --max-download-gb.
+pub fn format_gb(bytes: u64) -> String {
+    format!("{:.2} GB", bytes as f64 / 1e9)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_budget_is_exhausted_only_once_the_limit_is_crossed() {
+        let budget = DownloadBudget::new(Some(100));
+        assert!(budget.add(60));
+        // Reaching the limit exactly is still within the budget.
+        assert!(budget.add(40));
+        assert!(!budget.is_exhausted());
+        assert!(!budget.add(1));
+        assert!(budget.is_exhausted());
+        assert_eq!(budget.downloaded(), 101);
+    }
+
+    #[test]
+    fn a_budget_without_limit_is_never_exhausted() {
+        let budget = DownloadBudget::new(None);
+        assert!(budget.add(u64::MAX / 2));
+        assert!(!budget.is_exhausted());
+    }
+
+    #[test]
+    fn a_zero_budget_allows_no_bytes() {
+        let budget = DownloadBudget::new(Some(0));
+        assert!(!budget.is_exhausted());
+        assert!(!budget.add(1));
+        assert!(budget.is_exhausted());
+    }
+
+    #[test]
+    fn format_gb_rounds_to_two_decimals() {
+        for (bytes, expected) in [
+            (0, "0.00 GB"),
+            (1_234_567_890, "1.23 GB"),
+            (5e9 as u64, "5.00 GB"),
+        ] {
+            assert_eq!(format_gb(bytes), expected);
+        }
+    }
+}
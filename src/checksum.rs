@@ -0,0 +1,153 @@
+use clap::ValueEnum;
+use file_integrity::hash_file;
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// What to do with an archive that the --checksum-manifest does not list.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumPolicy {
+    /// Download the .md5 file next to the archive, as without a checksum manifest.
+    Fetch,
+    /// Process the archive without verifying it.
+    Skip,
+    /// Treat the archive as corrupt.
+    Fail,
+}
+
+/// The NLM md5 files have the form `MD5(pubmed24n0001.xml.gz)= <hash>`, plain `<hash>` files
+/// are accepted as well.
+pub fn md5_from_control_file(contents: &str) -> &str {
+    match contents.rsplit_once('=') {
+        Some((_, hash)) => hash.trim(),
+        None => contents.split_whitespace().next().unwrap_or(""),
+    }
+}
+
+/// Looks up an archive in an MD5SUMS listing. Both the `<hash>  <file name>` lines written by
+/// md5sum and the `MD5(<file name>)= <hash>` lines of the NLM md5 files are understood.
+pub fn md5_from_listing(contents: &str, archive_name: &str) -> Option<String> {
+    listing_entries(contents)
+        .find(|(name, _)| *name == archive_name)
+        .map(|(_, hash)| hash.to_string())
+}
+
+/// The file names and hashes of a listing, see md5_from_listing.
+fn listing_entries(contents: &str) -> impl Iterator<Item = (&str, &str)> {
+    contents.lines().filter_map(|line| {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("MD5(") {
+            return rest
+                .split_once(")=")
+                .map(|(name, hash)| (name, hash.trim()));
+        }
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            // md5sum marks binary mode with a leading asterisk.
+            (Some(hash), Some(name)) => Some((name.trim_start_matches('*'), hash)),
+            _ => None,
+        }
+    })
+}
+
+/// The checksums of a mirror that does not provide .md5 files, read from one listing in the
+/// MD5SUMS format.
+pub struct ChecksumManifest {
+    hashes: HashMap<String, String>,
+}
+
+impl ChecksumManifest {
+    /// Reads the listing from a http(s) url or a local file.
+    pub async fn load(
+        source: &str,
+        client: &reqwest::Client,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let contents = if source.starts_with("http://") || source.starts_with("https://") {
+            client
+                .get(source)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?
+        } else {
+            std::fs::read_to_string(source)?
+        };
+        let hashes: HashMap<String, String> = listing_entries(&contents)
+            .map(|(name, hash)| (name.to_string(), hash.to_string()))
+            .collect();
+        if hashes.is_empty() {
+            return Err(format!("the checksum manifest {} lists no files", source).into());
+        }
+        Ok(Self { hashes })
+    }
+
+    pub fn get(&self, archive_name: &str) -> Option<&str> {
+        self.hashes.get(archive_name).map(|h| h.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+}
+
+/// Finds the expected checksum of a local archive, either in an adjacent `<archive>.md5` file or
+/// in a `MD5SUMS` file in the same directory. Returns None if neither knows the archive.
+pub fn local_md5(directory: &str, archive_name: &str) -> std::io::Result<Option<String>> {
+    let adjacent = Path::new(directory).join(format!("{}.md5", archive_name));
+    if adjacent.exists() {
+        let contents = std::fs::read_to_string(adjacent)?;
+        return Ok(Some(md5_from_control_file(&contents).to_string()));
+    }
+    let listing = Path::new(directory).join("MD5SUMS");
+    if listing.exists() {
+        let contents = std::fs::read_to_string(listing)?;
+        return Ok(md5_from_listing(&contents, archive_name));
+    }
+    Ok(None)
+}
+
+/// Computes the md5 hash of a file on the blocking thread pool, so hashing large archives never
+/// stalls the tokio workers.
+pub async fn md5_of_file(path: String) -> Result<String, tokio::task::JoinError> {
+    tokio::task::spawn_blocking(move || hash_file(path).md5_hash.trim().to_string()).await
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verification {
+    Correct,
+    Wrong,
+    /// Neither an adjacent md5 file nor MD5SUMS knows the archive.
+    Missing,
+}
+
+/// Checks the archives of an input directory against their checksums, with up to `parallelism`
+/// archives hashed at the same time. Returns the file names with the result of their check.
+pub async fn verify_local_archives(
+    directory: &str,
+    file_names: &[String],
+    parallelism: usize,
+) -> std::io::Result<Vec<(String, Verification)>> {
+    futures_util::stream::iter(file_names.iter().cloned())
+        .map(|file_name| async move {
+            let archive_name = format!("{}.gz", file_name);
+            let Some(expected) = local_md5(directory, &archive_name)? else {
+                return Ok((file_name, Verification::Missing));
+            };
+            let path = Path::new(directory).join(&archive_name);
+            let actual = md5_of_file(path.to_string_lossy().to_string()).await?;
+            let verification = if expected.eq_ignore_ascii_case(&actual) {
+                Verification::Correct
+            } else {
+                Verification::Wrong
+            };
+            Ok((file_name, verification))
+        })
+        .buffer_unordered(parallelism.max(1))
+        .collect::<Vec<std::io::Result<(String, Verification)>>>()
+        .await
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,70 @@
+//! The time source of code that waits, e.g. between requests to the E-utilities. Tests pass a
+//! `ManualClock`, so waits take no real time and their length can be checked.
+use futures_util::future::{self, BoxFuture, FutureExt};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Waits for the duration. The future holds no state besides the timer, so it can be dropped
+    /// at any point, e.g. by a `tokio::select!` with a shutdown signal.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The wall clock, sleeping with tokio's timer. Needs a tokio runtime with the time driver.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        tokio::time::sleep(duration).boxed()
+    }
+}
+
+/// A clock that only moves when it is told to. Sleeping moves it forward by the duration and
+/// returns at once, so code that paces itself runs at full speed but sees the time it waited.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// The time the clock was moved forward in total, by `advance` and `sleep`.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.advance(duration);
+        future::ready(()).boxed()
+    }
+}
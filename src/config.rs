@@ -0,0 +1,171 @@
+use crate::article::{
+    AbstractSource, ArticleField, Extraction, ExtractionProfile, OversizedPolicy, VersionPolicy,
+};
+use crate::checksum::ChecksumPolicy;
+use crate::run_info::fnv1a_hex;
+use crate::topics::Topic;
+use crate::xml_backend::XmlBackendKind;
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// The directory on the NLM server that the archives are taken from.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// The yearly baseline, a full snapshot of PubMed.
+    Baseline,
+    /// The daily update files that are published on top of the baseline.
+    Updatefiles,
+}
+
+impl Source {
+    pub fn directory(&self) -> &'static str {
+        match self {
+            Source::Baseline => "baseline",
+            Source::Updatefiles => "updatefiles",
+        }
+    }
+}
+
+/// Settings that are shared by all parsers of one run.
+#[derive(Serialize, Debug)]
+pub struct Config {
+    pub year: u32,
+    /// The feeds the archives are downloaded from, see work_queue::merge_roots.
+    pub sources: Vec<Source>,
+    pub mirror_url: String,
+    /// How often a file is retried after a transient failure.
+    pub retries: u32,
+    /// The delay before the first retry, it doubles with every further attempt.
+    pub retry_delay_ms: u64,
+    /// An article is kept if its title and abstract each contain one of these keywords.
+    pub keywords: Vec<String>,
+    /// The optional metadata that is extracted in addition to ids, title and abstract.
+    pub fields: Vec<ArticleField>,
+    /// How much of every record is read, see ExtractionProfile.
+    pub profile: ExtractionProfile,
+    pub xml_backend: XmlBackendKind,
+    /// Read the archives from these directories instead of downloading them.
+    pub input_dirs: Vec<String>,
+    /// The directory the parsers keep their downloads and extracted files in. The system's
+    /// temporary directory if not set.
+    pub temp_dir: Option<String>,
+    /// The directory the offsets of the kept records in their archives are written to.
+    pub source_offsets: Option<String>,
+    /// A MD5SUMS listing of the mirror that replaces the per-file .md5 downloads.
+    pub checksum_manifest: Option<String>,
+    /// What happens to archives the checksum manifest does not list.
+    pub checksum_policy: ChecksumPolicy,
+    /// An ONNX classifier that replaces the keywords in deciding which articles are kept.
+    pub relevance_model: Option<String>,
+    pub relevance_threshold: f32,
+    pub model_input_size: usize,
+    /// The seed for all random decisions, see rng.rs.
+    pub seed: u64,
+    /// The fraction of the relevant articles that is kept.
+    pub sample_rate: f64,
+    pub shuffle: bool,
+    /// File indices that are processed before all others, in this order.
+    pub priority_indices: Vec<u32>,
+    /// Which versions of versioned citations are kept.
+    pub pmid_versions: VersionPolicy,
+    /// Topics the statistics are broken down by.
+    pub topics: Vec<Topic>,
+    /// The abstract the keywords and the relevance model are applied to.
+    pub filter_abstract: AbstractSource,
+    /// The abstract that is written as `abstract`.
+    pub export_abstract: AbstractSource,
+    /// Restricts OtherAbstracts to this language code, e.g. `ger`.
+    pub other_abstract_language: Option<String>,
+    /// Remove the built-in boilerplate sentences from the abstracts, see boilerplate.rs.
+    pub strip_boilerplate: bool,
+    /// More boilerplate sentences to remove, as patterns with `*` wildcards.
+    pub boilerplate_patterns: Vec<String>,
+    /// The most bytes of text a single record may have.
+    pub max_record_bytes: Option<usize>,
+    pub oversized_records: OversizedPolicy,
+    /// The number of files after which a parser starts over with fresh state, 0 for never.
+    pub recycle_after_files: usize,
+    /// The resident memory above which a warning is printed.
+    pub rss_warning_mb: Option<u64>,
+}
+
+impl Config {
+    /// A stable hash of all settings, to tell apart runs with different configurations.
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        fnv1a_hex(json.as_bytes())
+    }
+
+    /// A short hash of the settings that decide which articles are kept. It is part of the
+    /// output file names and the manifest, so corpora of different filters are never mixed.
+    pub fn filter_hash(&self) -> String {
+        let mut filter = serde_json::json!({
+            "keywords": self.keywords,
+            "relevance_model": self.relevance_model,
+            "relevance_threshold": self.relevance_threshold,
+            "model_input_size": self.model_input_size,
+            "seed": self.seed,
+            "sample_rate": self.sample_rate,
+            "pmid_versions": self.pmid_versions,
+        });
+        // Only part of the hash when used, so the outputs of earlier runs keep their names.
+        if self.strip_boilerplate || !self.boilerplate_patterns.is_empty() {
+            filter["strip_boilerplate"] = self.strip_boilerplate.into();
+            filter["boilerplate_patterns"] = self.boilerplate_patterns.clone().into();
+        }
+        if self.max_record_bytes.is_some() && self.oversized_records == OversizedPolicy::Skip {
+            filter["max_record_bytes"] = self.max_record_bytes.into();
+        }
+        if self.filter_abstract != AbstractSource::Main || self.other_abstract_language.is_some() {
+            filter["filter_abstract"] = serde_json::json!(self.filter_abstract);
+            filter["other_abstract_language"] = serde_json::json!(self.other_abstract_language);
+        }
+        fnv1a_hex(filter.to_string().as_bytes())[..8].to_string()
+    }
+
+    /// What the backends extract from every record, from the profile and the fields.
+    pub fn extraction(&self) -> Extraction {
+        Extraction {
+            max_record_bytes: self.max_record_bytes,
+            oversized_records: self.oversized_records,
+            ..self.profile.extraction(&self.fields)
+        }
+    }
+
+    /// The name of the extracted xml file for the given archive index, e.g. `pubmed24n1219.xml`.
+    pub fn file_name(&self, index: u32) -> String {
+        format!("pubmed{:0>2}n{:0>4}.xml", self.year % 100, index)
+    }
+
+    pub fn download_url(&self, source: Source, file_name: &str) -> String {
+        format!(
+            "{}/{}/{}.gz",
+            self.mirror_url.trim_end_matches('/'),
+            source.directory(),
+            file_name
+        )
+    }
+}
+
+/// The index in an NLM archive name, e.g. 1 for `pubmed24n0001.xml`.
+pub fn file_index(file_name: &str) -> Option<u32> {
+    let (_, index) = file_name.strip_suffix(".xml")?.rsplit_once('n')?;
+    index.parse().ok()
+}
+
+/// The names of the extracted xml files for all `*.xml.gz` archives in a directory, newest first.
+pub fn local_archives(directory: &str) -> std::io::Result<Vec<String>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(directory)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(file_name) = name.strip_suffix(".gz") {
+            if file_name.ends_with(".xml") {
+                files.push(file_name.to_string());
+            }
+        }
+    }
+    files.sort();
+    files.reverse();
+    Ok(files)
+}
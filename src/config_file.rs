@@ -0,0 +1,112 @@
+use crate::Args;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+pub type ConfigFileError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Parses the command line. Every flag that is not given on the command line is taken from the
+/// --config file if there is one, so a flag always overrides the file.
+pub fn parse_args() -> Result<Args, ConfigFileError> {
+    let matches = Args::command().get_matches();
+    let Some(path) = matches.get_one::<String>("config") else {
+        return Ok(Args::from_arg_matches(&matches)?);
+    };
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("could not read the config file {}: {}", path, e))?;
+    let command = Args::command();
+    let mut argv: Vec<String> = std::env::args().take(1).collect();
+    let settings = parse(&contents).map_err(|e| format!("{}: {}", path, e))?;
+    for (key, values) in settings {
+        let Some(arg) = command.get_arguments().find(|a| a.get_id() == key.as_str()) else {
+            return Err(format!("{}: unknown setting {}", path, key).into());
+        };
+        if key == "config" {
+            return Err(format!("{}: config files cannot be nested", path).into());
+        }
+        if values.is_empty() || matches.value_source(&key) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = format!("--{}", arg.get_long().unwrap_or(&key));
+        if arg.get_action().takes_values() && arg.get_value_delimiter().is_none() {
+            // Flags like --topic are repeated once per value.
+            for value in values {
+                argv.push(flag.clone());
+                argv.push(value);
+            }
+        } else if arg.get_action().takes_values() {
+            argv.push(flag);
+            argv.push(values.join(","));
+        } else if values.iter().any(|v| v == "true") {
+            argv.push(flag);
+        }
+    }
+    argv.extend(std::env::args().skip(1));
+    Ok(Args::try_parse_from(argv)?)
+}
+
+/// A config file, a TOML table whose keys are the flags of Args written with underscores.
+#[derive(Deserialize)]
+#[serde(transparent)]
+struct ConfigFile {
+    settings: BTreeMap<String, Setting>,
+}
+
+/// The value of a setting. Values are handed to clap as text, so they are checked like the
+/// flags on the command line.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Setting {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Setting>),
+}
+
+impl Setting {
+    fn values(self, key: &str) -> Result<Vec<String>, String> {
+        match self {
+            Setting::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Setting::Array(_) => Err(format!("{} cannot contain nested arrays", key)),
+                    item => Ok(item.text()),
+                })
+                .collect(),
+            setting => Ok(vec![setting.text()]),
+        }
+    }
+
+    fn text(self) -> String {
+        match self {
+            Setting::Boolean(value) => value.to_string(),
+            Setting::Integer(value) => value.to_string(),
+            Setting::Float(value) => value.to_string(),
+            Setting::String(value) => value,
+            Setting::Array(_) => unreachable!("arrays are split into their items"),
+        }
+    }
+}
+
+/// Reads the settings of a config file with their values.
+fn parse(contents: &str) -> Result<Vec<(String, Vec<String>)>, ConfigFileError> {
+    let file: ConfigFile = toml::from_str(contents)?;
+    let mut settings = vec![];
+    for (key, setting) in file.settings {
+        let values = setting.values(&key)?;
+        settings.push((key, values));
+    }
+    Ok(settings)
+}
+
+/// A TOML basic string, for the config files `hcse init` writes.
+pub fn quote(string: &str) -> String {
+    let escaped = string
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
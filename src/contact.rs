@@ -0,0 +1,43 @@
+/// How the parser identifies itself to NCBI, shared by all commands that send requests.
+#[derive(clap::Args, Debug)]
+pub struct ContactArgs {
+    /// An email address the NCBI operators can reach you at if your downloads cause problems. It
+    /// is sent in the User-Agent header of every request.
+    #[arg(long, value_parser = parse_email)]
+    pub contact_email: Option<String>,
+
+    /// Refuse to start without --contact-email, as the NCBI guidelines ask of bulk downloaders.
+    #[arg(long, requires = "contact_email")]
+    pub polite: bool,
+}
+
+impl ContactArgs {
+    /// The User-Agent header, e.g. `hcse_parser/1.1.1 (+https://github.com/...; mailto:a@b.org)`.
+    pub fn user_agent(&self) -> String {
+        let mut about = vec![format!("+{}", REPOSITORY)];
+        if let Some(email) = &self.contact_email {
+            about.push(format!("mailto:{}", email));
+        }
+        format!(
+            "{}/{} ({})",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            about.join("; ")
+        )
+    }
+
+    /// A client builder that sends the User-Agent with every request.
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        reqwest::Client::builder().user_agent(self.user_agent())
+    }
+}
+
+const REPOSITORY: &str = "https://github.com/Silver-HCSE/hcse_1_parser";
+
+fn parse_email(email: &str) -> Result<String, String> {
+    let email = email.trim();
+    match email.split_once('@') {
+        Some((user, domain)) if !user.is_empty() && domain.contains('.') => Ok(email.to_string()),
+        _ => Err(format!("{} is not an email address", email)),
+    }
+}
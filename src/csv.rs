@@ -0,0 +1,13 @@
+/// Quotes a field if it contains a separator, a quote or a line break.
+pub fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn row<S: AsRef<str>>(fields: &[S]) -> String {
+    let escaped: Vec<String> = fields.iter().map(|f| escape(f.as_ref())).collect();
+    format!("{}\n", escaped.join(","))
+}
@@ -0,0 +1,88 @@
+//! Fetches records by PMID from the E-utilities. Requests go through a `Fetcher` and are paced by
+//! a `Clock`, so tests answer from memory and check the pauses without waiting for them.
+use crate::article::{Article, Extraction};
+use crate::clock::Clock;
+use crate::fetcher::{FetchError, Fetcher};
+use crate::xml_backend::{RoxmltreeBackend, XmlBackend};
+use reqwest::Url;
+use std::time::Duration;
+
+/// NCBI asks for at most 200 ids per GET request.
+pub const MAX_BATCH_SIZE: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct Efetch {
+    /// The base url of the E-utilities, e.g. https://eutils.ncbi.nlm.nih.gov/entrez/eutils.
+    pub eutils_url: String,
+    /// An NCBI API key, which raises the allowed rate from 3 to 10 requests per second.
+    pub api_key: Option<String>,
+    /// The contact NCBI asks bulk users for.
+    pub email: Option<String>,
+    /// The number of PMIDs per request, cut to 1..=MAX_BATCH_SIZE.
+    pub batch_size: usize,
+}
+
+impl Efetch {
+    /// The efetch request for a batch of PMIDs, with the parameters encoded, since an API key or
+    /// an email address may contain characters like `+` or `&`.
+    pub fn efetch_url(&self, batch: &[String]) -> Result<Url, FetchError> {
+        let mut url = Url::parse(&format!(
+            "{}/efetch.fcgi",
+            self.eutils_url.trim_end_matches('/')
+        ))?;
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("db", "pubmed")
+                .append_pair("retmode", "xml")
+                .append_pair("id", &batch.join(","))
+                // The E-utilities want the tool and a contact in the parameters as well.
+                .append_pair("tool", env!("CARGO_PKG_NAME"));
+            if let Some(email) = &self.email {
+                query.append_pair("email", email);
+            }
+            if let Some(api_key) = &self.api_key {
+                query.append_pair("api_key", api_key);
+            }
+        }
+        Ok(url)
+    }
+
+    /// The pause between requests that keeps within the rate NCBI allows.
+    pub fn request_interval(&self) -> Duration {
+        match self.api_key {
+            Some(_) => Duration::from_millis(100),
+            None => Duration::from_millis(340),
+        }
+    }
+
+    /// Fetches the PMIDs in batches and hands the valid articles of every batch to `write`,
+    /// together with the number of the batch, from 0.
+    pub async fn fetch(
+        &self,
+        pmids: &[String],
+        extraction: &Extraction,
+        fetcher: &dyn Fetcher,
+        clock: &dyn Clock,
+        write: &mut dyn FnMut(usize, Vec<Article>) -> Result<(), FetchError>,
+    ) -> Result<(), FetchError> {
+        let batch_size = self.batch_size.clamp(1, MAX_BATCH_SIZE);
+        let mut last_request = None;
+        for (index, batch) in pmids.chunks(batch_size).enumerate() {
+            // Parsing and writing the last batch count towards the pause.
+            if let Some(last_request) = last_request {
+                let since = clock.now() - last_request;
+                clock
+                    .sleep(self.request_interval().saturating_sub(since))
+                    .await;
+            }
+            last_request = Some(clock.now());
+            let url = self.efetch_url(batch)?;
+            let xml = String::from_utf8(fetcher.get(url.as_str()).await?)?;
+            let articles =
+                RoxmltreeBackend {}.parse(&xml, extraction, &mut |_, _| {}, &mut |_| {})?;
+            write(index, articles)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use crate::manifest::FileStatus;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// The significant events of the pipeline. Each is written as one json object per line.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    FileStarted {
+        file: &'a str,
+        worker: u32,
+    },
+    StageFinished {
+        file: &'a str,
+        worker: u32,
+        stage: &'a str,
+        duration_ms: u128,
+    },
+    Error {
+        file: &'a str,
+        worker: u32,
+        state: String,
+        reason: &'a str,
+    },
+    Retry {
+        file: &'a str,
+        worker: u32,
+        attempt: u32,
+        delay_ms: u64,
+    },
+    Warning {
+        file: &'a str,
+        worker: u32,
+        reason: &'a str,
+    },
+    Skip {
+        file: &'a str,
+        worker: u32,
+        reason: &'a str,
+    },
+    ConcurrencyReduced {
+        file: &'a str,
+        worker: u32,
+        concurrency: usize,
+    },
+    ConcurrencyRaised {
+        file: &'a str,
+        worker: u32,
+        concurrency: usize,
+    },
+    FileFinished {
+        file: &'a str,
+        worker: u32,
+        status: FileStatus,
+        articles: usize,
+    },
+    /// The parser replaced its state and temporary directory after this many files.
+    Recycled {
+        worker: u32,
+        files: usize,
+    },
+}
+
+#[derive(Serialize)]
+struct TimestampedEvent<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// An append-only audit log of the run that other tools can tail. Without a path, events are
+/// dropped.
+pub struct EventLog {
+    file: Option<Mutex<File>>,
+}
+
+impl EventLog {
+    pub fn open(path: Option<&str>) -> std::io::Result<Self> {
+        let file = match path {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+        Ok(Self { file })
+    }
+
+    pub fn emit(&self, event: Event) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let event = TimestampedEvent {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            event,
+        };
+        if let Ok(mut line) = serde_json::to_string(&event) {
+            line.push('\n');
+            let mut file = file.lock().unwrap();
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::contact::ContactArgs;
+use crate::filter::KeywordFilter;
+use crate::output::OutputArgs;
+use hcse_parser::article::{Article, ArticleField, ExtractionProfile};
+use hcse_parser::clock::{Clock, SystemClock};
+use hcse_parser::efetch::{Efetch, MAX_BATCH_SIZE};
+use hcse_parser::fetcher::{Fetcher, HttpFetcher};
+use std::collections::BTreeSet;
+
+pub type FetchError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(clap::Args, Debug)]
+pub struct FetchPmidsArgs {
+    /// The PMIDs to fetch, e.g. --ids 123,456.
+    #[arg(long, value_delimiter = ',')]
+    ids: Vec<String>,
+
+    /// A file with one PMID per line. Empty lines and lines starting with # are ignored.
+    #[arg(long)]
+    ids_file: Option<String>,
+
+    /// Additional metadata to extract, e.g. --fields authors,journal.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    fields: Vec<ArticleField>,
+
+    /// The base url of the E-utilities.
+    #[arg(long, default_value = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils")]
+    eutils_url: String,
+
+    /// An NCBI API key, which raises the allowed rate from 3 to 10 requests per second.
+    #[arg(long)]
+    api_key: Option<String>,
+
+    /// The number of PMIDs per request.
+    #[arg(long, default_value_t = MAX_BATCH_SIZE)]
+    batch_size: usize,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    contact: ContactArgs,
+}
+
+impl FetchPmidsArgs {
+    /// The PMIDs from --ids and --ids-file without duplicates, in the order they were given.
+    fn pmids(&self) -> Result<Vec<String>, FetchError> {
+        let mut pmids = self.ids.clone();
+        if let Some(path) = &self.ids_file {
+            let contents = std::fs::read_to_string(path)?;
+            pmids.extend(
+                contents
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#')),
+            );
+        }
+        let mut seen = BTreeSet::new();
+        pmids.retain(|pmid| seen.insert(pmid.clone()));
+        if let Some(invalid) = pmids
+            .iter()
+            .find(|p| !p.chars().all(|c| c.is_ascii_digit()))
+        {
+            return Err(format!("{} is not a PMID", invalid).into());
+        }
+        if pmids.is_empty() {
+            return Err("no PMIDs given, use --ids or --ids-file".into());
+        }
+        Ok(pmids)
+    }
+
+    /// The requests to the E-utilities for the arguments.
+    fn efetch(&self) -> Efetch {
+        Efetch {
+            eutils_url: self.eutils_url.clone(),
+            api_key: self.api_key.clone(),
+            email: self.contact.contact_email.clone(),
+            batch_size: self.batch_size,
+        }
+    }
+}
+
+pub fn run(args: &FetchPmidsArgs) -> Result<(), FetchError> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let fetcher = HttpFetcher::new(args.contact.client_builder().build()?);
+            fetch(args, &fetcher, &SystemClock).await
+        })
+}
+
+async fn fetch(
+    args: &FetchPmidsArgs,
+    fetcher: &dyn Fetcher,
+    clock: &dyn Clock,
+) -> Result<(), FetchError> {
+    let pmids = args.pmids()?;
+    let sink = args.output.create_sink(None, KeywordFilter::new(vec![]))?;
+    let mut fields = args.fields.clone();
+    fields.extend(args.output.required_fields());
+    let extraction = ExtractionProfile::Standard.extraction(&fields);
+    let mut found = BTreeSet::new();
+    let mut write = |index: usize, articles: Vec<Article>| {
+        found.extend(articles.iter().map(|a| a.pmid.clone()));
+        sink.write(&format!("efetch_{:04}", index + 1), &articles)
+    };
+    args.efetch()
+        .fetch(&pmids, &extraction, fetcher, clock, &mut write)
+        .await?;
+    sink.finish()?;
+    println!(
+        "Fetched {} of {} articles into {}",
+        found.len(),
+        pmids.len(),
+        sink.describe()
+    );
+    let missing: Vec<&str> = pmids
+        .iter()
+        .filter(|p| !found.contains(*p))
+        .map(|p| p.as_str())
+        .collect();
+    if !missing.is_empty() {
+        eprintln!("Not found or without title and DOI: {}", missing.join(","));
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Retrieves documents over HTTP. Tests pass a closure that answers from memory instead:
+//!
+//! ```
+//! use hcse_parser::fetcher::{FetchError, Fetcher};
+//!
+//! let fetcher = |url: &str| -> Result<Vec<u8>, FetchError> {
+//!     match url.contains("id=100") {
+//!         true => Ok(b"<PubmedArticleSet/>".to_vec()),
+//!         false => Err(format!("unexpected request {}", url).into()),
+//!     }
+//! };
+//! # let _: &dyn Fetcher = &fetcher;
+//! ```
+use futures_util::future::{self, BoxFuture, FutureExt};
+
+pub type FetchError = Box<dyn std::error::Error + Send + Sync>;
+
+pub trait Fetcher: Send + Sync {
+    /// The body of a successful GET request. Statuses other than 2xx are errors. Dropping the
+    /// future cancels the request without side effects.
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>, FetchError>>;
+}
+
+/// Fetches with a reqwest client, e.g. one with the user agent of `--contact-email`.
+#[derive(Debug, Clone, Default)]
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+impl HttpFetcher {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>, FetchError>> {
+        async move {
+            let response = self.client.get(url).send().await?.error_for_status()?;
+            Ok(response.bytes().await?.to_vec())
+        }
+        .boxed()
+    }
+}
+
+/// Answers every request at once with the result of the function.
+impl<F: Fn(&str) -> Result<Vec<u8>, FetchError> + Send + Sync> Fetcher for F {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>, FetchError>> {
+        future::ready(self(url)).boxed()
+    }
+}
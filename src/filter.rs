@@ -0,0 +1,62 @@
+use crate::article::{AbstractSource, Article};
+
+/// The filter engine decides which articles are kept. An article is relevant if both its title
+/// and its abstract contain at least one of the keywords.
+pub struct KeywordFilter {
+    keywords: Vec<String>,
+    abstract_source: AbstractSource,
+    abstract_language: Option<String>,
+}
+
+impl KeywordFilter {
+    pub fn new(keywords: Vec<String>) -> Self {
+        Self {
+            keywords,
+            abstract_source: AbstractSource::Main,
+            abstract_language: None,
+        }
+    }
+
+    /// Matches the keywords against another abstract than the main one, see
+    /// Article::abstract_text.
+    pub fn with_abstract(mut self, source: AbstractSource, language: Option<String>) -> Self {
+        self.abstract_source = source;
+        self.abstract_language = language;
+        self
+    }
+
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    fn abstract_of<'a>(&self, article: &'a Article) -> std::borrow::Cow<'a, str> {
+        article.abstract_text(self.abstract_source, self.abstract_language.as_deref())
+    }
+
+    pub fn is_relevant(&self, article: &Article) -> bool {
+        self.is_string_relevant(&article.title)
+            && self.is_string_relevant(&self.abstract_of(article))
+    }
+
+    fn is_string_relevant(&self, some_text: &str) -> bool {
+        self.keywords
+            .iter()
+            .any(|keyword| some_text.contains(keyword.as_str()))
+    }
+
+    /// Counts for every keyword the number of articles that mention it in the title or abstract.
+    pub fn count_hits(&self, articles: &[Article]) -> Vec<usize> {
+        self.keywords
+            .iter()
+            .map(|keyword| {
+                articles
+                    .iter()
+                    .filter(|a| {
+                        a.title.contains(keyword.as_str())
+                            || self.abstract_of(a).contains(keyword.as_str())
+                    })
+                    .count()
+            })
+            .collect()
+    }
+}
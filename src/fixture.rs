@@ -0,0 +1,311 @@
+use crate::checksum::md5_of_file;
+use crate::config::Source;
+use crate::rng::SplitMix64;
+use async_compression::tokio::write::GzipEncoder;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+pub type FixtureError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(clap::Args, Debug)]
+pub struct GenFixtureArgs {
+    /// The number of articles per archive.
+    #[arg(long, default_value_t = 1000)]
+    articles: usize,
+
+    /// The number of archives, numbered from 1 like the baseline.
+    #[arg(long, default_value_t = 1)]
+    files: u32,
+
+    /// The directory the mirror layout is written to, e.g. fixture/baseline/pubmed24n0001.xml.gz.
+    #[arg(long, default_value = "fixture")]
+    output_dir: String,
+
+    #[arg(long, default_value_t = 24)]
+    year: u32,
+
+    #[arg(long, value_enum, default_value_t = Source::Baseline)]
+    source: Source,
+
+    /// The same seed gives byte for byte the same archives.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Relevant articles mention one of these keywords in their title and abstract.
+    #[arg(long, value_delimiter = ',', default_value = "cancer,tumor")]
+    keywords: Vec<String>,
+
+    /// The fraction of the articles that is relevant.
+    #[arg(long, default_value_t = 0.1)]
+    relevant_rate: f64,
+
+    /// The fraction of the articles that is built around one of the edge cases, e.g. a missing
+    /// DOI or a structured abstract with inline markup.
+    #[arg(long, default_value_t = 0.05)]
+    edge_case_rate: f64,
+}
+
+/// Words for titles and abstracts that are not relevant. None of them contains a default keyword.
+const WORDS: [&str; 24] = [
+    "heart",
+    "study",
+    "cohort",
+    "patients",
+    "analysis",
+    "effect",
+    "treatment",
+    "risk",
+    "clinical",
+    "trial",
+    "outcome",
+    "protein",
+    "expression",
+    "cells",
+    "mice",
+    "review",
+    "model",
+    "response",
+    "therapy",
+    "children",
+    "disease",
+    "genetic",
+    "factors",
+    "association",
+];
+
+/// The unusual records the parser has to handle. Every article with an edge case gets exactly one.
+#[derive(Clone, Copy, Debug)]
+enum EdgeCase {
+    /// Left out by the parser.
+    MissingDoi,
+    MultipleTitles,
+    StructuredAbstract,
+    /// The record is followed by a second version with the same PMID.
+    VersionedPmid,
+    OtherAbstract,
+    MedlineDate,
+    CollectiveName,
+    EntitiesAndUnicode,
+    UnknownElement,
+    NoAbstract,
+}
+
+const EDGE_CASES: [EdgeCase; 10] = [
+    EdgeCase::MissingDoi,
+    EdgeCase::MultipleTitles,
+    EdgeCase::StructuredAbstract,
+    EdgeCase::VersionedPmid,
+    EdgeCase::OtherAbstract,
+    EdgeCase::MedlineDate,
+    EdgeCase::CollectiveName,
+    EdgeCase::EntitiesAndUnicode,
+    EdgeCase::UnknownElement,
+    EdgeCase::NoAbstract,
+];
+
+/// What a run over the fixture should find, printed so configuration changes can be checked.
+#[derive(Default)]
+struct Expected {
+    records: usize,
+    valid: usize,
+    relevant: usize,
+    edge_cases: usize,
+}
+
+pub fn run(args: &GenFixtureArgs) -> Result<(), FixtureError> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(generate(args))
+}
+
+async fn generate(args: &GenFixtureArgs) -> Result<(), FixtureError> {
+    if args.keywords.is_empty() {
+        return Err("gen-fixture needs at least one keyword for the relevant articles".into());
+    }
+    let directory = Path::new(&args.output_dir).join(args.source.directory());
+    std::fs::create_dir_all(&directory)?;
+    let mut rng = SplitMix64::new(args.seed);
+    let mut expected = Expected::default();
+    let mut listing = String::new();
+    for index in 1..=args.files {
+        let first_pmid = (index as usize - 1) * args.articles + 1;
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE PubmedArticleSet>\n\
+             <PubmedArticleSet>\n",
+        );
+        for pmid in first_pmid..first_pmid + args.articles {
+            article(&mut xml, &mut rng, args, pmid, &mut expected);
+        }
+        xml.push_str("</PubmedArticleSet>\n");
+        // Named like Config::file_name, so a run with the same --year finds the archives.
+        let archive = format!("pubmed{:0>2}n{:0>4}.xml.gz", args.year % 100, index);
+        let path = directory.join(&archive);
+        let mut encoder = GzipEncoder::new(tokio::fs::File::create(&path).await?);
+        encoder.write_all(xml.as_bytes()).await?;
+        encoder.shutdown().await?;
+        let hash = md5_of_file(path.to_string_lossy().to_string()).await?;
+        let control = format!("MD5({})= {}\n", archive, hash);
+        std::fs::write(directory.join(format!("{}.md5", archive)), &control)?;
+        listing.push_str(&control);
+    }
+    std::fs::write(directory.join("MD5SUMS"), listing)?;
+    println!(
+        "Wrote {} archives to {}: {} records, {} valid articles, {} relevant for {}, {} edge cases.",
+        args.files,
+        directory.display(),
+        expected.records,
+        expected.valid,
+        expected.relevant,
+        args.keywords.join(","),
+        expected.edge_cases
+    );
+    println!(
+        "Serve {} as --mirror-url, or use --input-dir {} with --start 1 --end {}.",
+        args.output_dir,
+        directory.display(),
+        args.files
+    );
+    Ok(())
+}
+
+fn words<'a>(rng: &mut SplitMix64, n: usize) -> Vec<&'a str> {
+    (0..n)
+        .map(|_| WORDS[(rng.next_u64() % WORDS.len() as u64) as usize])
+        .collect()
+}
+
+fn pick<'a>(rng: &mut SplitMix64, items: &'a [String]) -> &'a str {
+    &items[(rng.next_u64() % items.len() as u64) as usize]
+}
+
+/// Appends one or, for a versioned PMID, two PubmedArticle records.
+fn article(
+    xml: &mut String,
+    rng: &mut SplitMix64,
+    args: &GenFixtureArgs,
+    pmid: usize,
+    expected: &mut Expected,
+) {
+    let relevant = rng.next_f64() < args.relevant_rate;
+    let edge_case = match rng.next_f64() < args.edge_case_rate {
+        true => Some(EDGE_CASES[(rng.next_u64() % EDGE_CASES.len() as u64) as usize]),
+        false => None,
+    };
+    let keyword = pick(rng, &args.keywords);
+    let mut title = words(rng, 6);
+    let mut sentence = words(rng, 12);
+    if relevant {
+        title[2] = keyword;
+        sentence[5] = keyword;
+    }
+    let title = capitalize(&title.join(" "));
+    let text = format!("{}.", capitalize(&sentence.join(" ")));
+    let abstract_element = match edge_case {
+        Some(EdgeCase::StructuredAbstract) => format!(
+            "<Abstract><AbstractText Label=\"BACKGROUND\">{}</AbstractText>\
+             <AbstractText Label=\"RESULTS\">Values rose by 10<sup>2</sup> in <i>vivo</i>.\
+             </AbstractText></Abstract>",
+            text
+        ),
+        Some(EdgeCase::NoAbstract) => String::new(),
+        _ => format!("<Abstract><AbstractText>{}</AbstractText></Abstract>", text),
+    };
+    let title_element = match edge_case {
+        Some(EdgeCase::MultipleTitles) => format!(
+            "<ArticleTitle>{}</ArticleTitle><ArticleTitle>{}</ArticleTitle>",
+            capitalize(&words(rng, 4).join(" ")),
+            title
+        ),
+        Some(EdgeCase::EntitiesAndUnicode) => {
+            format!("<ArticleTitle>{} &amp; Straße &lt;5%</ArticleTitle>", title)
+        }
+        _ => format!("<ArticleTitle>{}</ArticleTitle>", title),
+    };
+    let date = match edge_case {
+        Some(EdgeCase::MedlineDate) => "<MedlineDate>1998 Dec-1999 Jan</MedlineDate>".to_string(),
+        _ => format!("<Year>{}</Year>", 1990 + rng.next_u64() % 35),
+    };
+    let mut authors = String::new();
+    if let Some(EdgeCase::CollectiveName) = edge_case {
+        authors.push_str("<Author><CollectiveName>Study Group</CollectiveName></Author>");
+    }
+    for n in 0..1 + rng.next_u64() % 8 {
+        authors.push_str(&format!(
+            "<Author><LastName>Author{}</LastName><ForeName>F</ForeName><AffiliationInfo>\
+             <Affiliation>University {}</Affiliation></AffiliationInfo></Author>",
+            n,
+            rng.next_u64() % 100
+        ));
+    }
+    let other_abstract = match edge_case {
+        Some(EdgeCase::OtherAbstract) => {
+            "<OtherAbstract Type=\"Publisher\" Language=\"ger\"><AbstractText>Ein Text.\
+             </AbstractText></OtherAbstract>"
+        }
+        _ => "",
+    };
+    let unknown = match edge_case {
+        Some(EdgeCase::UnknownElement) => "<Extension>unexpected</Extension>",
+        _ => "",
+    };
+    let doi = match edge_case {
+        Some(EdgeCase::MissingDoi) => String::new(),
+        _ => format!(
+            "<ArticleId IdType=\"doi\">10.5555/fixture.{}</ArticleId>",
+            pmid
+        ),
+    };
+    let mut references = String::new();
+    for n in 0..rng.next_u64() % 30 {
+        references.push_str(&format!(
+            "<Reference><Citation>Reference {} of {}.</Citation></Reference>",
+            n, pmid
+        ));
+    }
+    let versions = match edge_case {
+        Some(EdgeCase::VersionedPmid) => 2,
+        _ => 1,
+    };
+    for version in 1..=versions {
+        xml.push_str(&format!(
+            "<PubmedArticle><MedlineCitation Status=\"MEDLINE\" Owner=\"NLM\">\
+             <PMID Version=\"{version}\">{pmid}</PMID><Article PubModel=\"Print\"><Journal>\
+             <ISSN IssnType=\"Print\">0000-{issn:04}</ISSN><JournalIssue><Volume>{volume}\
+             </Volume><Issue>1</Issue><PubDate>{date}</PubDate></JournalIssue>\
+             <Title>Journal of {journal}</Title></Journal>{title_element}<Pagination>\
+             <MedlinePgn>1-10</MedlinePgn></Pagination>{abstract_element}<AuthorList>\
+             {authors}</AuthorList><Language>eng</Language></Article>{other_abstract}\
+             <MeshHeadingList><MeshHeading><DescriptorName UI=\"D{mesh}\">{mesh_name}\
+             </DescriptorName></MeshHeading></MeshHeadingList></MedlineCitation>{unknown}\
+             <PubmedData><ArticleIdList><ArticleId IdType=\"pubmed\">{pmid}</ArticleId>{doi}\
+             </ArticleIdList><ReferenceList>{references}</ReferenceList></PubmedData>\
+             </PubmedArticle>\n",
+            issn = pmid % 10000,
+            volume = 1 + pmid % 50,
+            journal = WORDS[pmid % WORDS.len()],
+            mesh = pmid % 1000,
+            mesh_name = capitalize(WORDS[(pmid / 7) % WORDS.len()]),
+        ));
+        expected.records += 1;
+        if doi.is_empty() {
+            continue;
+        }
+        expected.valid += 1;
+        // Without an abstract, the keyword cannot be found in it.
+        if relevant && !matches!(edge_case, Some(EdgeCase::NoAbstract)) {
+            expected.relevant += 1;
+        }
+    }
+    if edge_case.is_some() {
+        expected.edge_cases += 1;
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,70 @@
+use crate::csv;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+struct FileHits {
+    articles: usize,
+    kept: usize,
+    hits: Vec<usize>,
+}
+
+/// Collects the keyword hit counts of every input file, to see which keywords actually drive the
+/// selection of the corpus.
+pub struct KeywordHeatmap {
+    keywords: Vec<String>,
+    rows: Mutex<BTreeMap<String, FileHits>>,
+}
+
+impl KeywordHeatmap {
+    pub fn new(keywords: Vec<String>) -> Self {
+        Self {
+            keywords,
+            rows: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(&self, file_name: &str, articles: usize, kept: usize, hits: Vec<usize>) {
+        let mut rows = self.rows.lock().unwrap();
+        rows.insert(
+            file_name.to_string(),
+            FileHits {
+                articles,
+                kept,
+                hits,
+            },
+        );
+    }
+
+    /// Moves the rows of another heatmap, e.g. the one of a shard, into this one.
+    #[cfg(feature = "thread-per-core")]
+    pub fn absorb(&self, other: &KeywordHeatmap) {
+        let mut rows = self.rows.lock().unwrap();
+        rows.append(&mut other.rows.lock().unwrap());
+    }
+
+    /// Files that were parsed without a single valid article.
+    pub fn files_without_articles(&self) -> Vec<String> {
+        let rows = self.rows.lock().unwrap();
+        rows.iter()
+            .filter(|(_, file_hits)| file_hits.articles == 0)
+            .map(|(file_name, _)| file_name.clone())
+            .collect()
+    }
+
+    /// Writes one row per file and one column per keyword.
+    pub fn write_csv(&self, path: &str) -> std::io::Result<()> {
+        let mut header = vec!["file".to_string()];
+        header.extend(self.keywords.iter().cloned());
+        header.push("articles".to_string());
+        header.push("kept".to_string());
+        let mut output = csv::row(&header);
+        for (file_name, file_hits) in self.rows.lock().unwrap().iter() {
+            let mut fields = vec![file_name.clone()];
+            fields.extend(file_hits.hits.iter().map(|n| n.to_string()));
+            fields.push(file_hits.articles.to_string());
+            fields.push(file_hits.kept.to_string());
+            output.push_str(&csv::row(&fields));
+        }
+        std::fs::write(path, output)
+    }
+}
@@ -0,0 +1,152 @@
+use crate::config::Source;
+use crate::config_file::quote;
+use crate::output::OutputFormat;
+use clap::ValueEnum;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+pub type InitError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(clap::Args, Debug)]
+pub struct InitArgs {
+    /// Where to write the config file.
+    #[arg(default_value = "hcse.toml")]
+    path: String,
+
+    /// Overwrite the config file if it exists.
+    #[arg(long)]
+    force: bool,
+}
+
+/// Asks for the most important settings and writes them as a config file for --config.
+pub fn run(args: &InitArgs) -> Result<(), InitError> {
+    if Path::new(&args.path).exists() && !args.force {
+        return Err(format!("{} exists already, use --force to overwrite it", args.path).into());
+    }
+    let stdin = std::io::stdin();
+    let mut prompt = Prompt {
+        input: stdin.lock(),
+    };
+    println!("This creates a config file for the PubMed parser. Press enter to keep a default.");
+    let source: Source = prompt.choice("Read the yearly baseline or the daily update files?")?;
+    let year: u32 = prompt.number("Which release year?", 24)?;
+    let start: u32 = prompt.number("The first file to process (0 is the oldest)?", 0)?;
+    let end: u32 = loop {
+        let end = prompt.number("The last file to process?", 1218)?;
+        if end >= start {
+            break end;
+        }
+        println!("The last file cannot come before the first file.");
+    };
+    let keywords = prompt.list(
+        "Keep articles whose title and abstract mention one of these keywords (comma separated)",
+        "cancer,oncology,tumor",
+    )?;
+    let output_format: OutputFormat = prompt.choice("Which output format?")?;
+    let output_path = prompt.text("Where should the results go? (empty for the current folder)")?;
+
+    let mut lines = vec![
+        "# Settings for the PubMed parser, written by `hcse init`.".to_string(),
+        format!("# Use them with: hcse --config {}", args.path),
+        "# Flags given on the command line override the values in this file.".to_string(),
+        format!("source = {}", quote(&value_name(source))),
+        format!("year = {}", year),
+        format!("start = {}", start),
+        format!("end = {}", end),
+        format!(
+            "keywords = [{}]",
+            keywords
+                .iter()
+                .map(|k| quote(k))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        format!("output_format = {}", quote(&value_name(output_format))),
+    ];
+    if !output_path.is_empty() {
+        lines.push(format!("output_path = {}", quote(&output_path)));
+    }
+    std::fs::write(&args.path, lines.join("\n") + "\n")?;
+    println!(
+        "Wrote {}. Start the parser with: hcse --config {}",
+        args.path, args.path
+    );
+    Ok(())
+}
+
+/// The name of an enum value as it is written on the command line.
+fn value_name<T: ValueEnum>(value: T) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+struct Prompt<R: BufRead> {
+    input: R,
+}
+
+impl<R: BufRead> Prompt<R> {
+    /// Prints the question and returns the trimmed answer.
+    fn text(&mut self, question: &str) -> Result<String, InitError> {
+        print!("{} ", question);
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            return Err("the input ended before all questions were answered".into());
+        }
+        Ok(answer.trim().to_string())
+    }
+
+    fn number(&mut self, question: &str, default: u32) -> Result<u32, InitError> {
+        loop {
+            let answer = self.text(&format!("{} [{}]", question, default))?;
+            if answer.is_empty() {
+                return Ok(default);
+            }
+            match answer.parse() {
+                Ok(number) => return Ok(number),
+                Err(_) => println!("Please enter a whole number."),
+            }
+        }
+    }
+
+    fn list(&mut self, question: &str, default: &str) -> Result<Vec<String>, InitError> {
+        loop {
+            let answer = self.text(&format!("{} [{}]", question, default))?;
+            let answer = if answer.is_empty() { default } else { &answer };
+            let items: Vec<String> = answer
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect();
+            if !items.is_empty() {
+                return Ok(items);
+            }
+            println!("Please enter at least one value.");
+        }
+    }
+
+    /// Offers all values of an enum, the first one is the default.
+    fn choice<T: ValueEnum>(&mut self, question: &str) -> Result<T, InitError> {
+        let names: Vec<String> = T::value_variants()
+            .iter()
+            .map(|v| value_name(v.clone()))
+            .collect();
+        loop {
+            let answer = self.text(&format!(
+                "{} ({}) [{}]",
+                question,
+                names.join("/"),
+                names[0]
+            ))?;
+            if answer.is_empty() {
+                return Ok(T::value_variants()[0].clone());
+            }
+            match T::from_str(&answer, true) {
+                Ok(value) => return Ok(value),
+                Err(_) => println!("Please answer with one of: {}", names.join(", ")),
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! The parsing part of the PubMed parser as a library, for services that fetch a handful of
+//! records, e.g. through the E-utilities, and do not need the download pipeline.
+pub mod article;
+pub mod clock;
+pub mod efetch;
+pub mod fetcher;
+pub mod file_system;
+pub mod pipeline;
+pub mod xml_backend;
+
+pub use article::{Article, ArticleField, Extraction, ExtractionProfile};
+use std::io::Read;
+use xml_backend::{RoxmltreeBackend, XmlBackend};
+
+pub type ParseError = xml_backend::BackendError;
+
+/// Parses all PubmedArticle records of a PubMed xml document with all optional fields, including
+/// the ones without a title or a DOI. `Article::is_valid` tells which of them the pipeline of the
+/// parser would keep.
+pub fn parse_pubmed_xml(xml: &str) -> Result<Vec<Article>, ParseError> {
+    let extraction = Extraction {
+        keep_invalid: true,
+        ..ExtractionProfile::Full.extraction(&[])
+    };
+    RoxmltreeBackend {}.parse(xml, &extraction, &mut |_, _| {}, &mut |_| {})
+}
+
+pub fn parse_pubmed_xml_bytes(xml: &[u8]) -> Result<Vec<Article>, ParseError> {
+    parse_pubmed_xml(std::str::from_utf8(xml)?)
+}
+
+/// Reads the document from a reader, e.g. the body of an efetch response or a decompressing
+/// reader. The document is read completely before it is parsed.
+pub fn parse_pubmed_xml_reader<R: Read>(mut reader: R) -> Result<Vec<Article>, ParseError> {
+    let mut xml = String::new();
+    reader.read_to_string(&mut xml)?;
+    parse_pubmed_xml(&xml)
+}
@@ -1,7 +1,109 @@
-use crate::parser::{ParserMessage, ParserState};
+use crate::metrics::Channel;
+use crate::parser::{ParserMessage, ParserState, Progress};
+use crate::run_info::{process_title, set_process_title};
+use crate::topics::TopicStats;
+use crossterm::terminal;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::VecDeque;
 use std::io::{self, Write};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The columns the bar template needs besides the message: elapsed time, the 40 column bar,
+/// the percentage, and the eta.
+const BAR_TEMPLATE_WIDTH: usize = 65;
+/// The columns the bar template of a parser needs besides the message: elapsed time, the 40
+/// column bar and the throughput.
+const WORKER_BAR_TEMPLATE_WIDTH: usize = 63;
+/// The columns the spinner template needs besides the message.
+const SPINNER_TEMPLATE_WIDTH: usize = 4;
+/// The throughput of a parser is measured over this window, so a parser that stops making
+/// progress drops to zero instead of keeping the average of its stage.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+/// How often the bars are redrawn when no message arrives, so stalled parsers show up.
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+/// The overall progress bar counts in fractions of files, so files in progress move it as well.
+const UNITS_PER_FILE: u64 = 1000;
+
+/// The share of the time a file spends in each stage with a progress report, in units of
+/// UNITS_PER_FILE. Without downloads, e.g. with --input-dir, extraction and parsing make up the
+/// whole file. Counting every stage the same would skew the eta differently in both modes.
+struct StageWeights {
+    download: u64,
+    extract: u64,
+    process: u64,
+}
+
+impl StageWeights {
+    fn new(downloads: bool) -> Self {
+        match downloads {
+            true => StageWeights {
+                download: 600,
+                extract: 100,
+                process: 300,
+            },
+            false => StageWeights {
+                download: 0,
+                extract: 250,
+                process: 750,
+            },
+        }
+    }
+
+    /// How far a file in this state has come, or None for states without a progress report.
+    fn file_progress(&self, state: ParserState) -> Option<u64> {
+        let (done_before, weight, percentage) = match state {
+            ParserState::Downloading(p) => (0, self.download, p.percentage),
+            ParserState::Extracting(p) => (self.download, self.extract, p.percentage),
+            ParserState::Processing(p) => {
+                (self.download + self.extract, self.process, p.percentage)
+            }
+            _ => return None,
+        };
+        Some(done_before + weight * percentage.min(100) as u64 / 100)
+    }
+}
+
+/// The recent amounts a parser reported for its current stage.
+#[derive(Default)]
+struct Throughput {
+    stage: &'static str,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Throughput {
+    fn record(&mut self, stage: &'static str, amount: u64) {
+        if self.stage != stage {
+            self.stage = stage;
+            self.samples.clear();
+        }
+        let now = Instant::now();
+        self.samples.push_back((now, amount));
+        // The newest sample from before the window is kept as the start of the window.
+        while self.samples.len() > 2 && now - self.samples[1].0 > THROUGHPUT_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The amount per second since the start of the window, up to now rather than up to the
+    /// last report. Empty right after the stage started.
+    fn describe(&self) -> String {
+        let (Some((started, first)), Some((_, last))) = (self.samples.front(), self.samples.back())
+        else {
+            return String::new();
+        };
+        let seconds = started.elapsed().as_secs_f64();
+        if seconds < 0.5 {
+            return String::new();
+        }
+        let per_second = last.saturating_sub(*first) as f64 / seconds;
+        match self.stage {
+            "Processing" => format!("{:.0} rec/s", per_second),
+            _ => format!("{:.1} MB/s", per_second / 1e6),
+        }
+    }
+}
 
 pub struct Logger {
     n_progs: usize,
@@ -14,7 +116,14 @@ pub struct Logger {
     spinner_style: ProgressStyle,
     finished_files: usize,
     found_articles: usize,
+    stage_weights: StageWeights,
+    /// The weighted progress of the file each parser works on.
+    file_progress: Vec<u64>,
+    throughput: Vec<Throughput>,
     overall_progress_bar: ProgressBar,
+    run_id: String,
+    wide: bool,
+    topics: Option<Arc<TopicStats>>,
 }
 
 /// This class handles the log output from all the worker processes.
@@ -22,7 +131,13 @@ pub struct Logger {
 /// get_sender as many times as required and the, once computation and reporting begins, run()
 /// initializes a loop that waits for status updates and reprints the console output.
 impl Logger {
-    pub fn new(number_of_processes: usize, number_of_files: usize) -> Self {
+    pub fn new(
+        number_of_processes: usize,
+        number_of_files: usize,
+        downloads: bool,
+        run_id: String,
+        wide: bool,
+    ) -> Self {
         let (sender, receiver) = channel();
         let mut last_parser_states = vec![];
         for _i in 0..number_of_processes {
@@ -34,12 +149,19 @@ impl Logger {
             .unwrap()
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ");
         let bar_style = ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>5}/{len:5} {msg} ({eta})",
+            "[{elapsed_precise}] {bar:40.cyan/blue} {percent:>3}% {msg} ({eta})",
+        )
+        .unwrap()
+        .progress_chars("##-");
+        // The throughput takes the place of the percentage, which does not tell whether a
+        // parser is still making progress.
+        let worker_bar_style = ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {prefix:>10} {msg}",
         )
         .unwrap()
         .progress_chars("##-");
         let m = MultiProgress::new();
-        let overall = m.add(ProgressBar::new(number_of_files as u64));
+        let overall = m.add(ProgressBar::new(number_of_files as u64 * UNITS_PER_FILE));
         overall.set_style(bar_style.clone());
         for _i in 0..number_of_processes {
             let pb = m.add(ProgressBar::new(100));
@@ -54,14 +176,27 @@ impl Logger {
             last_parser_states,
             multi_progress: m,
             bars,
-            progress_bar_style: bar_style.clone(),
+            progress_bar_style: worker_bar_style,
             spinner_style: spinner_style.clone(),
             finished_files: 0,
             found_articles: 0,
+            stage_weights: StageWeights::new(downloads),
+            file_progress: vec![0; number_of_processes],
+            throughput: (0..number_of_processes)
+                .map(|_| Throughput::default())
+                .collect(),
             overall_progress_bar: overall,
+            run_id,
+            wide,
+            topics: None,
         }
     }
 
+    /// Adds the kept articles per topic to the overall progress.
+    pub fn show_topics(&mut self, topics: Arc<TopicStats>) {
+        self.topics = Some(topics);
+    }
+
     pub fn get_sender(&self) -> Sender<ParserMessage> {
         self.sender.clone()
     }
@@ -78,21 +213,27 @@ impl Logger {
                 }
                 clear_counter = 0;
             }
-            let msg = self.receiver.recv();
-            if msg.is_ok() {
-                let m = msg.unwrap();
-
-                if matches!(m.new_state, ParserState::Terminate) {
-                    println!("Shutting down.");
-                    break;
+            let waiting = Instant::now();
+            let m = match self.receiver.recv_timeout(REDRAW_INTERVAL) {
+                Ok(m) => m,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.refresh_throughput();
+                    continue;
                 }
-                let index = m.id as usize;
-                if index < self.n_progs {
-                    self.last_parser_states[index] = m.new_state;
-                }
-                self.update_view(index);
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            Channel::Logger.metrics().received(waiting.elapsed());
+            if matches!(m.new_state, ParserState::Terminate) {
+                println!("Shutting down.");
+                break;
+            }
+            let index = m.id as usize;
+            if index < self.n_progs {
+                self.last_parser_states[index] = m.new_state;
+                self.account_progress(index, m.new_state);
             }
-            std::thread::sleep(std::time::Duration::from_millis(100));
+            self.update_view(index);
+            std::thread::sleep(Duration::from_millis(100));
         }
     }
 
@@ -103,49 +244,82 @@ impl Logger {
         Ok(())
     }
 
-    fn set_message(&self, message: &String, index: usize) {
-        self.bars[index].set_message(message.clone());
+    fn set_message(&self, message: &str, index: usize) {
+        self.bars[index].set_message(self.fit_to_terminal(message, SPINNER_TEMPLATE_WIDTH));
         self.bars[index].set_style(self.spinner_style.clone());
     }
 
     fn update_view(&mut self, index: usize) {
         match self.last_parser_states[index] {
             ParserState::Restarting => self.bars[index].reset_elapsed(),
-            ParserState::Waiting => self.set_message(&"Waiting".to_string(), index),
-            ParserState::FinishedInputFile(n_articles) => {
-                self.update_article_and_file_counts(n_articles)
-            }
-            ParserState::WritingFile => {
-                self.set_message(&"Writing output file... ".to_string(), index)
+            ParserState::Waiting => self.set_message("Waiting", index),
+            ParserState::Retrying(attempt) => {
+                self.set_message(&format!("Retrying (attempt {})", attempt + 1), index)
             }
+            ParserState::FinishedInputFile(_) => {}
+            ParserState::WritingFile => self.set_message("Writing output file... ", index),
             ParserState::Done => self.finish_parser_progress(index),
-            ParserState::CheckMd5 => self.set_message(&"Check Md5 Checksum".to_string(), index),
+            ParserState::CheckMd5 => self.set_message("Check Md5 Checksum", index),
+            ParserState::ChecksumMissing => {
+                self.set_message("No checksum found, skipping verification", index)
+            }
             ParserState::Downloading(progress) => {
-                self.print_progress_bar("Downloading".to_string(), index, &progress)
+                self.print_progress_bar("Downloading", index, progress)
             }
             ParserState::Processing(progress) => {
-                self.print_progress_bar("Processing".to_string(), index, &progress)
+                self.print_progress_bar("Processing", index, progress)
             }
             ParserState::Extracting(progress) => {
-                self.print_progress_bar("Extracting".to_string(), index, &progress)
+                self.print_progress_bar("Extracting", index, progress)
             }
             ParserState::ErrorChecksumWrong => {
-                Logger::print_error_message("Checksum is wrong!", index)
+                self.print_error_message("Checksum is wrong!", index)
+            }
+            ParserState::ErrorChecksumMissing => {
+                self.print_error_message("No checksum for the archive!", index)
             }
             ParserState::ErrorWritingFailed => {
-                Logger::print_error_message("Writing file failed!", index)
+                self.print_error_message("Writing file failed!", index)
             }
             ParserState::ErrorDownloadFailed => {
-                Logger::print_error_message("Downloading data failed!", index)
+                self.print_error_message("Downloading data failed!", index)
             }
-            ParserState::ErrorParsingFailed => {
-                Logger::print_error_message("Parsing failed!", index)
+            ParserState::ErrorParsingFailed => self.print_error_message("Parsing failed!", index),
+            ParserState::ErrorFilteringFailed => {
+                self.print_error_message("Filtering failed!", index)
             }
             ParserState::ErrorExtractionFailed => {
-                Logger::print_error_message("Extracting archive failed!", index)
+                self.print_error_message("Extracting archive failed!", index)
             }
             ParserState::ErrorDeleting => {
-                Logger::print_error_message("Deleting artifacts failed!", index)
+                self.print_error_message("Deleting artifacts failed!", index)
+            }
+            ParserState::NoValidArticles => self.print_error_message(
+                "WARNING: no valid articles in this file, is the parser out of date?",
+                index,
+            ),
+            ParserState::OversizedRecords(count) => self.print_error_message(
+                &format!(
+                    "WARNING: {} records were above the size limit and were truncated or skipped",
+                    count
+                ),
+                index,
+            ),
+            // Printed once when it arrives, see account_progress.
+            ParserState::Warning(_) => {}
+            ParserState::ConcurrencyReduced(limit) => self.print_error_message(
+                &format!(
+                    "Throttled by the server, downloading {} files at a time",
+                    limit
+                ),
+                index,
+            ),
+            ParserState::ConcurrencyRaised(limit) => self.set_message(
+                &format!("Server recovered, downloading {} files at a time", limit),
+                index,
+            ),
+            ParserState::BudgetExhausted => {
+                self.set_message("Download budget exhausted, stopping", index)
             }
             ParserState::Terminate => {
                 let _ = self.multi_progress.clear();
@@ -154,29 +328,110 @@ impl Logger {
         }
     }
 
-    fn update_article_and_file_counts(&mut self, articles: usize) {
-        self.finished_files += 1;
-        self.found_articles += articles;
+    /// Counts every message once when it arrives. The views are redrawn from the last states
+    /// repeatedly, so they cannot do the counting.
+    fn account_progress(&mut self, index: usize, state: ParserState) {
+        match state {
+            ParserState::FinishedInputFile(n_articles) => {
+                self.finished_files += 1;
+                self.found_articles += n_articles;
+                self.file_progress[index] = 0;
+            }
+            ParserState::Restarting | ParserState::Retrying(_) | ParserState::Done => {
+                self.file_progress[index] = 0
+            }
+            ParserState::Warning(kind) => {
+                // The bars are cleared while printing, so the layout of the progress display stays intact.
+                let line = format!("{}Process: WARNING: {}", index, kind.description());
+                let line = self.fit_to_terminal(&line, 0);
+                self.multi_progress.suspend(|| println!("{}", line));
+            }
+            _ => {
+                if let Some(progress) = self.stage_weights.file_progress(state) {
+                    self.file_progress[index] = progress;
+                }
+                if let Some((stage, progress)) = stage_progress(state) {
+                    self.throughput[index].record(stage, progress.amount);
+                }
+            }
+        }
     }
 
     fn update_overall_progress_bar(&self) {
+        let in_progress: u64 = self.file_progress.iter().sum();
+        let position = self.finished_files as u64 * UNITS_PER_FILE + in_progress;
+        self.overall_progress_bar.set_position(position);
+        let total_files = self.overall_progress_bar.length().unwrap_or(0) / UNITS_PER_FILE;
+        let mut message = format!(
+            "{}/{} files, found {} articles.",
+            self.finished_files, total_files, self.found_articles
+        );
+        if let Some(topics) = &self.topics {
+            let per_topic: Vec<String> = topics
+                .summary()
+                .iter()
+                .map(|t| format!("{}: {}", t.name, t.counts.kept))
+                .collect();
+            message.push_str(&format!(" {}", per_topic.join(", ")));
+        }
         self.overall_progress_bar
-            .set_position(self.finished_files.clone() as u64);
-        self.overall_progress_bar
-            .set_message(format!("Found {} articles.", self.found_articles.clone()));
+            .set_message(self.fit_to_terminal(&message, BAR_TEMPLATE_WIDTH));
+        let total_units = self.overall_progress_bar.length().unwrap_or(0).max(1);
+        set_process_title(&process_title(&self.run_id, 100 * position / total_units));
     }
 
-    fn print_progress_bar(&self, message: String, index: usize, progress: &u8) {
-        self.bars[index].set_message(format!("Process {}:{}", index + 1, message));
-        self.bars[index].set_position(*progress as u64);
+    fn print_progress_bar(&self, stage: &str, index: usize, progress: Progress) {
+        let message = format!("Process {}:{}", index + 1, stage);
+        self.bars[index].set_message(self.fit_to_terminal(&message, WORKER_BAR_TEMPLATE_WIDTH));
+        self.bars[index].set_prefix(self.throughput[index].describe());
+        self.bars[index].set_position(progress.percentage as u64);
         self.bars[index].set_style(self.progress_bar_style.clone());
     }
 
-    fn print_error_message(message: &str, index: usize) {
-        println!("{}Process: {}", index, message,);
+    /// Redraws the bars of the parsers that are in a stage with a throughput.
+    fn refresh_throughput(&mut self) {
+        for index in 0..self.n_progs {
+            if stage_progress(self.last_parser_states[index]).is_some() {
+                self.update_view(index);
+            }
+        }
+    }
+
+    fn print_error_message(&self, message: &str, index: usize) {
+        let line = format!("{}Process: {}", index, message);
+        println!("{}", self.fit_to_terminal(&line, 0));
+    }
+
+    /// MultiProgress corrupts its layout when a line wraps, so messages are cut to the width of
+    /// the terminal. --wide keeps them complete, e.g. when the output is written to a file.
+    fn fit_to_terminal(&self, message: &str, reserved_columns: usize) -> String {
+        if self.wide {
+            return message.to_string();
+        }
+        let width = terminal::size().map(|(w, _)| w as usize).unwrap_or(80);
+        truncate_with_ellipsis(message, width.saturating_sub(reserved_columns))
     }
 
     fn finish_parser_progress(&self, index: usize) {
         self.bars[index].finish_with_message("Done");
     }
 }
+
+/// The name and progress of the states that report progress.
+fn stage_progress(state: ParserState) -> Option<(&'static str, Progress)> {
+    match state {
+        ParserState::Downloading(progress) => Some(("Downloading", progress)),
+        ParserState::Extracting(progress) => Some(("Extracting", progress)),
+        ParserState::Processing(progress) => Some(("Processing", progress)),
+        _ => None,
+    }
+}
+
+fn truncate_with_ellipsis(message: &str, max_chars: usize) -> String {
+    if message.chars().count() <= max_chars {
+        return message.to_string();
+    }
+    let mut truncated: String = message.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
@@ -1,61 +1,1080 @@
-use futures_util;
+use budget::DownloadBudget;
+use checksum::{ChecksumManifest, ChecksumPolicy};
+use config::{Config, Source};
+use contact::ContactArgs;
+use events::EventLog;
+use hcse_parser::article::{
+    AbstractSource, ArticleField, ExtractionProfile, OversizedPolicy, VersionPolicy,
+};
+use hcse_parser::{article, xml_backend};
+use heatmap::KeywordHeatmap;
 use logger::Logger;
+use manifest::{FileOutcome, FileStatus, Manifest, RunManifest};
+use output::OutputArgs;
 use parser::*;
-mod article;
+use resources::ResourceMonitor;
+use run_info::StartupBanner;
+use scheduling::ExecutionMode;
+use summary::RunSummary;
+use throttle::DownloadThrottle;
+use topics::{Topic, TopicStats};
+use warnings::WarningLog;
+use work_queue::{Origin, Origins, ReleaseOrder, WorkQueue};
+use xml_backend::XmlBackendKind;
+mod avro;
+mod boilerplate;
+mod budget;
+mod checksum;
+mod config;
+mod config_file;
+mod contact;
+mod csv;
+mod events;
+mod fetch;
+mod filter;
+mod fixture;
+mod heatmap;
+mod init;
 mod logger;
+mod manifest;
+mod metrics;
+mod migrate;
+mod output;
 mod parser;
-use clap::Parser;
-use std::sync::atomic::AtomicI32;
+mod paths;
+mod relevance_model;
+mod report;
+mod resources;
+mod rng;
+mod run_info;
+mod scheduling;
+mod sharded;
+mod source_index;
+mod summary;
+mod throttle;
+mod topics;
+mod warnings;
+mod work_dir;
+mod work_queue;
+mod writer;
+use clap::{Parser, Subcommand};
+use std::collections::HashSet;
 use std::sync::Arc;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// The number of files to use. Will count down from this to zero.
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Read the settings from this file, e.g. one written by `hcse init`. Flags on the command
+    /// line override the settings in the file.
+    #[arg(long)]
+    config: Option<String>,
+
+    /// The number of files to use. Will count down from this to zero. With both feeds, the
+    /// files from this index on are taken from the update files.
     #[arg(short, long, default_value_t = 1219)]
     filecount: usize,
 
     /// The number of download processes.
     #[arg(short, long, default_value_t = 10)]
     processes: usize,
+
+    /// The year of the PubMed release, either as 2024 or as 24.
+    #[arg(short, long, default_value_t = 24)]
+    year: u32,
+
+    /// Whether to read the yearly baseline or the daily update files. Both can be given, e.g.
+    /// --feed baseline --feed updatefiles with --end above --filecount, and are processed in
+    /// release order.
+    #[arg(
+        short,
+        long,
+        visible_alias = "feed",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "baseline"
+    )]
+    source: Vec<Source>,
+
+    /// The first file index to process. Defaults to 0.
+    #[arg(long)]
+    start: Option<u32>,
+
+    /// The last file index to process (inclusive). Defaults to filecount - 1, required with
+    /// several feeds.
+    #[arg(long)]
+    end: Option<u32>,
+
+    /// The base url of the PubMed archive, for institutions that run a local NLM mirror.
+    #[arg(long, default_value = "https://ftp.ncbi.nlm.nih.gov/pubmed")]
+    mirror_url: String,
+
+    /// How often a file is retried after a failed download, checksum, extraction or parse.
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+
+    /// The delay before the first retry in milliseconds. Doubles with every further attempt, up
+    /// to 10 minutes.
+    #[arg(long, default_value_t = 1000)]
+    retry_delay_ms: u64,
+
+    /// The file the outcome of every input file is recorded in.
+    #[arg(long, default_value = "run_manifest.json")]
+    manifest: String,
+
+    /// Only process the files that failed or were never attempted according to the manifest.
+    #[arg(long)]
+    resume: bool,
+
+    /// An article is kept if its title and its abstract each contain one of these keywords.
+    #[arg(long, value_delimiter = ',', default_value = "cancer,oncology,tumor")]
+    keywords: Vec<String>,
+
+    /// Write the per-file keyword hit counts as csv to this file at the end of the run.
+    #[arg(long)]
+    heatmap_path: Option<String>,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    contact: ContactArgs,
+
+    /// Additional metadata to extract, e.g. --fields authors,journal. By default only ids, title
+    /// and abstract are written.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    fields: Vec<ArticleField>,
+
+    /// How much of every record is read. minimal skips everything but ids, title and the main
+    /// abstract, full extracts all --fields. Requires --xml-backend quick-xml, roxmltree builds
+    /// the tree of every record in full before anything could be skipped.
+    #[arg(long, value_enum, default_value_t = ExtractionProfile::Standard)]
+    profile: ExtractionProfile,
+
+    /// The xml parser. quick-xml streams the input and requires a build with the quick-xml feature.
+    #[arg(long, value_enum, default_value_t = XmlBackendKind::Roxmltree)]
+    xml_backend: XmlBackendKind,
+
+    /// Print complete status messages instead of cutting them to the terminal width.
+    #[arg(long)]
+    wide: bool,
+
+    /// Process the *.xml.gz archives in this directory instead of downloading them. They are
+    /// verified against adjacent *.md5 files or a MD5SUMS file, see --checksum-policy for
+    /// archives without either. --start and --end select archives by the index in their name.
+    /// Can be given several times, the archives of all directories are then processed by
+    /// sequence number.
+    #[arg(long)]
+    input_dir: Vec<String>,
+
+    /// Verify the checksums of all archives in --input-dir in parallel before processing starts.
+    /// Corrupt archives are marked as failed in the manifest and not processed.
+    #[arg(long, requires = "input_dir")]
+    verify_existing: bool,
+
+    /// The directory for downloads and extracted files, the system's temporary directory by
+    /// default. Neither it nor the default may lie inside of the input directory or the output
+    /// path, and a --temp-dir must not contain the outputs or the input directory either.
+    #[arg(long)]
+    temp_dir: Option<String>,
+
+    /// Write the byte offsets of the kept records in the decompressed archives to a small
+    /// `<archive>.offsets.tsv` index per input file in this directory, so the raw xml of a
+    /// record can be looked up later without being stored twice.
+    #[arg(long)]
+    source_offsets: Option<String>,
+
+    /// A url or file with the checksums of all archives in the MD5SUMS format, for mirrors that
+    /// do not provide the .md5 files. It is read once at the start of the run.
+    #[arg(long, conflicts_with = "input_dir")]
+    checksum_manifest: Option<String>,
+
+    /// What to do with archives that are missing from --checksum-manifest. Archives in
+    /// --input-dir without an md5 file or MD5SUMS entry are processed with a warning, unless the
+    /// policy is fail.
+    #[arg(long, value_enum, default_value_t = ChecksumPolicy::Fetch)]
+    checksum_policy: ChecksumPolicy,
+
+    /// Append every significant event of the run as one json object per line to this file.
+    #[arg(long)]
+    events_file: Option<String>,
+
+    /// Append every warning about a record, e.g. a missing DOI, as one json object per line to
+    /// this file. The summary only counts them.
+    #[arg(long)]
+    warnings_file: Option<String>,
+
+    /// Serve the depths of and waiting times on the internal channels in the Prometheus text
+    /// format at http://<address>/metrics during the run, e.g. 127.0.0.1:9464.
+    #[arg(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Run with this niceness (0 to 19), so interactive users of a shared server are not starved.
+    #[arg(long)]
+    nice: Option<i32>,
+
+    /// Pin the threads of the runtime to these cpu cores, e.g. 0-3,8. The main thread and the
+    /// logger are not pinned.
+    #[arg(long, value_parser = scheduling::parse_cpu_list)]
+    cpus: Option<scheduling::CpuSet>,
+
+    /// thread-per-core gives every process a thread of its own with a fixed shard of the files,
+    /// which avoids contention on servers with many cores. With --cpus, the threads are pinned
+    /// to those cores in turn.
+    #[arg(long, value_enum, default_value_t = ExecutionMode::Shared)]
+    execution: ExecutionMode,
+
+    /// Keep articles by the score of this ONNX text classifier instead of the keywords. Requires a
+    /// build with the onnx feature.
+    #[arg(long)]
+    relevance_model: Option<String>,
+
+    /// The minimum score of the relevance model for an article to be kept.
+    #[arg(long, default_value_t = 0.5)]
+    relevance_threshold: f32,
+
+    /// The length of the hashed bag of words the relevance model expects as input.
+    #[arg(long, default_value_t = 4096)]
+    model_input_size: usize,
+
+    /// The seed for sampling and shuffling. The same seed on the same input gives the same subset.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Keep only this fraction of the relevant articles, e.g. 0.1 for a 10% sample.
+    #[arg(long, default_value_t = 1.0, value_parser = rng::parse_sample_rate)]
+    sample_rate: f64,
+
+    /// Process the input files in a random order instead of newest first. Not with several feeds
+    /// or input directories, which are processed in release order.
+    #[arg(long)]
+    shuffle: bool,
+
+    /// Process these file indices first, e.g. --priority-indices 1219,1218 to make the newest
+    /// files available while the rest of the range is still being processed. Not with several
+    /// feeds or input directories.
+    #[arg(long, value_delimiter = ',')]
+    priority_indices: Vec<u32>,
+
+    /// Which versions of a versioned citation to keep. Records are told apart by PMID and
+    /// version, so with `all` every version is written once. Duplicates are only removed within
+    /// one input file: a citation that is revised in a later update file is written once for
+    /// every file it appears in, and `latest` keeps the highest version of each file.
+    #[arg(long, value_enum, default_value_t = VersionPolicy::All)]
+    pmid_versions: VersionPolicy,
+
+    /// The abstract the keywords and the relevance model are applied to. Articles without an
+    /// OtherAbstract fall back to the main abstract.
+    #[arg(long, value_enum, default_value_t = AbstractSource::Main)]
+    filter_abstract: AbstractSource,
+
+    /// The abstract that is written to the results.
+    #[arg(long, value_enum, default_value_t = AbstractSource::Main)]
+    export_abstract: AbstractSource,
+
+    /// Only consider OtherAbstracts in this language, e.g. ger or spa.
+    #[arg(long)]
+    other_abstract_language: Option<String>,
+
+    /// Remove copyright statements, "ABSTRACT TRUNCATED AT 250 WORDS" markers and trial
+    /// registrations from the abstracts before they are filtered and written.
+    #[arg(long)]
+    strip_boilerplate: bool,
+
+    /// Also remove the sentences of the abstracts that match this pattern, where `*` matches any
+    /// text and case is ignored, e.g. "funding: *". Can be given several times.
+    #[arg(long = "boilerplate-pattern")]
+    boilerplate_patterns: Vec<String>,
+
+    /// The most bytes of text a single record may have, so a few huge records cannot blow up
+    /// the memory or produce lines that line-based readers cannot handle. It is applied while
+    /// parsing, so the keywords are matched against truncated records and skipped records are
+    /// not counted anywhere. No limit by default.
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_record_bytes: Option<usize>,
+
+    /// Whether records above --max-record-bytes are shortened or left out.
+    #[arg(long, value_enum, default_value_t = OversizedPolicy::Truncate)]
+    oversized_records: OversizedPolicy,
+
+    /// Break the statistics down by topic, e.g. --topic immunotherapy=checkpoint,CAR-T. Can be
+    /// given several times. Topics do not change which articles are kept.
+    #[arg(long = "topic", value_parser = topics::parse_topic)]
+    topics: Vec<Topic>,
+
+    /// Write the counts of the run as json to this file at the end.
+    #[arg(long)]
+    summary_path: Option<String>,
+
+    /// Rebuild the state of every parser, including its temporary directory, after it processed
+    /// this many files, so slow leaks in dependencies cannot add up over runs of many weeks. 0
+    /// keeps the state for the whole run.
+    #[arg(long, default_value_t = 0)]
+    recycle_after_files: usize,
+
+    /// Warn when the resident memory of the process grows above this many megabytes.
+    #[arg(long)]
+    rss_warning_mb: Option<u64>,
+
+    /// Halve the number of concurrent downloads when the server answered with 429 Too Many
+    /// Requests more than this many times. The limit grows by one again after 20 downloads
+    /// without throttling. The current limit is kept in the manifest for later runs, which start
+    /// with it.
+    #[arg(long, default_value_t = 3)]
+    throttle_threshold: u32,
+
+    /// Stop once this many gigabytes have been downloaded. Running downloads are aborted and the
+    /// remaining files are left for a later run with --resume.
+    #[arg(long)]
+    max_download_gb: Option<f64>,
+
+    /// Read every output back at the end of the run and mark the files with corrupt outputs as
+    /// failed in the manifest.
+    #[arg(long)]
+    validate_outputs: bool,
+
+    /// Delete corrupt per-file outputs found by --validate-outputs and process their input files
+    /// again.
+    #[arg(long, requires = "validate_outputs")]
+    regenerate_corrupt: bool,
+}
+
+// Without a subcommand, the parser downloads and processes the archives.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Ask for the most important settings and write them to a config file.
+    Init(init::InitArgs),
+    /// Fetch single articles by PMID from the NCBI E-utilities and write them like the results
+    /// of a run.
+    FetchPmids(fetch::FetchPmidsArgs),
+    /// Rewrite result files written with an older schema version.
+    Migrate(migrate::MigrateArgs),
+    /// Show the stage timeline of every worker from the events file of a run, to see whether the
+    /// run is limited by the network or by the cpu.
+    Report(report::ReportArgs),
+    /// Write synthetic PubMed archives with checksums and edge cases, to benchmark and validate
+    /// configurations without downloading from NCBI.
+    GenFixture(fixture::GenFixtureArgs),
+}
+
+impl Args {
+    /// The file indices to process from a feed, newest first. With several feeds, the update
+    /// files continue the numbering of the baseline at --filecount.
+    fn file_indices(&self, source: Source) -> Vec<u32> {
+        let start = self.start.unwrap_or(0);
+        let end = match self.end {
+            Some(end) => end as usize + 1,
+            None => self.filecount,
+        };
+        let several_feeds = self.source.len() > 1;
+        (start as usize..end)
+            .rev()
+            .filter(|index| match source {
+                _ if !several_feeds => true,
+                Source::Baseline => *index < self.filecount,
+                Source::Updatefiles => *index >= self.filecount,
+            })
+            .map(|i| i as u32)
+            .collect()
+    }
+
+    /// Whether a local archive is in the range of --start and --end. Without --end, all archives
+    /// from --start on are taken, --filecount only applies to the feeds. Archives that are not
+    /// named like the NLM ones are always taken.
+    fn selects_local_archive(&self, file_name: &str) -> bool {
+        match config::file_index(file_name) {
+            Some(index) => {
+                index >= self.start.unwrap_or(0) && self.end.is_none_or(|end| index <= end)
+            }
+            None => true,
+        }
+    }
+
+    /// Whether the archives come from several feeds or input directories and have to be
+    /// processed in release order, see work_queue::merge_roots.
+    fn has_several_roots(&self) -> bool {
+        match self.input_dir.len() {
+            0 => self.source.len() > 1,
+            n => n > 1,
+        }
+    }
+
+    /// Several feeds or input directories are processed in release order, which a shuffled or
+    /// prioritized order would break.
+    fn check_order(&self) -> Result<(), String> {
+        if !self.has_several_roots() {
+            return Ok(());
+        }
+        if self.shuffle {
+            return Err(
+                "--shuffle cannot be combined with several feeds or input directories, \
+                        they are processed in release order"
+                    .into(),
+            );
+        }
+        if !self.priority_indices.is_empty() {
+            return Err(
+                "--priority-indices cannot be combined with several feeds or input directories, \
+                 they are processed in release order"
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Refuses an index range that would silently select no files.
+    fn check_range(&self) -> Result<(), String> {
+        let start = self.start.unwrap_or(0) as usize;
+        // The update files are numbered from --filecount on, so the default end of the range
+        // would leave all of them out.
+        if self.input_dir.is_empty() && self.source.len() > 1 && self.end.is_none() {
+            return Err(
+                "several feeds need --end, the index of the last update file to process".into(),
+            );
+        }
+        match self.end {
+            Some(end) if start > end as usize => Err(format!(
+                "--start {} is after --end {}, no files would be processed",
+                start, end
+            )),
+            None if start >= self.filecount => Err(format!(
+                "--start {} is not below --filecount {}, no files would be processed",
+                start, self.filecount
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Makes the directories absolute, so the config and the parsers do not depend on the
+    /// working directory.
+    fn normalize_paths(&mut self) -> std::io::Result<()> {
+        for path in self.input_dir.iter_mut() {
+            *path = paths::normalize_string(path)?;
+        }
+        for path in [
+            &mut self.temp_dir,
+            &mut self.source_offsets,
+            &mut self.output.output_path,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = paths::normalize_string(path)?;
+        }
+        Ok(())
+    }
+
+    /// Profiles other than standard only change what the streaming backend parses. The minimal
+    /// profile does not read the elements that optional fields and other abstracts come from, so
+    /// asking for them would silently give empty results.
+    fn check_profile(&self) -> Result<(), String> {
+        if self.profile != ExtractionProfile::Standard
+            && self.xml_backend != XmlBackendKind::QuickXml
+        {
+            return Err(
+                "--profile only saves work with the streaming parser, use --xml-backend quick-xml \
+                 or --fields"
+                    .into(),
+            );
+        }
+        if self.profile != ExtractionProfile::Minimal {
+            return Ok(());
+        }
+        if !self.output.required_fields().is_empty() {
+            return Err(
+                "--output-format aggregate counts by year and journal, which --profile \
+                        minimal does not extract, use standard or full"
+                    .into(),
+            );
+        }
+        if !self.fields.is_empty() {
+            return Err("--profile minimal does not extract --fields, use standard or full".into());
+        }
+        if self.filter_abstract != AbstractSource::Main
+            || self.export_abstract != AbstractSource::Main
+        {
+            return Err(
+                "--profile minimal only reads the main abstract, use standard for other abstracts"
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Refuses layouts where the cleanup of the temporary files could delete inputs or results,
+    /// or where inputs and results are mixed in one directory.
+    fn check_paths(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let output = match &self.output.output_path {
+            Some(path) => Some(paths::RunPath::new("--output-path", path)?),
+            None => None,
+        };
+        let mut inputs = vec![];
+        for path in &self.input_dir {
+            inputs.push(paths::RunPath::new("--input-dir", path)?);
+        }
+        if let Some(output) = &output {
+            for input in &inputs {
+                output
+                    .ensure_outside(input, "The results would be mixed with the input archives")?;
+                input
+                    .ensure_outside(output, "The input archives would be mixed with the results")?;
+            }
+        }
+        // The downloads go to work directories inside the temporary directory, which must not be
+        // an input or output directory either when it is the system's default.
+        let temp_dir = match &self.temp_dir {
+            Some(temp_dir) => paths::RunPath::new("--temp-dir", temp_dir)?,
+            None => paths::RunPath::new(
+                "the temporary directory",
+                &std::env::temp_dir().to_string_lossy(),
+            )?,
+        };
+        for path in output.iter().chain(&inputs) {
+            temp_dir.ensure_outside(
+                path,
+                "The temporary files would be mixed with the inputs or results",
+            )?;
+        }
+        // A directory given as --temp-dir is taken as scratch space as a whole, files of the
+        // run in the shared system directory are left alone by the cleanup.
+        if self.temp_dir.is_none() {
+            return Ok(());
+        }
+        let mut kept = vec![paths::RunPath::new("--manifest", &self.manifest)?];
+        if let Some(heatmap_path) = &self.heatmap_path {
+            kept.push(paths::RunPath::new("--heatmap-path", heatmap_path)?);
+        }
+        if let Some(summary_path) = &self.summary_path {
+            kept.push(paths::RunPath::new("--summary-path", summary_path)?);
+        }
+        if let Some(events_file) = &self.events_file {
+            kept.push(paths::RunPath::new("--events-file", events_file)?);
+        }
+        if let Some(warnings_file) = &self.warnings_file {
+            kept.push(paths::RunPath::new("--warnings-file", warnings_file)?);
+        }
+        if let Some(source_offsets) = &self.source_offsets {
+            kept.push(paths::RunPath::new("--source-offsets", source_offsets)?);
+        }
+        kept.extend(output);
+        kept.extend(inputs);
+        for path in &kept {
+            path.ensure_outside(
+                &temp_dir,
+                "The temporary files are deleted after every file",
+            )?;
+        }
+        Ok(())
+    }
 }
 
 fn main() {
-    let args = Args::parse();
-    let multi_threaded_runtime = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .max_blocking_threads(args.processes)
-        .worker_threads(args.processes)
-        .build()
-        .unwrap();
-    let _ = multi_threaded_runtime.block_on(run(args.processes, args.filecount));
-}
-
-async fn run(n_procs: usize, n_files: usize) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::builder()
+    let args = match config_file::parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    let result = match &args.command {
+        Some(Command::Migrate(migrate_args)) => Some(migrate::run(migrate_args)),
+        Some(Command::Init(init_args)) => Some(init::run(init_args)),
+        Some(Command::FetchPmids(fetch_args)) => Some(fetch::run(fetch_args)),
+        Some(Command::Report(report_args)) => Some(report::run(report_args)),
+        Some(Command::GenFixture(fixture_args)) => Some(fixture::run(fixture_args)),
+        None => None,
+    };
+    if let Some(result) = result {
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(niceness) = args.nice {
+        if let Err(error) = scheduling::set_niceness(niceness) {
+            eprintln!("Could not set the niceness: {}", error);
+        }
+    }
+    let runtime = match args.execution {
+        // The shards bring their own runtimes, the main one only prepares the run.
+        ExecutionMode::ThreadPerCore => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build(),
+        ExecutionMode::Shared => {
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            if let Some(cpus) = args.cpus.clone() {
+                builder.on_thread_start(move || {
+                    if let Err(error) = scheduling::pin_to_cpus(&cpus) {
+                        eprintln!("Could not pin a worker to cpus {:?}: {}", cpus.0, error);
+                    }
+                });
+            }
+            builder
+                .enable_all()
+                .max_blocking_threads(args.processes)
+                .worker_threads(args.processes)
+                .build()
+        }
+    }
+    .unwrap();
+    if let Err(error) = runtime.block_on(run(args)) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+async fn run(mut args: Args) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    args.normalize_paths()?;
+    args.check_range()?;
+    args.check_order()?;
+    args.check_paths()?;
+    args.check_profile()?;
+    for field in args.output.required_fields() {
+        if !args.fields.contains(field) {
+            args.fields.push(*field);
+        }
+    }
+    if args.execution == ExecutionMode::ThreadPerCore {
+        sharded::ensure_supported()?;
+    }
+    for directory in [&args.temp_dir, &args.source_offsets].into_iter().flatten() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let n_procs = args.processes;
+    let config = Arc::new(Config {
+        year: args.year,
+        sources: args.source.clone(),
+        mirror_url: args.mirror_url.clone(),
+        retries: args.retries,
+        retry_delay_ms: args.retry_delay_ms,
+        keywords: args.keywords.clone(),
+        fields: args.fields.clone(),
+        profile: args.profile,
+        xml_backend: args.xml_backend,
+        input_dirs: args.input_dir.clone(),
+        temp_dir: args.temp_dir.clone(),
+        source_offsets: args.source_offsets.clone(),
+        checksum_manifest: args.checksum_manifest.clone(),
+        checksum_policy: args.checksum_policy,
+        relevance_model: args.relevance_model.clone(),
+        relevance_threshold: args.relevance_threshold,
+        model_input_size: args.model_input_size,
+        seed: args.seed,
+        sample_rate: args.sample_rate,
+        shuffle: args.shuffle,
+        priority_indices: args.priority_indices.clone(),
+        pmid_versions: args.pmid_versions,
+        topics: args.topics.clone(),
+        filter_abstract: args.filter_abstract,
+        export_abstract: args.export_abstract,
+        other_abstract_language: args.other_abstract_language.clone(),
+        strip_boilerplate: args.strip_boilerplate,
+        boilerplate_patterns: args.boilerplate_patterns.clone(),
+        max_record_bytes: args.max_record_bytes,
+        oversized_records: args.oversized_records,
+        recycle_after_files: args.recycle_after_files,
+        rss_warning_mb: args.rss_warning_mb,
+    });
+    let existing_run = Manifest::load(&args.manifest);
+    // Manifests from before the outputs were tagged with the filter hash belong to untagged
+    // outputs, which have to stay visible to a run that resumes them and are not renamed. A fresh
+    // run always tags its outputs, so it never takes up outputs of another filter.
+    let untagged = args.resume
+        && matches!(&existing_run, Ok(run) if run.filter_hash.is_none() && !run.files.is_empty());
+    let mut previous_run = if args.resume {
+        existing_run?
+    } else {
+        // A fresh run starts over, but keeps what earlier runs learned about the server.
+        RunManifest {
+            download_concurrency: existing_run.ok().and_then(|m| m.download_concurrency),
+            ..RunManifest::default()
+        }
+    };
+    let filter_hash = config.filter_hash();
+    match &previous_run.filter_hash {
+        Some(previous) if args.resume && *previous != filter_hash => {
+            return Err(format!(
+                "{} was written with filter version {}, but this run uses {}. Resuming would mix \
+                 both in one corpus, use another --manifest for the new filter.",
+                args.manifest, previous, filter_hash
+            )
+            .into())
+        }
+        _ => {}
+    }
+    if untagged {
+        println!(
+            "{} was written before outputs were tagged with the filter hash, the outputs keep \
+             their untagged names",
+            args.manifest
+        );
+    } else {
+        previous_run.filter_hash = Some(filter_hash.clone());
+    }
+    let download_concurrency = previous_run
+        .download_concurrency
+        .map_or(n_procs, |learned| learned.min(n_procs));
+    let manifest = Arc::new(Manifest::new(&args.manifest, previous_run));
+    let heatmap = Arc::new(KeywordHeatmap::new(config.keywords.clone()));
+    let mut roots = vec![];
+    for input_dir in &args.input_dir {
+        let files = config::local_archives(input_dir)?
+            .into_iter()
+            .filter(|file_name| args.selects_local_archive(file_name))
+            .collect();
+        roots.push((Origin::Directory(input_dir.clone()), files));
+    }
+    if roots.is_empty() {
+        for source in &args.source {
+            let files = args
+                .file_indices(*source)
+                .into_iter()
+                .map(|index| config.file_name(index))
+                .collect();
+            roots.push((Origin::Feed(*source), files));
+        }
+    }
+    let (mut candidates, origins, duplicates) = work_queue::merge_roots(roots);
+    if !duplicates.is_empty() {
+        eprintln!(
+            "WARNING: these archives are in several feeds or input directories and are only \
+             processed from the first: {}",
+            duplicates.join(", ")
+        );
+    }
+    if config.shuffle {
+        rng::shuffle(&mut candidates, config.seed);
+    }
+    let priority_files: Vec<String> = config
+        .priority_indices
+        .iter()
+        .map(|index| config.file_name(*index))
+        .collect();
+    let outside_of_run = work_queue::prioritize(&mut candidates, &priority_files);
+    if !outside_of_run.is_empty() {
+        eprintln!(
+            "WARNING: the priority files {} are not part of this run",
+            outside_of_run.join(", ")
+        );
+    }
+    let files: Vec<String> = candidates
+        .into_iter()
+        .filter(|file_name| manifest.needs_processing(file_name))
+        .collect();
+    check_local_checksums(&args.input_dir, &files, &origins, config.checksum_policy)?;
+    let (verified_archives, corrupt_archives) = match args.verify_existing {
+        true => verify_existing(&args.input_dir, &files, &origins, &manifest).await?,
+        false => (HashSet::new(), vec![]),
+    };
+    let sink = args.output.create_sink(
+        (!untagged).then_some(filter_hash.as_str()),
+        parser::keyword_filter(&config),
+    )?;
+    let backend = xml_backend::create_backend(config.xml_backend)?;
+    let model = match &config.relevance_model {
+        Some(path) => Some(relevance_model::load_model(path, config.model_input_size)?),
+        None => None,
+    };
+    if let Some(address) = args.metrics_addr {
+        metrics::serve(address)?;
+    }
+    let run_id = run_info::new_run_id();
+    StartupBanner {
+        event: "startup",
+        run_id: &run_id,
+        version: env!("CARGO_PKG_VERSION"),
+        config_hash: config.hash(),
+        filter_hash: &filter_hash,
+        config_file: args.config.as_deref(),
+        config: &config,
+        first_file: files.first().map(|f| f.as_str()),
+        last_file: files.last().map(|f| f.as_str()),
+        file_count: files.len(),
+        sinks: vec![sink.describe()],
+    }
+    .print();
+    run_info::set_process_title(&run_info::process_title(&run_id, 0));
+    let client = args
+        .contact
+        .client_builder()
         .pool_max_idle_per_host(100) // Optimize the connection pool
         .build()?;
-    let mut logger = Logger::new(n_procs, n_files);
-    let task_counter = Arc::new(AtomicI32::new(n_files.clone() as i32));
+    let checksums = match &config.checksum_manifest {
+        Some(source) => {
+            let checksums = ChecksumManifest::load(source, &client).await?;
+            println!("Read {} checksums from {}", checksums.len(), source);
+            Some(Arc::new(checksums))
+        }
+        None => None,
+    };
+    let queued: Vec<String> = files
+        .iter()
+        .filter(|file_name| !corrupt_archives.contains(file_name))
+        .cloned()
+        .collect();
+    let release_order = match args.has_several_roots() {
+        true => ReleaseOrder::new(&queued),
+        false => ReleaseOrder::unordered(),
+    };
+    let mut context = RunContext {
+        queue: Arc::new(WorkQueue::new(queued)),
+        release_order: Arc::new(release_order),
+        origins: Arc::new(origins),
+        config: config.clone(),
+        manifest: manifest.clone(),
+        heatmap: heatmap.clone(),
+        sink: sink.clone(),
+        backend,
+        events: Arc::new(EventLog::open(args.events_file.as_deref())?),
+        model,
+        budget: Arc::new(DownloadBudget::new(
+            args.max_download_gb.map(|gb| (gb * 1e9) as u64),
+        )),
+        topics: Arc::new(TopicStats::new(&config.topics)),
+        throttle: Arc::new(DownloadThrottle::new(
+            download_concurrency,
+            n_procs,
+            args.throttle_threshold,
+        )),
+        verified_archives: Arc::new(verified_archives),
+        checksums,
+        resources: ResourceMonitor::start(config.rss_warning_mb.map(|mb| mb * 1024 * 1024)),
+        warnings: Arc::new(WarningLog::open(args.warnings_file.as_deref())?),
+    };
+    if download_concurrency < n_procs && config.input_dirs.is_empty() {
+        println!(
+            "Starting with {} downloads at a time, the server throttled earlier runs",
+            download_concurrency
+        );
+    }
+    process_files(&context, &client, &args, &run_id).await?;
+
+    if args.validate_outputs {
+        let corrupt_files = validate_outputs(&context);
+        if args.regenerate_corrupt && !corrupt_files.is_empty() {
+            for file_name in &corrupt_files {
+                sink.remove_output(file_name)?;
+            }
+            println!("Regenerating {} corrupt outputs", corrupt_files.len());
+            context.queue = Arc::new(WorkQueue::new(corrupt_files));
+            process_files(&context, &client, &args, &run_id).await?;
+            validate_outputs(&context);
+        }
+    }
+    report_downloads(&context.budget);
+    if context.budget.is_exhausted() {
+        manifest.checkpoint()?;
+    }
+    if let Some(heatmap_path) = &args.heatmap_path {
+        heatmap.write_csv(heatmap_path)?;
+    }
+    let files_without_articles = heatmap.files_without_articles();
+    if !files_without_articles.is_empty() {
+        eprintln!(
+            "WARNING: {} files contained no valid articles, check the parser: {}",
+            files_without_articles.len(),
+            files_without_articles.join(", ")
+        );
+    }
+    // Built in any case, finishing the resource monitor stops its sampling thread.
+    let summary = RunSummary {
+        run_id: &run_id,
+        config_hash: config.hash(),
+        filter_hash: &filter_hash,
+        files: manifest.tally(&files),
+        files_without_valid_articles: files_without_articles,
+        topics: context.topics.summary(),
+        warnings: context.warnings.counts(),
+        resources: context.resources.finish(),
+    };
+    if let Some(summary_path) = &args.summary_path {
+        summary.write(summary_path)?;
+    }
+    Ok(())
+}
+
+fn report_downloads(budget: &DownloadBudget) {
+    let downloaded = budget::format_gb(budget.downloaded());
+    match budget.limit() {
+        Some(limit) if budget.is_exhausted() => println!(
+            "Stopped after downloading {} of the {} budget. Run again with --resume to continue.",
+            downloaded,
+            budget::format_gb(limit)
+        ),
+        _ => println!("Downloaded {}", downloaded),
+    }
+}
+
+/// Lets the parsers work through the queue of the context until it is empty.
+async fn process_files(
+    context: &RunContext,
+    client: &reqwest::Client,
+    args: &Args,
+    run_id: &str,
+) -> Result<(), sharded::ShardError> {
+    let n_procs = args.processes;
+    let mut logger = Logger::new(
+        n_procs,
+        context.queue.len(),
+        context.config.input_dirs.is_empty(),
+        run_id.to_string(),
+        args.wide,
+    );
+    // The shards keep their topic counts to themselves until the end.
+    if !context.topics.is_empty() && args.execution == ExecutionMode::Shared {
+        logger.show_topics(context.topics.clone());
+    }
     let logger_sender = logger.get_sender();
     let mut tasks = vec![];
 
     let logger_thread = std::thread::spawn(move || logger.run());
-    for n in 0..n_procs {
-        let client = client.clone();
-        let c = logger_sender.clone();
-        let mut parser =
-            crate::parser::Parser::initialize(task_counter.clone(), &c.clone(), n as u32);
-        let handle = tokio::spawn(async move {
-            parser.try_restart(&client).await;
-        });
-        tasks.push(handle);
-    }
-    let _ = futures_util::future::join_all(tasks).await;
-    let _ = logger_sender.send(ParserMessage {
-        id: 0,
-        new_state: ParserState::Terminate,
-    });
+    let result = match args.execution {
+        ExecutionMode::Shared => {
+            for n in 0..n_procs {
+                let client = client.clone();
+                let c = logger_sender.clone();
+                let mut parser = crate::parser::Parser::initialize(context, &c, n as u32);
+                let handle = tokio::spawn(async move {
+                    parser.try_restart(&client).await;
+                });
+                tasks.push(handle);
+            }
+            let _ = futures_util::future::join_all(tasks).await;
+            Ok(())
+        }
+        ExecutionMode::ThreadPerCore => sharded::process_files(
+            context,
+            &args.contact,
+            n_procs,
+            args.cpus.as_ref(),
+            &logger_sender,
+        ),
+    };
+    parser::report(&logger_sender, 0, ParserState::Terminate);
     let _ = logger_thread.join();
+    result?;
+    context.sink.finish()
+}
+
+/// Reports the local archives that neither an md5 file nor MD5SUMS knows, so they are not
+/// processed unverified without notice. With the fail policy, the run does not start.
+fn check_local_checksums(
+    input_dirs: &[String],
+    files: &[String],
+    origins: &Origins,
+    policy: ChecksumPolicy,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut unverifiable = vec![];
+    for input_dir in input_dirs {
+        let origin = Origin::Directory(input_dir.clone());
+        for file_name in files.iter().filter(|f| *origins.get(f) == origin) {
+            if checksum::local_md5(input_dir, &format!("{}.gz", file_name))?.is_none() {
+                unverifiable.push(format!("{}/{}.gz", input_dir, file_name));
+            }
+        }
+    }
+    if unverifiable.is_empty() {
+        return Ok(());
+    }
+    if policy == ChecksumPolicy::Fail {
+        return Err(format!(
+            "these archives have no md5 file and no MD5SUMS entry: {}. Add the checksums or use \
+             --checksum-policy skip to process them unverified.",
+            unverifiable.join(", ")
+        )
+        .into());
+    }
+    eprintln!(
+        "WARNING: these archives have no md5 file and no MD5SUMS entry and are processed without \
+         verification: {}",
+        unverifiable.join(", ")
+    );
     Ok(())
 }
+
+/// Checks the local archives before the run and marks corrupt ones as failed. Returns the
+/// archives with a correct checksum, which the parsers do not hash again, and the corrupt ones.
+async fn verify_existing(
+    input_dirs: &[String],
+    files: &[String],
+    origins: &Origins,
+    manifest: &Manifest,
+) -> Result<(HashSet<String>, Vec<String>), Box<dyn std::error::Error + Send + Sync>> {
+    let started = std::time::Instant::now();
+    let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut verified = HashSet::new();
+    let mut corrupt = vec![];
+    let mut missing = 0;
+    let mut verifications = vec![];
+    for input_dir in input_dirs {
+        let origin = Origin::Directory(input_dir.clone());
+        let files_in_dir: Vec<String> = files
+            .iter()
+            .filter(|file_name| *origins.get(file_name) == origin)
+            .cloned()
+            .collect();
+        verifications
+            .extend(checksum::verify_local_archives(input_dir, &files_in_dir, parallelism).await?);
+    }
+    for (file_name, verification) in verifications {
+        match verification {
+            checksum::Verification::Correct => {
+                verified.insert(file_name);
+            }
+            checksum::Verification::Missing => missing += 1,
+            checksum::Verification::Wrong => {
+                eprintln!("Corrupt archive {}.gz: checksum does not match", file_name);
+                let outcome = FileOutcome {
+                    status: FileStatus::Failed,
+                    attempts: 0,
+                    articles: 0,
+                    error: Some("checksum does not match".to_string()),
+                };
+                manifest.record(&file_name, outcome)?;
+                corrupt.push(file_name);
+            }
+        }
+    }
+    println!(
+        "Verified {} archives in {:.1}s: {} correct, {} corrupt, {} without checksum",
+        files.len(),
+        started.elapsed().as_secs_f64(),
+        verified.len(),
+        corrupt.len(),
+        missing
+    );
+    Ok((verified, corrupt))
+}
+
+/// Reads back the outputs of all succeeded and skipped files and marks the ones with corrupt
+/// outputs as failed, so a --resume run picks them up. Returns the input files that can be
+/// regenerated.
+fn validate_outputs(context: &RunContext) -> Vec<String> {
+    let mut with_output = context.manifest.files_with_status(FileStatus::Succeeded);
+    with_output.extend(context.manifest.files_with_status(FileStatus::Skipped));
+    let corrupt = context.sink.validate(&with_output);
+    if corrupt.is_empty() {
+        println!("All outputs are valid");
+        return vec![];
+    }
+    let mut corrupt_files = vec![];
+    for output in corrupt {
+        eprintln!("Corrupt output {}: {}", output.path, output.reason);
+        if let Some(file_name) = output.file_name {
+            let outcome = FileOutcome {
+                status: FileStatus::Failed,
+                attempts: 0,
+                articles: 0,
+                error: Some(format!("corrupt output: {}", output.reason)),
+            };
+            if let Err(error) = context.manifest.record(&file_name, outcome) {
+                eprintln!("Could not update the manifest: {}", error);
+            }
+            corrupt_files.push(file_name);
+        }
+    }
+    corrupt_files
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Succeeded,
+    /// The output file was already present, so the input was not processed again.
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileOutcome {
+    pub status: FileStatus,
+    pub attempts: u32,
+    pub articles: usize,
+    pub error: Option<String>,
+}
+
+/// The number of files per status and the number of articles written from them.
+#[derive(Serialize, Default, Debug)]
+pub struct Tally {
+    pub succeeded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Files that were not reached, e.g. because the download budget ran out.
+    pub not_processed: usize,
+    pub articles: usize,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct RunManifest {
+    pub files: BTreeMap<String, FileOutcome>,
+    /// The number of concurrent downloads the server tolerated without throttling, so later runs
+    /// do not have to learn it again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_concurrency: Option<usize>,
+    /// The filter hash of the run that wrote the manifest, see Config::filter_hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_hash: Option<String>,
+}
+
+/// The manifest records the outcome of every input file of a run. It is written to disk after
+/// every update, so an interrupted run can be continued with --resume.
+pub struct Manifest {
+    path: String,
+    data: Mutex<RunManifest>,
+}
+
+impl Manifest {
+    pub fn new(path: &str, data: RunManifest) -> Self {
+        Self {
+            path: path.to_string(),
+            data: Mutex::new(data),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<RunManifest, Box<dyn std::error::Error + Send + Sync>> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Files that have neither succeeded nor been skipped in the manifest still need processing.
+    pub fn needs_processing(&self, file_name: &str) -> bool {
+        let data = self.data.lock().unwrap();
+        match data.files.get(file_name) {
+            Some(outcome) => outcome.status == FileStatus::Failed,
+            None => true,
+        }
+    }
+
+    pub fn files_with_status(&self, status: FileStatus) -> Vec<String> {
+        let data = self.data.lock().unwrap();
+        data.files
+            .iter()
+            .filter(|(_, outcome)| outcome.status == status)
+            .map(|(file_name, _)| file_name.clone())
+            .collect()
+    }
+
+    pub fn tally(&self, file_names: &[String]) -> Tally {
+        let data = self.data.lock().unwrap();
+        let mut tally = Tally::default();
+        for file_name in file_names {
+            match data.files.get(file_name) {
+                Some(outcome) => {
+                    match outcome.status {
+                        FileStatus::Succeeded => tally.succeeded += 1,
+                        FileStatus::Skipped => tally.skipped += 1,
+                        FileStatus::Failed => tally.failed += 1,
+                    }
+                    tally.articles += outcome.articles;
+                }
+                None => tally.not_processed += 1,
+            }
+        }
+        tally
+    }
+
+    pub fn set_download_concurrency(&self, concurrency: usize) -> std::io::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.download_concurrency = Some(concurrency);
+        self.write(&data)
+    }
+
+    pub fn record(&self, file_name: &str, outcome: FileOutcome) -> std::io::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.files.insert(file_name.to_string(), outcome);
+        self.write(&data)
+    }
+
+    /// Writes the manifest even if no file was recorded, so a run that stopped early can be
+    /// resumed.
+    pub fn checkpoint(&self) -> std::io::Result<()> {
+        self.write(&self.data.lock().unwrap())
+    }
+
+    fn write(&self, data: &RunManifest) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(data)?;
+        // Write to a temporary file first, so a crash never leaves a truncated manifest behind.
+        let temp_path = format!("{}.tmp", self.path);
+        std::fs::write(&temp_path, json)?;
+        std::fs::rename(&temp_path, &self.path)
+    }
+}
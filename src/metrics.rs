@@ -0,0 +1,220 @@
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// The internal channels between the parts of a run. Which of them fills up shows where the run
+/// is held back: a long logger queue means the console cannot keep up, parsers that wait to hand
+/// over to the writer mean the sink is slow, and a writer or logger that waits to receive while
+/// files are left in the work queue means the parsers, usually their downloads, are.
+#[derive(Clone, Copy, Debug)]
+pub enum Channel {
+    /// The files that no parser has taken yet. Parsers never wait for it, so only the depth and
+    /// the number of files handed out are counted.
+    WorkQueue,
+    /// The records the parsers hand to the writer of a merged output.
+    Writer,
+    /// The state updates of the parsers for the progress display.
+    Logger,
+}
+
+const CHANNELS: [Channel; 3] = [Channel::WorkQueue, Channel::Writer, Channel::Logger];
+
+/// Counters of one channel. They are process wide, so the shards of the thread-per-core mode and
+/// the writer threads of the sinks count into the same ones.
+pub struct ChannelMetrics {
+    depth: AtomicI64,
+    sent: AtomicU64,
+    received: AtomicU64,
+    send_wait_nanos: AtomicU64,
+    receive_wait_nanos: AtomicU64,
+    reply_wait_nanos: AtomicU64,
+}
+
+impl ChannelMetrics {
+    const fn new() -> Self {
+        Self {
+            depth: AtomicI64::new(0),
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            send_wait_nanos: AtomicU64::new(0),
+            receive_wait_nanos: AtomicU64::new(0),
+            reply_wait_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts messages that went into the channel after the sender waited this long for room.
+    pub fn sent(&self, messages: usize, waited: Duration) {
+        self.depth.fetch_add(messages as i64, Ordering::Relaxed);
+        self.sent.fetch_add(messages as u64, Ordering::Relaxed);
+        self.send_wait_nanos
+            .fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a message that was taken out after the receiver waited this long for it.
+    pub fn received(&self, waited: Duration) {
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.receive_wait_nanos
+            .fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Counts the time a sender waited for the receiver to handle its message, e.g. for the
+    /// writer to sync the batch it ended up in.
+    pub fn replied(&self, waited: Duration) {
+        self.reply_wait_nanos
+            .fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// A receiver may count a message before its sender does, so the depth is briefly negative.
+    fn depth(&self) -> i64 {
+        self.depth.load(Ordering::Relaxed).max(0)
+    }
+}
+
+static METRICS: [ChannelMetrics; 3] = [
+    ChannelMetrics::new(),
+    ChannelMetrics::new(),
+    ChannelMetrics::new(),
+];
+
+impl Channel {
+    pub fn metrics(self) -> &'static ChannelMetrics {
+        &METRICS[self as usize]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Channel::WorkQueue => "work_queue",
+            Channel::Writer => "writer",
+            Channel::Logger => "logger",
+        }
+    }
+}
+
+/// Serves the channel metrics in the Prometheus text format at /metrics until the process exits.
+/// Binds before returning, so a taken address fails the run at the start. The server has its own
+/// thread and runtime, because the thread-per-core mode blocks the main runtime while the shards
+/// run.
+pub fn serve(address: SocketAddr) -> std::io::Result<()> {
+    let listener = std::net::TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    println!(
+        "Serving metrics at http://{}/metrics",
+        listener.local_addr()?
+    );
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::Builder::new()
+        .name("metrics".into())
+        .spawn(move || {
+            runtime.block_on(async move {
+                let Ok(listener) = TcpListener::from_std(listener) else {
+                    return;
+                };
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(async move {
+                        // The timer enables hyper's default timeout for reading the request head,
+                        // so a client that never sends one does not keep the connection open.
+                        let _ = http1::Builder::new()
+                            .timer(TokioTimer::new())
+                            .serve_connection(TokioIo::new(stream), service_fn(respond))
+                            .await;
+                    });
+                }
+            })
+        })?;
+    Ok(())
+}
+
+async fn respond(request: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        let mut response = Response::new(Full::default());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+    let mut response = Response::new(Full::new(Bytes::from(render())));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    Ok(response)
+}
+
+/// The name, type, help text and value of a metric that is reported per channel.
+type Family = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&ChannelMetrics) -> String,
+);
+
+fn render() -> String {
+    let families: [Family; 6] = [
+        (
+            "hcse_channel_depth",
+            "gauge",
+            "Messages waiting in the channel.",
+            |m| m.depth().to_string(),
+        ),
+        (
+            "hcse_channel_sent_total",
+            "counter",
+            "Messages put into the channel.",
+            |m| m.sent.load(Ordering::Relaxed).to_string(),
+        ),
+        (
+            "hcse_channel_received_total",
+            "counter",
+            "Messages taken out of the channel.",
+            |m| m.received.load(Ordering::Relaxed).to_string(),
+        ),
+        (
+            "hcse_channel_send_wait_seconds_total",
+            "counter",
+            "Time senders were blocked because the channel was full.",
+            |m| seconds(&m.send_wait_nanos),
+        ),
+        (
+            "hcse_channel_receive_wait_seconds_total",
+            "counter",
+            "Time the receiver was blocked because the channel was empty.",
+            |m| seconds(&m.receive_wait_nanos),
+        ),
+        (
+            "hcse_channel_reply_wait_seconds_total",
+            "counter",
+            "Time senders waited for the receiver to handle their message.",
+            |m| seconds(&m.reply_wait_nanos),
+        ),
+    ];
+    let mut body = String::new();
+    for (name, kind, help, value) in families {
+        body.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for channel in CHANNELS {
+            body.push_str(&format!(
+                "{}{{channel=\"{}\"}} {}\n",
+                name,
+                channel.name(),
+                value(channel.metrics())
+            ));
+        }
+    }
+    body
+}
+
+fn seconds(nanos: &AtomicU64) -> String {
+    format!("{:.6}", nanos.load(Ordering::Relaxed) as f64 / 1e9)
+}
@@ -0,0 +1,152 @@
+use crate::article::Article;
+use clap::{Args, ValueEnum};
+use serde_json::Value;
+use std::path::Path;
+
+pub type MigrationError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The versions of the result schema. Every change to the serialized Article adds a version and
+/// a migration step from the previous one.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SchemaVersion {
+    /// Up to 1.1.1: the abstract is called `paper_abstract`.
+    V1,
+    /// The abstract is called `abstract`, metadata fields are optional.
+    V2,
+}
+
+pub const CURRENT_SCHEMA: SchemaVersion = SchemaVersion::V2;
+
+#[derive(Args, Debug)]
+pub struct MigrateArgs {
+    /// The schema version the result files were written with.
+    #[arg(long, value_enum)]
+    from: SchemaVersion,
+
+    /// The schema version to rewrite them to.
+    #[arg(long, value_enum, default_value_t = CURRENT_SCHEMA)]
+    to: SchemaVersion,
+
+    /// The directory containing the results_*.json and results_*.jsonl files.
+    directory: String,
+}
+
+/// Rewrites all json and jsonl result files in a directory in place.
+pub fn run(args: &MigrateArgs) -> Result<(), MigrationError> {
+    if args.to < args.from {
+        return Err("migrations can only upgrade to a newer schema".into());
+    }
+    if args.to != CURRENT_SCHEMA {
+        return Err(format!("only migrations to {:?} are supported", CURRENT_SCHEMA).into());
+    }
+    let mut n_files = 0;
+    for entry in std::fs::read_dir(&args.directory)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !name.starts_with("results") {
+            continue;
+        }
+        let migrated = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => migrate_json(&path, args.from)?,
+            Some("jsonl") => migrate_jsonl(&path, args.from)?,
+            _ => continue,
+        };
+        // Replace the file only once the complete new version has been written.
+        let temp_path = path.with_extension("migrating");
+        std::fs::write(&temp_path, migrated)?;
+        std::fs::rename(&temp_path, &path)?;
+        n_files += 1;
+    }
+    println!("Migrated {} files to {:?}.", n_files, args.to);
+    Ok(())
+}
+
+fn migrate_json(path: &Path, from: SchemaVersion) -> Result<String, MigrationError> {
+    let records: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let articles = records
+        .into_iter()
+        .map(|record| migrate_record(record, from))
+        .collect::<Result<Vec<Article>, MigrationError>>()?;
+    Ok(serde_json::to_string_pretty(&articles)?)
+}
+
+fn migrate_jsonl(path: &Path, from: SchemaVersion) -> Result<String, MigrationError> {
+    let mut output = String::new();
+    for line in std::fs::read_to_string(path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let article = migrate_record(serde_json::from_str(line)?, from)?;
+        output.push_str(&serde_json::to_string(&article)?);
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Applies all migration steps after `from`, then reads the record as an Article so fields that
+/// did not exist in the old schema are filled with their defaults.
+fn migrate_record(mut record: Value, from: SchemaVersion) -> Result<Article, MigrationError> {
+    if from < SchemaVersion::V2 {
+        if let Some(object) = record.as_object_mut() {
+            if let Some(paper_abstract) = object.remove("paper_abstract") {
+                object.insert("abstract".to_string(), paper_abstract);
+            }
+        }
+    }
+    Ok(serde_json::from_value(record)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn migrating_from_v1_renames_the_abstract_and_keeps_every_record() {
+        let directory = TempDir::new("migrate").unwrap();
+        let record = |pmid: &str| {
+            format!(
+                r#"{{"title":"t{0}","pmid":"{0}","doi":"d{0}","pmc":"","pii":"","paper_abstract":"a{0}"}}"#,
+                pmid
+            )
+        };
+        let json = directory.path().join("results_a.json");
+        let jsonl = directory.path().join("results_b.jsonl");
+        let other = directory.path().join("notes.json");
+        std::fs::write(&json, format!("[{},{}]", record("1"), record("2"))).unwrap();
+        std::fs::write(&jsonl, format!("{}\n\n{}\n", record("3"), record("4"))).unwrap();
+        std::fs::write(&other, "not results").unwrap();
+
+        run(&MigrateArgs {
+            from: SchemaVersion::V1,
+            to: SchemaVersion::V2,
+            directory: directory.path().to_string_lossy().to_string(),
+        })
+        .unwrap();
+
+        let articles: Vec<Article> =
+            serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        let pmids: Vec<&str> = articles.iter().map(|a| a.pmid.as_str()).collect();
+        assert_eq!(pmids, ["1", "2"]);
+        assert_eq!(articles[1].paper_abstract, "a2");
+        let lines: Vec<Value> = std::fs::read_to_string(&jsonl)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["abstract"], "a3");
+        assert!(lines[0].get("paper_abstract").is_none());
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "not results");
+    }
+
+    #[test]
+    fn migrating_to_an_older_schema_is_refused() {
+        let args = MigrateArgs {
+            from: SchemaVersion::V2,
+            to: SchemaVersion::V1,
+            directory: ".".to_string(),
+        };
+        assert!(run(&args).is_err());
+    }
+}
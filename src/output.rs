@@ -0,0 +1,1346 @@
+use crate::article::{Article, ArticleField};
+use crate::avro;
+use crate::csv;
+use crate::filter::KeywordFilter;
+use crate::writer::{BatchWriter, WriterThread};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub type SinkError = Box<dyn Error + Send + Sync>;
+
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// One pretty printed json array per input file.
+    Json,
+    /// One json object per line.
+    Jsonl,
+    Csv,
+    /// One table in a SQLite database. Requires the `sqlite` feature.
+    Sqlite,
+    /// One Avro object container file per input file, with the schema in its header.
+    Avro,
+    /// One directory per input file with pmids.txt, titles.txt and abstracts.txt, where line n
+    /// of every file belongs to the same article.
+    Columns,
+    /// Only the number of articles per publication year, journal and keyword, in one json file.
+    /// No article level data is written.
+    Aggregate,
+}
+
+/// The flags that choose the output sink, shared by all commands that write articles.
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+    /// The format of the results. sqlite requires a build with the sqlite feature.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    pub output_format: OutputFormat,
+
+    /// The directory for per-file results, or the single output file when consolidating.
+    #[arg(long)]
+    pub output_path: Option<String>,
+
+    /// Let all processes append to one jsonl or csv file instead of writing one file per input.
+    #[arg(long)]
+    pub consolidate: bool,
+
+    /// How long the writer of a consolidated or sqlite output collects records before it writes
+    /// and syncs them in one go. A crash loses at most this interval, 0 syncs as soon as the
+    /// writer is idle.
+    #[arg(long, default_value_t = 0)]
+    pub flush_interval_ms: u64,
+}
+
+impl OutputArgs {
+    /// The keywords are only used by formats that count them.
+    pub fn create_sink(
+        &self,
+        tag: Option<&str>,
+        filter: KeywordFilter,
+    ) -> Result<Arc<dyn OutputSink>, SinkError> {
+        create_sink(
+            self.output_format,
+            self.output_path.as_deref(),
+            self.consolidate,
+            Duration::from_millis(self.flush_interval_ms),
+            tag,
+            filter,
+        )
+    }
+
+    /// The optional fields the format needs in addition to the ones that were asked for.
+    pub fn required_fields(&self) -> &'static [ArticleField] {
+        match self.output_format {
+            OutputFormat::Aggregate => &[ArticleField::Date, ArticleField::Journal],
+            _ => &[],
+        }
+    }
+}
+
+/// A file name with an optional tag before the extension, e.g. `results.3fa9c2d1.jsonl`.
+fn tagged_name(stem: &str, tag: Option<&str>, extension: &str) -> String {
+    match tag {
+        Some(tag) => format!("{}.{}.{}", stem, tag, extension),
+        None => format!("{}.{}", stem, extension),
+    }
+}
+
+/// Writes the file under a `.partial` name and renames it into place once it is on disk, so a
+/// crash never leaves a truncated output that has_output_for would take for a finished one.
+fn write_atomically(path: &str, contents: &[u8]) -> std::io::Result<()> {
+    let partial = format!("{}.partial", path);
+    let mut file = File::create(&partial)?;
+    file.write_all(contents)?;
+    file.sync_data()?;
+    std::fs::rename(partial, path)
+}
+
+/// An output sink receives the filtered articles of every input file. Sinks are shared by all
+/// parsers, so implementations that write to a single destination hand their records to a
+/// WriterThread instead of writing themselves.
+pub trait OutputSink: Send + Sync {
+    fn write(&self, file_name: &str, articles: &[Article]) -> Result<(), SinkError>;
+
+    /// Whether the output for this input file already exists, so the file can be skipped.
+    fn has_output_for(&self, _file_name: &str) -> bool {
+        false
+    }
+
+    /// A short description for the startup banner, e.g. `jsonl:results.jsonl`.
+    fn describe(&self) -> String;
+
+    /// Re-reads the outputs of the given input files and reports the ones that are not valid.
+    fn validate(&self, file_names: &[String]) -> Vec<CorruptOutput>;
+
+    /// Deletes the output of an input file, so it can be generated again.
+    fn remove_output(&self, _file_name: &str) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Called when the parsers are done, before the outputs are validated.
+    fn finish(&self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// An output that could not be read back, e.g. because it was truncated on a flaky network
+/// file system.
+pub struct CorruptOutput {
+    /// The input file the output belongs to. None if all input files share the output, in which
+    /// case it cannot be regenerated per file.
+    pub file_name: Option<String>,
+    pub path: String,
+    pub reason: String,
+}
+
+impl CorruptOutput {
+    fn check(file_name: Option<&str>, path: &str, result: Result<(), String>) -> Option<Self> {
+        result.err().map(|reason| CorruptOutput {
+            file_name: file_name.map(|f| f.to_string()),
+            path: path.to_string(),
+            reason,
+        })
+    }
+}
+
+fn open_for_validation(path: &str) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| e.to_string())
+}
+
+/// Streams through a json document without building it in memory.
+fn validate_json(path: &str) -> Result<(), String> {
+    serde_json::from_reader::<_, serde::de::IgnoredAny>(open_for_validation(path)?)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn validate_jsonl(path: &str) -> Result<(), String> {
+    let mut reader = open_for_validation(path)?;
+    let mut line = vec![];
+    let mut line_number = 0;
+    loop {
+        line.clear();
+        let n = reader
+            .read_until(b'\n', &mut line)
+            .map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Ok(());
+        }
+        line_number += 1;
+        if line.last() != Some(&b'\n') {
+            return Err(format!("line {} is truncated", line_number));
+        }
+        serde_json::from_slice::<serde::de::IgnoredAny>(&line)
+            .map_err(|e| format!("line {}: {}", line_number, e))?;
+    }
+}
+
+/// Checks that every record has as many fields as the header and that no quote is left open.
+fn validate_csv(path: &str) -> Result<(), String> {
+    let reader = open_for_validation(path)?;
+    let mut expected_fields = None;
+    let mut fields = 1;
+    let mut in_quotes = false;
+    let mut record = 1;
+    for line in reader.lines() {
+        for c in line.map_err(|e| e.to_string())?.chars() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                ',' if !in_quotes => fields += 1,
+                _ => {}
+            }
+        }
+        if in_quotes {
+            // A quoted field with a line break, the record continues on the next line.
+            continue;
+        }
+        match expected_fields {
+            None => expected_fields = Some(fields),
+            Some(n) if n != fields => {
+                return Err(format!(
+                    "record {} has {} fields instead of {}",
+                    record, fields, n
+                ))
+            }
+            _ => {}
+        }
+        fields = 1;
+        record += 1;
+    }
+    if in_quotes {
+        return Err("the last record is truncated".to_string());
+    }
+    Ok(())
+}
+
+/// Creates the sink for the given format. If consolidate is set, all parsers write into the one
+/// file at output_path, otherwise output_path is the directory the per-file results go to.
+/// The tag, usually the filter hash, is added to all default file names, so outputs of
+/// different filter configurations never end up in the same file. An explicit consolidated
+/// output path is used as it is. Merged outputs are written every flush_interval.
+pub fn create_sink(
+    format: OutputFormat,
+    output_path: Option<&str>,
+    consolidate: bool,
+    flush_interval: Duration,
+    tag: Option<&str>,
+    filter: KeywordFilter,
+) -> Result<Arc<dyn OutputSink>, SinkError> {
+    let tag = tag.map(|t| t.to_string());
+    match (format, consolidate) {
+        (OutputFormat::Json, false) => Ok(Arc::new(JsonSink {
+            directory: output_path.unwrap_or(".").to_string(),
+            tag,
+        })),
+        (OutputFormat::Json, true) => {
+            Err("json output is written per file, use jsonl to consolidate".into())
+        }
+        (OutputFormat::Avro, false) => Ok(Arc::new(AvroSink {
+            directory: output_path.unwrap_or(".").to_string(),
+            tag,
+        })),
+        (OutputFormat::Avro, true) => {
+            Err("avro output is written per file, use jsonl to consolidate".into())
+        }
+        (OutputFormat::Columns, false) => Ok(Arc::new(ColumnsSink {
+            directory: output_path.unwrap_or(".").to_string(),
+            tag,
+        })),
+        (OutputFormat::Columns, true) => {
+            Err("columns output is written per file, use jsonl to consolidate".into())
+        }
+        (OutputFormat::Jsonl, false) | (OutputFormat::Csv, false) => Ok(Arc::new(PerFileSink {
+            directory: output_path.unwrap_or(".").to_string(),
+            format: LineFormat::from(format),
+            tag,
+        })),
+        (OutputFormat::Jsonl, true) | (OutputFormat::Csv, true) => {
+            let format = LineFormat::from(format);
+            let default_path = tagged_name("results", tag.as_deref(), format.extension());
+            let sink = ConsolidatedSink::open(
+                output_path.unwrap_or(&default_path),
+                format,
+                flush_interval,
+            )?;
+            Ok(Arc::new(sink))
+        }
+        // Always a single file, whether consolidated or not.
+        (OutputFormat::Aggregate, _) => {
+            let default_path = tagged_name("aggregates", tag.as_deref(), "json");
+            let sink = AggregateSink::open(output_path.unwrap_or(&default_path), filter)?;
+            Ok(Arc::new(sink))
+        }
+        (OutputFormat::Sqlite, _) => {
+            let default_path = tagged_name("results", tag.as_deref(), "sqlite");
+            create_sqlite_sink(output_path.unwrap_or(&default_path), flush_interval)
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn create_sqlite_sink(
+    path: &str,
+    flush_interval: Duration,
+) -> Result<Arc<dyn OutputSink>, SinkError> {
+    Ok(Arc::new(SqliteSink::open(path, flush_interval)?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn create_sqlite_sink(
+    _path: &str,
+    _flush_interval: Duration,
+) -> Result<Arc<dyn OutputSink>, SinkError> {
+    Err("this build does not support sqlite output, rebuild with --features sqlite".into())
+}
+
+/// The original output: `results_<input file>.json` next to each other in one directory.
+pub struct JsonSink {
+    directory: String,
+    tag: Option<String>,
+}
+
+impl JsonSink {
+    fn output_filename(&self, file_name: &str) -> String {
+        let stem = format!("{}/results_{}", self.directory, file_name);
+        tagged_name(&stem, self.tag.as_deref(), "json")
+    }
+}
+
+impl OutputSink for JsonSink {
+    fn write(&self, file_name: &str, articles: &[Article]) -> Result<(), SinkError> {
+        let articles_json = serde_json::to_string_pretty(articles)?;
+        write_atomically(&self.output_filename(file_name), articles_json.as_bytes())?;
+        Ok(())
+    }
+
+    fn has_output_for(&self, file_name: &str) -> bool {
+        Path::new(&self.output_filename(file_name)).exists()
+    }
+
+    fn describe(&self) -> String {
+        format!("json:{}", self.directory)
+    }
+
+    fn validate(&self, file_names: &[String]) -> Vec<CorruptOutput> {
+        file_names
+            .iter()
+            .filter_map(|file_name| {
+                let path = self.output_filename(file_name);
+                CorruptOutput::check(Some(file_name), &path, validate_json(&path))
+            })
+            .collect()
+    }
+
+    fn remove_output(&self, file_name: &str) -> std::io::Result<()> {
+        std::fs::remove_file(self.output_filename(file_name))
+    }
+}
+
+/// Writes `results_<input file>.avro` for every input file.
+struct AvroSink {
+    directory: String,
+    tag: Option<String>,
+}
+
+impl AvroSink {
+    fn output_filename(&self, file_name: &str) -> String {
+        let stem = format!("{}/results_{}", self.directory, file_name);
+        tagged_name(&stem, self.tag.as_deref(), "avro")
+    }
+}
+
+impl OutputSink for AvroSink {
+    fn write(&self, file_name: &str, articles: &[Article]) -> Result<(), SinkError> {
+        write_atomically(
+            &self.output_filename(file_name),
+            &avro::container(articles, file_name),
+        )?;
+        Ok(())
+    }
+
+    fn has_output_for(&self, file_name: &str) -> bool {
+        Path::new(&self.output_filename(file_name)).exists()
+    }
+
+    fn describe(&self) -> String {
+        format!("avro:{}", self.directory)
+    }
+
+    fn validate(&self, file_names: &[String]) -> Vec<CorruptOutput> {
+        file_names
+            .iter()
+            .filter_map(|file_name| {
+                let path = self.output_filename(file_name);
+                let result = std::fs::read(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| avro::validate(&data));
+                CorruptOutput::check(Some(file_name), &path, result)
+            })
+            .collect()
+    }
+
+    fn remove_output(&self, file_name: &str) -> std::io::Result<()> {
+        std::fs::remove_file(self.output_filename(file_name))
+    }
+}
+
+/// The files of the columns output in the order they are written.
+const COLUMN_FILES: [&str; 3] = ["pmids.txt", "titles.txt", "abstracts.txt"];
+
+/// Writes the pmids, titles and abstracts to `results_<input file>.columns/<column>.txt`, one line
+/// per article, for tools that read plain text line by line. Line breaks and tabs in the text,
+/// e.g. between the sections of a structured abstract, become spaces so the files stay aligned.
+/// The files are written to a `.partial` directory that is renamed when all of them are on disk,
+/// so the output directory of an input file is always complete.
+struct ColumnsSink {
+    directory: String,
+    tag: Option<String>,
+}
+
+impl ColumnsSink {
+    fn output_directory(&self, file_name: &str) -> String {
+        let stem = format!("{}/results_{}", self.directory, file_name);
+        tagged_name(&stem, self.tag.as_deref(), "columns")
+    }
+
+    fn validate_directory(directory: &str) -> Result<(), String> {
+        let mut lines = None;
+        for column in COLUMN_FILES {
+            let contents = std::fs::read_to_string(Path::new(directory).join(column))
+                .map_err(|e| format!("{}: {}", column, e))?;
+            if !contents.is_empty() && !contents.ends_with('\n') {
+                return Err(format!("{} is truncated", column));
+            }
+            let count = contents.lines().count();
+            match lines {
+                Some((first, n)) if n != count => {
+                    return Err(format!(
+                        "{} has {} lines but {} has {}",
+                        column, count, first, n
+                    ))
+                }
+                None => lines = Some((column, count)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n', '\t'], " ")
+}
+
+impl OutputSink for ColumnsSink {
+    fn write(&self, file_name: &str, articles: &[Article]) -> Result<(), SinkError> {
+        let directory = self.output_directory(file_name);
+        let partial = format!("{}.partial", directory);
+        // Left behind by an interrupted write.
+        if Path::new(&partial).exists() {
+            std::fs::remove_dir_all(&partial)?;
+        }
+        std::fs::create_dir_all(&partial)?;
+        let columns: [fn(&Article) -> &str; 3] = [|a| &a.pmid, |a| &a.title, |a| &a.paper_abstract];
+        for (column, value) in COLUMN_FILES.iter().zip(columns) {
+            let lines: String = articles
+                .iter()
+                .map(|article| format!("{}\n", single_line(value(article))))
+                .collect();
+            let mut file = File::create(Path::new(&partial).join(column))?;
+            file.write_all(lines.as_bytes())?;
+            file.sync_data()?;
+        }
+        // A directory cannot be renamed over a non-empty one, so a regenerated output replaces
+        // the old one in two steps.
+        if Path::new(&directory).exists() {
+            std::fs::remove_dir_all(&directory)?;
+        }
+        std::fs::rename(partial, directory)?;
+        Ok(())
+    }
+
+    /// The directory only appears under its name once all columns are written.
+    fn has_output_for(&self, file_name: &str) -> bool {
+        Path::new(&self.output_directory(file_name)).exists()
+    }
+
+    fn describe(&self) -> String {
+        format!("columns:{}", self.directory)
+    }
+
+    fn validate(&self, file_names: &[String]) -> Vec<CorruptOutput> {
+        file_names
+            .iter()
+            .filter_map(|file_name| {
+                let path = self.output_directory(file_name);
+                CorruptOutput::check(Some(file_name), &path, Self::validate_directory(&path))
+            })
+            .collect()
+    }
+
+    fn remove_output(&self, file_name: &str) -> std::io::Result<()> {
+        std::fs::remove_dir_all(self.output_directory(file_name))
+    }
+}
+
+/// The counts of the kept articles of one input file, or of all of them.
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct Aggregates {
+    articles: usize,
+    by_year: BTreeMap<String, usize>,
+    by_journal: BTreeMap<String, usize>,
+    by_keyword: BTreeMap<String, usize>,
+}
+
+impl Aggregates {
+    fn add(&mut self, other: &Aggregates) {
+        self.articles += other.articles;
+        for (totals, counts) in [
+            (&mut self.by_year, &other.by_year),
+            (&mut self.by_journal, &other.by_journal),
+            (&mut self.by_keyword, &other.by_keyword),
+        ] {
+            for (key, count) in counts {
+                *totals.entry(key.clone()).or_default() += count;
+            }
+        }
+    }
+}
+
+/// The document of the aggregate output. The counts are kept per input file, so a resumed or
+/// regenerated file replaces its counts instead of adding them a second time.
+#[derive(Serialize, Deserialize, Default)]
+struct AggregateDocument {
+    totals: Aggregates,
+    files: BTreeMap<String, Aggregates>,
+}
+
+/// The label of articles without a publication year or journal.
+const UNKNOWN: &str = "unknown";
+
+/// Writes only counts of the kept articles, for environments where the bibliographic records
+/// must not leave the network but summary statistics may.
+///
+/// Rewriting the whole document after every input file would cost time with the square of the
+/// number of files. Instead, the counts of every file are appended to `<path>.journal` and synced
+/// before the parser is told they are written, and the document is only rewritten when the
+/// parsers are done. The journal of an interrupted run is replayed when the output is opened
+/// again.
+struct AggregateSink {
+    path: String,
+    filter: KeywordFilter,
+    state: Mutex<AggregateState>,
+}
+
+struct AggregateState {
+    files: BTreeMap<String, Aggregates>,
+    journal: File,
+    /// Whether the journal has entries the document does not have yet.
+    pending: bool,
+}
+
+/// One line of the journal. No counts means the output of the file was removed.
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    file: String,
+    counts: Option<Aggregates>,
+}
+
+impl AggregateSink {
+    /// Continues the counts of an existing document and its journal at the path.
+    fn open(path: &str, filter: KeywordFilter) -> Result<Self, SinkError> {
+        let mut files = match std::fs::read(path) {
+            Ok(data) => {
+                serde_json::from_slice::<AggregateDocument>(&data)
+                    .map_err(|e| format!("{} is not an aggregate output: {}", path, e))?
+                    .files
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+        let journal_path = format!("{}.journal", path);
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(&journal_path)?;
+        let contents = std::fs::read_to_string(&journal_path)?;
+        // A line that was cut off by a crash was never reported as written, so it is dropped.
+        let complete = contents.rfind('\n').map_or(0, |end| end + 1);
+        journal.set_len(complete as u64)?;
+        for line in contents[..complete].lines() {
+            let entry: JournalEntry = serde_json::from_str(line)
+                .map_err(|e| format!("{} is not an aggregate journal: {}", journal_path, e))?;
+            match entry.counts {
+                Some(counts) => files.insert(entry.file, counts),
+                None => files.remove(&entry.file),
+            };
+        }
+        Ok(Self {
+            path: path.to_string(),
+            filter,
+            state: Mutex::new(AggregateState {
+                files,
+                journal,
+                pending: complete > 0,
+            }),
+        })
+    }
+
+    /// Keywords are counted like the filter matches them, in the title and the abstract it was
+    /// applied to.
+    fn count(&self, articles: &[Article]) -> Aggregates {
+        let mut counts = Aggregates {
+            articles: articles.len(),
+            ..Aggregates::default()
+        };
+        for article in articles {
+            let year = article.publication_year.map(|y| y.to_string());
+            let journal = article
+                .journal
+                .as_ref()
+                .map(|j| j.title.clone())
+                .filter(|title| !title.is_empty());
+            *counts
+                .by_year
+                .entry(year.unwrap_or_else(|| UNKNOWN.to_string()))
+                .or_default() += 1;
+            *counts
+                .by_journal
+                .entry(journal.unwrap_or_else(|| UNKNOWN.to_string()))
+                .or_default() += 1;
+        }
+        let hits = self.filter.count_hits(articles);
+        for (keyword, hits) in self.filter.keywords().iter().zip(hits) {
+            if hits > 0 {
+                counts.by_keyword.insert(keyword.clone(), hits);
+            }
+        }
+        counts
+    }
+
+    fn append(state: &mut AggregateState, entry: &JournalEntry) -> Result<(), SinkError> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        state.journal.write_all(line.as_bytes())?;
+        state.journal.sync_data()?;
+        state.pending = true;
+        Ok(())
+    }
+
+    /// Replaces the document, under another name first so it is never left half written, and
+    /// empties the journal it contains.
+    fn save(&self, state: &mut AggregateState) -> Result<(), SinkError> {
+        let mut document = AggregateDocument {
+            totals: Aggregates::default(),
+            files: state.files.clone(),
+        };
+        for counts in state.files.values() {
+            document.totals.add(counts);
+        }
+        let partial = format!("{}.partial", self.path);
+        let mut file = File::create(&partial)?;
+        file.write_all(serde_json::to_string_pretty(&document)?.as_bytes())?;
+        file.sync_data()?;
+        std::fs::rename(partial, &self.path)?;
+        state.journal.set_len(0)?;
+        state.journal.sync_data()?;
+        state.pending = false;
+        Ok(())
+    }
+}
+
+impl OutputSink for AggregateSink {
+    fn write(&self, file_name: &str, articles: &[Article]) -> Result<(), SinkError> {
+        let counts = self.count(articles);
+        let mut state = self.state.lock().unwrap();
+        let entry = JournalEntry {
+            file: file_name.to_string(),
+            counts: Some(counts.clone()),
+        };
+        Self::append(&mut state, &entry)?;
+        state.files.insert(entry.file, counts);
+        Ok(())
+    }
+
+    fn has_output_for(&self, file_name: &str) -> bool {
+        self.state.lock().unwrap().files.contains_key(file_name)
+    }
+
+    fn describe(&self) -> String {
+        format!("aggregate:{}", self.path)
+    }
+
+    fn validate(&self, _file_names: &[String]) -> Vec<CorruptOutput> {
+        let result = std::fs::read(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                serde_json::from_slice::<AggregateDocument>(&data)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            });
+        CorruptOutput::check(None, &self.path, result)
+            .into_iter()
+            .collect()
+    }
+
+    fn remove_output(&self, file_name: &str) -> std::io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let entry = JournalEntry {
+            file: file_name.to_string(),
+            counts: None,
+        };
+        Self::append(&mut state, &entry).map_err(std::io::Error::other)?;
+        state.files.remove(file_name);
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), SinkError> {
+        let mut state = self.state.lock().unwrap();
+        // A run that had nothing to do still writes the document, so there always is one.
+        if state.pending || !Path::new(&self.path).exists() {
+            self.save(&mut state)?;
+        }
+        Ok(())
+    }
+}
+
+/// The columns of the tabular formats. Lists like the authors are joined with `; `.
+const FLAT_COLUMNS: [&str; 16] = [
+    "title",
+    "pmid",
+    "pmid_version",
+    "doi",
+    "pmc",
+    "pii",
+    "abstract",
+    "authors",
+    "journal",
+    "issn",
+    "volume",
+    "issue",
+    "pages",
+    "publication_year",
+    "languages",
+    "mesh_terms",
+];
+
+/// The values of an article in the order of FLAT_COLUMNS.
+fn flat_fields(article: &Article) -> Vec<String> {
+    let authors: Vec<String> = article
+        .authors
+        .iter()
+        .map(|a| {
+            format!("{} {}", a.last_name, a.fore_name)
+                .trim()
+                .to_string()
+        })
+        .collect();
+    let journal = article.journal.clone().unwrap_or_default();
+    vec![
+        article.title.clone(),
+        article.pmid.clone(),
+        article.pmid_version.to_string(),
+        article.doi.clone(),
+        article.pmc.clone(),
+        article.pii.clone(),
+        article.paper_abstract.clone(),
+        authors.join("; "),
+        journal.title,
+        journal.issn,
+        journal.volume,
+        journal.issue,
+        journal.pages,
+        article
+            .publication_year
+            .map(|y| y.to_string())
+            .unwrap_or_default(),
+        article.languages.join("; "),
+        article.mesh_terms.join("; "),
+    ]
+}
+
+/// Formats that write one record per line and can therefore be appended to.
+#[derive(Clone, Copy)]
+enum LineFormat {
+    Jsonl,
+    Csv,
+}
+
+impl LineFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Csv => LineFormat::Csv,
+            _ => LineFormat::Jsonl,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            LineFormat::Jsonl => "jsonl",
+            LineFormat::Csv => "csv",
+        }
+    }
+
+    fn header(&self) -> Option<String> {
+        match self {
+            LineFormat::Jsonl => None,
+            LineFormat::Csv => Some(csv::row(&FLAT_COLUMNS)),
+        }
+    }
+
+    fn validate(&self, path: &str) -> Result<(), String> {
+        match self {
+            LineFormat::Jsonl => validate_jsonl(path),
+            LineFormat::Csv => validate_csv(path),
+        }
+    }
+
+    fn records(&self, articles: &[Article]) -> Result<String, SinkError> {
+        let mut output = String::new();
+        for article in articles {
+            match self {
+                LineFormat::Jsonl => {
+                    output.push_str(&serde_json::to_string(article)?);
+                    output.push('\n');
+                }
+                LineFormat::Csv => output.push_str(&csv::row(&flat_fields(article))),
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Writes `results_<input file>.<jsonl|csv>` for every input file.
+struct PerFileSink {
+    directory: String,
+    format: LineFormat,
+    tag: Option<String>,
+}
+
+impl PerFileSink {
+    fn output_filename(&self, file_name: &str) -> String {
+        let stem = format!("{}/results_{}", self.directory, file_name);
+        tagged_name(&stem, self.tag.as_deref(), self.format.extension())
+    }
+}
+
+impl OutputSink for PerFileSink {
+    fn write(&self, file_name: &str, articles: &[Article]) -> Result<(), SinkError> {
+        let mut output = self.format.header().unwrap_or_default();
+        output.push_str(&self.format.records(articles)?);
+        write_atomically(&self.output_filename(file_name), output.as_bytes())?;
+        Ok(())
+    }
+
+    fn has_output_for(&self, file_name: &str) -> bool {
+        Path::new(&self.output_filename(file_name)).exists()
+    }
+
+    fn describe(&self) -> String {
+        format!("{}:{}", self.format.extension(), self.directory)
+    }
+
+    fn validate(&self, file_names: &[String]) -> Vec<CorruptOutput> {
+        file_names
+            .iter()
+            .filter_map(|file_name| {
+                let path = self.output_filename(file_name);
+                CorruptOutput::check(Some(file_name), &path, self.format.validate(&path))
+            })
+            .collect()
+    }
+
+    fn remove_output(&self, file_name: &str) -> std::io::Result<()> {
+        std::fs::remove_file(self.output_filename(file_name))
+    }
+}
+
+/// Appends the articles of all input files to one file. The records of one input file are
+/// formatted by the parser and written in one piece by the writer thread, so they are never
+/// interleaved.
+///
+/// Once a batch is on disk, the byte range of every input file in it is appended to
+/// `<path>.segments`. Whatever lies behind the last segment was interrupted by a crash before
+/// the parsers were told it is written, so those files are not in the manifest either. It is cut
+/// off when the output is opened again, and resuming writes the files once more without leaving
+/// partial or duplicate records behind.
+struct ConsolidatedSink {
+    path: String,
+    writer: WriterThread<String>,
+    format: LineFormat,
+    /// The input files with a segment, which are not written again on a resumed run.
+    written: Mutex<HashSet<String>>,
+}
+
+/// The name that marks segments which do not belong to an input file.
+const HEADER_SEGMENT: &str = "(header)";
+const EXISTING_SEGMENT: &str = "(existing)";
+
+impl ConsolidatedSink {
+    fn open(path: &str, format: LineFormat, flush_interval: Duration) -> Result<Self, SinkError> {
+        let segments_path = format!("{}.segments", path);
+        let recorded = read_segments(&segments_path)?;
+        let committed = recorded.as_ref().map(|segments| {
+            segments
+                .iter()
+                .map(|(_, offset, length)| offset + length)
+                .max()
+                .unwrap_or(0)
+        });
+        let written = recorded
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(file_name, _, _)| file_name)
+            .collect();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let segments = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&segments_path)?;
+        // A line that was cut off by a crash is removed, so the next line does not continue it.
+        let segments_length = std::fs::read(&segments_path)?
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |last_newline| last_newline as u64 + 1);
+        segments.set_len(segments_length)?;
+        let mut writer = AppendWriter {
+            offset: file.metadata()?.len(),
+            file,
+            segments,
+            segments_length,
+        };
+        match committed {
+            Some(end) if writer.offset > end => {
+                writer.file.set_len(end)?;
+                writer.file.sync_data()?;
+                eprintln!(
+                    "Removed {} bytes of an interrupted write from the end of {}",
+                    writer.offset - end,
+                    path
+                );
+                writer.offset = end;
+            }
+            // Written before segments were recorded, all of it counts as committed.
+            None if writer.offset > 0 => {
+                writer.commit(&[(EXISTING_SEGMENT.to_string(), 0, writer.offset)])?
+            }
+            _ => {}
+        }
+        if writer.offset == 0 {
+            if let Some(header) = format.header() {
+                writer.write_batch(vec![(HEADER_SEGMENT.to_string(), header)])?;
+            }
+        }
+        Ok(Self {
+            path: path.to_string(),
+            writer: WriterThread::spawn(writer, flush_interval),
+            format,
+            written: Mutex::new(written),
+        })
+    }
+}
+
+/// The `(input file, offset, length)` lines of a segments file, None if there is none.
+fn read_segments(segments_path: &str) -> std::io::Result<Option<Vec<(String, u64, u64)>>> {
+    let contents = match std::fs::read_to_string(segments_path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    // A line that was cut off by a crash does not parse and is ignored like its segment.
+    let segments = contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let offset: u64 = fields.next()?.parse().ok()?;
+            let length: u64 = fields.next()?.parse().ok()?;
+            Some((fields.next()?.to_string(), offset, length))
+        })
+        .collect();
+    Ok(Some(segments))
+}
+
+struct AppendWriter {
+    file: File,
+    /// The length of the output, where the next segment starts.
+    offset: u64,
+    segments: File,
+    segments_length: u64,
+}
+
+impl AppendWriter {
+    /// Records segments as `<offset>\t<length>\t<input file>` lines, once they are on disk.
+    fn commit(&mut self, segments: &[(String, u64, u64)]) -> std::io::Result<()> {
+        let lines: String = segments
+            .iter()
+            .map(|(file_name, offset, length)| format!("{}\t{}\t{}\n", offset, length, file_name))
+            .collect();
+        self.segments.write_all(lines.as_bytes())?;
+        self.segments.sync_data()?;
+        self.segments_length += lines.len() as u64;
+        Ok(())
+    }
+
+    fn append(&mut self, records: &str, segments: &[(String, u64, u64)]) -> std::io::Result<()> {
+        self.file.write_all(records.as_bytes())?;
+        self.file.sync_data()?;
+        self.commit(segments)
+    }
+}
+
+impl BatchWriter for AppendWriter {
+    type Records = String;
+
+    fn write_batch(&mut self, batch: Vec<(String, String)>) -> Result<(), SinkError> {
+        let mut segments = Vec::with_capacity(batch.len());
+        let mut records = String::new();
+        let mut offset = self.offset;
+        for (file_name, file_records) in batch {
+            segments.push((file_name, offset, file_records.len() as u64));
+            offset += file_records.len() as u64;
+            records.push_str(&file_records);
+        }
+        if let Err(error) = self.append(&records, &segments) {
+            // A batch is written completely or not at all. Its files fail and are written again
+            // when they are retried, so nothing of this attempt may stay behind.
+            let _ = self.file.set_len(self.offset);
+            let _ = self.segments.set_len(self.segments_length);
+            return Err(error.into());
+        }
+        self.offset = offset;
+        Ok(())
+    }
+}
+
+impl OutputSink for ConsolidatedSink {
+    fn write(&self, file_name: &str, articles: &[Article]) -> Result<(), SinkError> {
+        let records = self.format.records(articles)?;
+        self.writer.write(file_name, records)?;
+        self.written.lock().unwrap().insert(file_name.to_string());
+        Ok(())
+    }
+
+    fn has_output_for(&self, file_name: &str) -> bool {
+        self.written.lock().unwrap().contains(file_name)
+    }
+
+    fn describe(&self) -> String {
+        format!("{}:{}", self.format.extension(), self.path)
+    }
+
+    fn validate(&self, _file_names: &[String]) -> Vec<CorruptOutput> {
+        CorruptOutput::check(None, &self.path, self.format.validate(&self.path))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Writes all articles into the `articles` table of one SQLite database.
+#[cfg(feature = "sqlite")]
+struct SqliteSink {
+    path: String,
+    writer: WriterThread<Vec<Vec<String>>>,
+    /// The input files in the `completed_files` table.
+    written: Mutex<HashSet<String>>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSink {
+    fn open(path: &str, flush_interval: Duration) -> Result<Self, SinkError> {
+        let connection = rusqlite::Connection::open(path)?;
+        let columns: Vec<String> = FLAT_COLUMNS.iter().map(|c| format!("{} TEXT", c)).collect();
+        connection.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS articles (source_file TEXT NOT NULL, {});
+             CREATE TABLE IF NOT EXISTS completed_files (file_name TEXT PRIMARY KEY);",
+            columns.join(", ")
+        ))?;
+        migrate_sqlite_schema(&connection, path)?;
+        let written = connection
+            .prepare("SELECT file_name FROM completed_files")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            path: path.to_string(),
+            writer: WriterThread::spawn(SqliteWriter { connection }, flush_interval),
+            written: Mutex::new(written),
+        })
+    }
+}
+
+/// The `user_version` of the databases this build writes. Version 1 only had the identifiers,
+/// title and abstract of an article, version 2 has all of FLAT_COLUMNS.
+#[cfg(feature = "sqlite")]
+const SQLITE_SCHEMA_VERSION: i64 = 2;
+
+/// Adds the columns an older version did not have, so a database can be written by a resumed
+/// run after an upgrade. Its existing rows keep NULL in the new columns.
+#[cfg(feature = "sqlite")]
+fn migrate_sqlite_schema(connection: &rusqlite::Connection, path: &str) -> Result<(), SinkError> {
+    let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > SQLITE_SCHEMA_VERSION {
+        return Err(format!(
+            "{} has schema version {}, this version only writes up to {}",
+            path, version, SQLITE_SCHEMA_VERSION
+        )
+        .into());
+    }
+    let existing: HashSet<String> = connection
+        .prepare("SELECT name FROM pragma_table_info('articles')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let transaction = connection.unchecked_transaction()?;
+    for column in FLAT_COLUMNS.iter().filter(|c| !existing.contains(**c)) {
+        transaction.execute_batch(&format!("ALTER TABLE articles ADD COLUMN {} TEXT;", column))?;
+    }
+    transaction.execute_batch(&format!("PRAGMA user_version = {};", SQLITE_SCHEMA_VERSION))?;
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Inserts a whole batch in one transaction, which SQLite only reports as committed once it is
+/// on disk. Rows an input file left behind before are replaced, and the file is recorded in
+/// `completed_files` by the same transaction.
+#[cfg(feature = "sqlite")]
+struct SqliteWriter {
+    connection: rusqlite::Connection,
+}
+
+#[cfg(feature = "sqlite")]
+impl BatchWriter for SqliteWriter {
+    type Records = Vec<Vec<String>>;
+
+    fn write_batch(&mut self, batch: Vec<(String, Vec<Vec<String>>)>) -> Result<(), SinkError> {
+        let placeholders: Vec<String> = (1..=FLAT_COLUMNS.len() + 1)
+            .map(|i| format!("?{}", i))
+            .collect();
+        let insert = format!(
+            "INSERT INTO articles (source_file, {}) VALUES ({})",
+            FLAT_COLUMNS.join(", "),
+            placeholders.join(", ")
+        );
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(&insert)?;
+            let mut delete =
+                transaction.prepare_cached("DELETE FROM articles WHERE source_file = ?1")?;
+            let mut complete = transaction
+                .prepare_cached("INSERT OR REPLACE INTO completed_files (file_name) VALUES (?1)")?;
+            for (file_name, rows) in batch {
+                delete.execute([&file_name])?;
+                complete.execute([&file_name])?;
+                for row in rows {
+                    let mut values = vec![file_name.clone()];
+                    values.extend(row);
+                    statement.execute(rusqlite::params_from_iter(values))?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl OutputSink for SqliteSink {
+    fn write(&self, file_name: &str, articles: &[Article]) -> Result<(), SinkError> {
+        let rows = articles.iter().map(flat_fields).collect();
+        self.writer.write(file_name, rows)?;
+        self.written.lock().unwrap().insert(file_name.to_string());
+        Ok(())
+    }
+
+    fn has_output_for(&self, file_name: &str) -> bool {
+        self.written.lock().unwrap().contains(file_name)
+    }
+
+    fn describe(&self) -> String {
+        format!("sqlite:{}", self.path)
+    }
+
+    fn validate(&self, _file_names: &[String]) -> Vec<CorruptOutput> {
+        let result = rusqlite::Connection::open(&self.path)
+            .and_then(|connection| {
+                connection.query_row("PRAGMA integrity_check", [], |row| row.get::<_, String>(0))
+            })
+            .map_err(|e| e.to_string())
+            .and_then(|status| match status.as_str() {
+                "ok" => Ok(()),
+                _ => Err(status),
+            });
+        CorruptOutput::check(None, &self.path, result)
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn article(pmid: &str) -> Article {
+        Article {
+            title: format!("cancer study {}", pmid),
+            pmid: pmid.to_string(),
+            doi: format!("10.1/{}", pmid),
+            paper_abstract: "about cancer".to_string(),
+            ..Article::new()
+        }
+    }
+
+    fn append_to(path: &Path, contents: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+    }
+
+    /// The pmids of the records in a consolidated jsonl output.
+    fn consolidated_pmids(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Article>(line).unwrap().pmid)
+            .collect()
+    }
+
+    #[test]
+    fn consolidated_output_drops_an_interrupted_write_when_it_is_reopened() {
+        let directory = TempDir::new("consolidated").unwrap();
+        let path = directory.path().join("results.jsonl");
+        let path_name = path.to_str().unwrap();
+        let sink = ConsolidatedSink::open(path_name, LineFormat::Jsonl, Duration::ZERO).unwrap();
+        sink.write("a.xml", &[article("1"), article("2")]).unwrap();
+        sink.write("b.xml", &[article("3")]).unwrap();
+        drop(sink);
+        // A crash after the records of c.xml were on disk but before their segment was, and in
+        // the middle of the records and the segment line of d.xml.
+        let records = LineFormat::Jsonl.records(&[article("4")]).unwrap();
+        append_to(&path, &records);
+        append_to(&path, "{\"title\":\"cancer");
+        append_to(&directory.path().join("results.jsonl.segments"), "120\t4");
+
+        let sink = ConsolidatedSink::open(path_name, LineFormat::Jsonl, Duration::ZERO).unwrap();
+        assert!(sink.has_output_for("a.xml") && sink.has_output_for("b.xml"));
+        assert!(!sink.has_output_for("c.xml") && !sink.has_output_for("d.xml"));
+        sink.write("c.xml", &[article("4")]).unwrap();
+        sink.write("d.xml", &[article("5")]).unwrap();
+        drop(sink);
+
+        assert_eq!(consolidated_pmids(&path), ["1", "2", "3", "4", "5"]);
+        let sink = ConsolidatedSink::open(path_name, LineFormat::Jsonl, Duration::ZERO).unwrap();
+        assert!(["a.xml", "b.xml", "c.xml", "d.xml"]
+            .iter()
+            .all(|f| sink.has_output_for(f)));
+    }
+
+    fn aggregates(path: &Path) -> AggregateDocument {
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn aggregate_output_replays_the_journal_of_an_interrupted_run() {
+        let directory = TempDir::new("aggregate").unwrap();
+        let path = directory.path().join("aggregates.json");
+        let path_name = path.to_str().unwrap();
+        let filter = || KeywordFilter::new(vec!["cancer".to_string(), "tumor".to_string()]);
+        let sink = AggregateSink::open(path_name, filter()).unwrap();
+        sink.write("a.xml", &[article("1"), article("2")]).unwrap();
+        sink.write("b.xml", &[article("3")]).unwrap();
+        // The run is killed before finish, and in the middle of the journal line of c.xml.
+        drop(sink);
+        let journal = directory.path().join("aggregates.json.journal");
+        append_to(&journal, "{\"file\":\"c.xml\",\"cou");
+
+        let sink = AggregateSink::open(path_name, filter()).unwrap();
+        assert!(sink.has_output_for("a.xml") && sink.has_output_for("b.xml"));
+        assert!(!sink.has_output_for("c.xml"));
+        sink.write("c.xml", &[article("4")]).unwrap();
+        sink.finish().unwrap();
+        drop(sink);
+
+        let document = aggregates(&path);
+        assert_eq!(document.totals.articles, 4);
+        assert_eq!(document.totals.by_keyword["cancer"], 4);
+        assert!(!document.totals.by_keyword.contains_key("tumor"));
+        assert_eq!(document.files.len(), 3);
+        assert_eq!(std::fs::metadata(&journal).unwrap().len(), 0);
+
+        // A regenerated file replaces its counts instead of adding to them.
+        let sink = AggregateSink::open(path_name, filter()).unwrap();
+        sink.write("a.xml", &[article("1")]).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(aggregates(&path).totals.articles, 3);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_output_migrates_an_older_database_and_keeps_its_rows() {
+        let directory = TempDir::new("sqlite").unwrap();
+        let path = directory.path().join("results.sqlite");
+        let path_name = path.to_str().unwrap();
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE articles (source_file TEXT NOT NULL, title TEXT, pmid TEXT,
+                     doi TEXT, pmc TEXT, pii TEXT, abstract TEXT);
+                 CREATE TABLE completed_files (file_name TEXT PRIMARY KEY);
+                 INSERT INTO articles VALUES ('a.xml', 'old', '1', '10.1/1', '', '', 'text');
+                 INSERT INTO completed_files VALUES ('a.xml');
+                 PRAGMA user_version = 1;",
+            )
+            .unwrap();
+        drop(connection);
+
+        let sink = SqliteSink::open(path_name, Duration::ZERO).unwrap();
+        assert!(sink.has_output_for("a.xml"));
+        sink.write("b.xml", &[article("2")]).unwrap();
+        drop(sink);
+
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let version: i64 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SQLITE_SCHEMA_VERSION);
+        let rows: Vec<(String, String, Option<String>)> = connection
+            .prepare("SELECT source_file, title, journal FROM articles ORDER BY pmid")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                ("a.xml".to_string(), "old".to_string(), None),
+                (
+                    "b.xml".to_string(),
+                    "cancer study 2".to_string(),
+                    Some(String::new())
+                ),
+            ]
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_output_refuses_a_newer_database() {
+        let directory = TempDir::new("sqlite").unwrap();
+        let path = directory.path().join("results.sqlite");
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(&format!(
+                "PRAGMA user_version = {};",
+                SQLITE_SCHEMA_VERSION + 1
+            ))
+            .unwrap();
+        assert!(SqliteSink::open(path.to_str().unwrap(), Duration::ZERO).is_err());
+    }
+}
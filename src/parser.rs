@@ -1,277 +1,916 @@
 use crate::article::*;
+use crate::boilerplate::BoilerplateFilter;
+use crate::budget::DownloadBudget;
+use crate::checksum::{self, ChecksumManifest, ChecksumPolicy};
+use crate::config::Config;
+use crate::events::{Event, EventLog};
+use crate::filter::KeywordFilter;
+use crate::heatmap::KeywordHeatmap;
+use crate::manifest::{FileOutcome, FileStatus, Manifest};
+use crate::metrics::Channel;
+use crate::output::{OutputSink, SinkError};
+use crate::relevance_model::{ModelError, RelevanceModel};
+use crate::resources::{ResourceMonitor, Stage};
+use crate::rng;
+use crate::source_index;
+use crate::throttle::DownloadThrottle;
+use crate::topics::TopicStats;
+use crate::warnings::WarningLog;
+use crate::work_dir::WorkDir;
+use crate::work_queue::{Origin, Origins, ReleaseOrder, WorkQueue};
+use crate::xml_backend::{BackendError, ParseWarning, XmlBackend};
 use async_compression::tokio::bufread::GzipDecoder;
-use tokio::fs;
-use core::fmt;
-use file_integrity::hash_file;
 use reqwest::Client;
-use roxmltree::{Node, ParsingOptions};
-use std::sync::atomic::*;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::mpsc::Sender;
 use std::sync::Arc;
-use std::{path::Path, sync::mpsc::Sender};
-use tempdir::TempDir;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::fs;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
-use tokio::io::BufReader;
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+/// How much is decompressed at a time, between two progress reports at most.
+const EXTRACT_CHUNK_SIZE: usize = 1 << 20;
+/// The longest pause before a retry, however many retries and whatever first delay were asked
+/// for.
+const MAX_RETRY_DELAY_MS: u64 = 10 * 60 * 1000;
 
+#[derive(Clone, Copy, Debug)]
 pub enum ParserState {
     Restarting,
     Waiting,
-    Downloading(u8),
+    Retrying(u32),
+    Downloading(Progress),
     CheckMd5,
-    Extracting(u8),
-    Processing(u8),
+    ChecksumMissing,
+    Extracting(Progress),
+    Processing(Progress),
     WritingFile,
     FinishedInputFile(usize),
     Done,
     ErrorDownloadFailed,
     ErrorChecksumWrong,
+    /// The archive has no checksum and --checksum-policy is fail.
+    ErrorChecksumMissing,
     ErrorExtractionFailed,
     ErrorParsingFailed,
+    ErrorFilteringFailed,
     ErrorWritingFailed,
     ErrorDeleting,
+    /// The file was parsed, but contained no valid article at all.
+    NoValidArticles,
+    /// This many records were above --max-record-bytes and were truncated or skipped.
+    OversizedRecords(usize),
+    /// Sent once per file for every kind of warning its records caused.
+    Warning(WarningKind),
+    /// The server throttled the downloads, they continue with at most this many at a time.
+    ConcurrencyReduced(usize),
+    /// The server accepted the downloads again, they continue with up to this many at a time.
+    ConcurrencyRaised(usize),
+    /// The download budget of the run is used up, the file is left for a later run.
+    BudgetExhausted,
     Terminate,
 }
 
+/// How far a stage of a file has come: the percentage and the amount done so far, which is bytes
+/// for downloads and extraction and records for processing. The logger derives the throughput
+/// of every parser from the amounts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Progress {
+    pub percentage: u8,
+    pub amount: u64,
+}
+
+impl Progress {
+    pub fn new(percentage: u8, amount: u64) -> Self {
+        Self { percentage, amount }
+    }
+}
+
+impl ParserState {
+    /// Errors that may go away on their own, e.g. because of a flaky network connection.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ParserState::ErrorDownloadFailed
+                | ParserState::ErrorChecksumWrong
+                | ParserState::ErrorExtractionFailed
+                | ParserState::ErrorParsingFailed
+        )
+    }
+}
+
+/// The error state a stage ended in, together with the underlying reason.
+struct StageFailure {
+    state: ParserState,
+    reason: String,
+}
+
+impl StageFailure {
+    fn from<E: Display>(state: ParserState) -> impl FnOnce(E) -> StageFailure {
+        move |error| StageFailure {
+            state,
+            reason: error.to_string(),
+        }
+    }
+}
+
 pub struct ParserMessage {
     pub id: u32,
     pub new_state: ParserState,
 }
 
+/// Sends a state to the logger and counts it in the metrics of the logger channel.
+pub fn report(sender: &Sender<ParserMessage>, id: u32, new_state: ParserState) {
+    if sender.send(ParserMessage { id, new_state }).is_ok() {
+        Channel::Logger.metrics().sent(1, Duration::ZERO);
+    }
+}
+
+/// Everything the parsers of one run share.
+#[derive(Clone)]
+pub struct RunContext {
+    pub queue: Arc<WorkQueue>,
+    pub release_order: Arc<ReleaseOrder>,
+    pub origins: Arc<Origins>,
+    pub config: Arc<Config>,
+    pub manifest: Arc<Manifest>,
+    pub heatmap: Arc<KeywordHeatmap>,
+    pub sink: Arc<dyn OutputSink>,
+    pub backend: Arc<dyn XmlBackend>,
+    pub events: Arc<EventLog>,
+    pub model: Option<Arc<dyn RelevanceModel>>,
+    pub budget: Arc<DownloadBudget>,
+    pub topics: Arc<TopicStats>,
+    pub throttle: Arc<DownloadThrottle>,
+    /// Local archives whose checksum was already verified by --verify-existing.
+    pub verified_archives: Arc<HashSet<String>>,
+    pub checksums: Option<Arc<ChecksumManifest>>,
+    pub resources: Arc<ResourceMonitor>,
+    pub warnings: Arc<WarningLog>,
+}
+
 pub struct Parser {
     id: u32,
+    file_name: String,
     download_url: String,
     local_download_filename: String,
     md5_file_name: String,
     extracted_filename: String,
     article_data: Vec<Article>,
-    output_filename: String,
+    sink: Arc<dyn OutputSink>,
     sender: Sender<ParserMessage>,
-    counter_arc: Arc<AtomicI32>,
-    temp_dir: String,
+    queue: Arc<WorkQueue>,
+    release_order: Arc<ReleaseOrder>,
+    origins: Arc<Origins>,
+    /// The input directory of the current file, None if it is downloaded.
+    input_dir: Option<String>,
+    config: Arc<Config>,
+    manifest: Arc<Manifest>,
+    filter: KeywordFilter,
+    boilerplate: Option<BoilerplateFilter>,
+    extraction: Extraction,
+    heatmap: Arc<KeywordHeatmap>,
+    backend: Arc<dyn XmlBackend>,
+    events: Arc<EventLog>,
+    model: Option<Arc<dyn RelevanceModel>>,
+    budget: Arc<DownloadBudget>,
+    topics: Arc<TopicStats>,
+    throttle: Arc<DownloadThrottle>,
+    verified_archives: Arc<HashSet<String>>,
+    checksums: Option<Arc<ChecksumManifest>>,
+    resources: Arc<ResourceMonitor>,
+    warnings: Arc<WarningLog>,
+    work_dir: WorkDir,
+    /// The files processed since the parser was created or last recycled.
+    files_since_recycle: usize,
 }
 
 impl Parser {
     pub fn initialize(
-        arc: Arc<AtomicI32>,
+        context: &RunContext,
         reporting_channel: &Sender<ParserMessage>,
         id: u32,
     ) -> Self {
-        let id_string: &String = &format!("dir{}", id);
-        let dir = TempDir::new(id_string).unwrap();
-        let temp_dir = dir.path().to_string_lossy().replace(".", "");
+        let work_dir = create_work_dir(&context.config, id).unwrap();
         Parser {
+            file_name: String::new(),
             download_url: String::new(),
             local_download_filename: String::new(),
             md5_file_name: String::new(),
             extracted_filename: String::new(),
             article_data: vec![],
-            output_filename: String::new(),
-            counter_arc: arc,
-            temp_dir,
+            sink: context.sink.clone(),
+            queue: context.queue.clone(),
+            release_order: context.release_order.clone(),
+            origins: context.origins.clone(),
+            input_dir: None,
+            config: context.config.clone(),
+            manifest: context.manifest.clone(),
+            filter: keyword_filter(&context.config),
+            boilerplate: BoilerplateFilter::new(
+                context.config.strip_boilerplate,
+                &context.config.boilerplate_patterns,
+            ),
+            extraction: context.config.extraction(),
+            heatmap: context.heatmap.clone(),
+            backend: context.backend.clone(),
+            events: context.events.clone(),
+            model: context.model.clone(),
+            budget: context.budget.clone(),
+            topics: context.topics.clone(),
+            throttle: context.throttle.clone(),
+            verified_archives: context.verified_archives.clone(),
+            checksums: context.checksums.clone(),
+            resources: context.resources.clone(),
+            warnings: context.warnings.clone(),
+            work_dir,
+            files_since_recycle: 0,
             sender: reporting_channel.clone(),
             id,
         }
     }
 
     pub async fn try_restart(&mut self, client: &Client) {
-        loop {
-            self.counter_arc
-                .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
-            let counter_value = self.counter_arc.load(std::sync::atomic::Ordering::SeqCst);
-            if counter_value < 0 {
-                self.report_state(ParserState::Done);
-                return;
-            } else {
-                self.reinit_for_index(counter_value as u32, client).await;
+        while !self.budget.is_exhausted() {
+            let Some(fname) = self.queue.next_file() else {
+                break;
+            };
+            self.reinit_for_file(&fname, client).await;
+            self.files_since_recycle += 1;
+            let recycle_after = self.config.recycle_after_files;
+            if recycle_after > 0 && self.files_since_recycle >= recycle_after {
+                self.recycle();
             }
         }
+        // With the thread-per-core mode, the files left in the queue of this parser are not taken
+        // by any other one, which would otherwise wait for them forever.
+        self.release_order.finish_remaining(&self.queue);
+        self.report_state(ParserState::Done);
+    }
+
+    /// Replaces what the parser keeps from one file to the next with fresh state, and its work
+    /// directory with a new one. Whatever a dependency leaked into the old state is freed with it.
+    fn recycle(&mut self) {
+        match create_work_dir(&self.config, self.id) {
+            // The old directory is removed when it is dropped.
+            Ok(work_dir) => self.work_dir = work_dir,
+            Err(error) => eprintln!(
+                "Could not create a new work directory for parser {}, keeping the old one: {}",
+                self.id, error
+            ),
+        }
+        self.filter = keyword_filter(&self.config);
+        self.boilerplate = BoilerplateFilter::new(
+            self.config.strip_boilerplate,
+            &self.config.boilerplate_patterns,
+        );
+        self.extraction = self.config.extraction();
+        self.article_data = Vec::new();
+        self.emit(Event::Recycled {
+            worker: self.id,
+            files: self.files_since_recycle,
+        });
+        self.files_since_recycle = 0;
     }
 
-    async fn reinit_for_index(&mut self, index: u32, client: &Client) {
-        let _ = tokio::fs::create_dir(&self.temp_dir.clone()).await;
-        let fname = format!("pubmed24n{:0>4}.xml", index);
+    async fn reinit_for_file(&mut self, fname: &str, client: &Client) {
         self.report_state(ParserState::Restarting);
-        self.download_url = format!("https://ftp.ncbi.nlm.nih.gov/pubmed/baseline/{}.gz", fname);
-        self.local_download_filename = format!("{}/{}.gz", &self.temp_dir, fname).to_string();
-        self.md5_file_name = format!("{}/{}.gz.md5", &self.temp_dir, fname).to_string();
-        self.extracted_filename = format!("{}/{}", &self.temp_dir, fname).to_string();
+        self.file_name = fname.to_string();
+        (self.download_url, self.input_dir) = match self.origins.get(fname) {
+            Origin::Feed(source) => (self.config.download_url(*source, fname), None),
+            Origin::Directory(input_dir) => (String::new(), Some(input_dir.clone())),
+        };
+        self.local_download_filename = match &self.input_dir {
+            Some(input_dir) => format!("{}/{}.gz", input_dir, fname),
+            None => self.work_dir.file(&format!("{}.gz", fname)),
+        };
+        self.md5_file_name = self.work_dir.file(&format!("{}.gz.md5", fname));
+        self.extracted_filename = self.work_dir.file(fname);
         self.article_data = vec![];
-        self.output_filename = format!("results_{}.json", fname).to_string();
         self.run(client).await;
+        self.release_order.finish(fname);
     }
 
     pub async fn run(&mut self, client: &Client) {
         let is_already_parsed_locally = self.check_if_file_is_present();
         if is_already_parsed_locally {
             self.report_state(ParserState::FinishedInputFile(0));
+            self.emit(Event::Skip {
+                file: &self.file_name,
+                worker: self.id,
+                reason: "output already present",
+            });
+            self.record_outcome(FileStatus::Skipped, 0, 0, None);
             return;
         }
-        let download_worked = self.download(client).await;
-        if download_worked.is_err() {
-            self.report_state(ParserState::ErrorDownloadFailed);
-            return;
+        self.emit(Event::FileStarted {
+            file: &self.file_name,
+            worker: self.id,
+        });
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.run_once(client).await {
+                Ok(n_articles) => {
+                    self.record_outcome(FileStatus::Succeeded, attempt, n_articles, None);
+                    break;
+                }
+                Err(failure) if matches!(failure.state, ParserState::BudgetExhausted) => {
+                    // Not recorded in the manifest, so --resume picks the file up again.
+                    self.report_state(failure.state);
+                    self.emit(Event::Skip {
+                        file: &self.file_name,
+                        worker: self.id,
+                        reason: "download budget exhausted",
+                    });
+                    break;
+                }
+                Err(failure) => {
+                    self.report_state(failure.state);
+                    self.emit(Event::Error {
+                        file: &self.file_name,
+                        worker: self.id,
+                        state: format!("{:?}", failure.state),
+                        reason: &failure.reason,
+                    });
+                    if !failure.state.is_transient() || attempt > self.config.retries {
+                        self.record_outcome(FileStatus::Failed, attempt, 0, Some(failure.reason));
+                        break;
+                    }
+                    self.report_state(ParserState::Retrying(attempt));
+                    let backoff = retry_delay_ms(self.config.retry_delay_ms, attempt);
+                    self.emit(Event::Retry {
+                        file: &self.file_name,
+                        worker: self.id,
+                        attempt,
+                        delay_ms: backoff,
+                    });
+                    tokio::time::sleep(Duration::from_millis(backoff)).await;
+                }
+            }
         }
-        let is_checksum_correct = self.check_md5().await;
-        if is_checksum_correct.is_err() {
-            self.report_state(ParserState::ErrorChecksumWrong);
-            return;
+        let delete_worked = self.delete_artifacts().await;
+        if delete_worked.is_err() {
+            self.report_state(ParserState::ErrorDeleting);
         }
-        let extracting_status = self.extract().await;
-        if extracting_status.is_err() {
-            self.report_state(ParserState::ErrorExtractionFailed);
-            return;
+    }
+
+    /// Runs all stages for the current file once. Returns the number of articles written.
+    async fn run_once(&mut self, client: &Client) -> Result<usize, StageFailure> {
+        self.article_data = vec![];
+        self.ensure_checksum_known()
+            .map_err(StageFailure::from(ParserState::ErrorChecksumMissing))?;
+        if self.input_dir.is_none() {
+            let _permit = self.throttle.acquire().await;
+            // Started after the wait for a slot, so the download time is only the transfer.
+            let started = Instant::now();
+            let _stage = self.resources.enter(Stage::Download);
+            self.download(client).await.map_err(|error| {
+                let state = match self.budget.is_exhausted() {
+                    true => ParserState::BudgetExhausted,
+                    false => ParserState::ErrorDownloadFailed,
+                };
+                StageFailure::from(state)(error)
+            })?;
+            self.stage_finished("download", started);
+            self.accepted();
         }
-        let processing_state = self.process().await;
-        if processing_state.is_err() {
-            self.report_state(ParserState::ErrorParsingFailed);
+        let started = Instant::now();
+        let stage = self.resources.enter(Stage::Checksum);
+        let is_checksum_correct = self
+            .check_md5(client)
+            .await
+            .map_err(StageFailure::from(ParserState::ErrorChecksumWrong))?;
+        if !is_checksum_correct {
+            return Err(StageFailure {
+                state: ParserState::ErrorChecksumWrong,
+                reason: "checksum does not match".to_string(),
+            });
         }
-        self.filter_articles();
-        let write_putput_worked = self.write_output().await;
-        if !write_putput_worked {
-            self.report_state(ParserState::ErrorWritingFailed);
+        self.stage_finished("checksum", started);
+        drop(stage);
+        let started = Instant::now();
+        let stage = self.resources.enter(Stage::Extract);
+        self.extract()
+            .await
+            .map_err(StageFailure::from(ParserState::ErrorExtractionFailed))?;
+        self.stage_finished("extract", started);
+        drop(stage);
+        let started = Instant::now();
+        let stage = self.resources.enter(Stage::Process);
+        let n_parsed = self
+            .process()
+            .await
+            .map_err(StageFailure::from(ParserState::ErrorParsingFailed))?;
+        self.stage_finished("process", started);
+        drop(stage);
+        if n_parsed == 0 {
+            // Every real PubMed file has valid articles, none at all usually means the parser no
+            // longer understands the format. An empty result after filtering is normal.
+            self.report_state(ParserState::NoValidArticles);
+            self.emit(Event::Warning {
+                file: &self.file_name,
+                worker: self.id,
+                reason: "the file contains no valid articles",
+            });
         }
-        let delete_worked = self.delete_artifacts().await;
-        if delete_worked.is_err() {
-            self.report_state(ParserState::ErrorDeleting);
+        let started = Instant::now();
+        let stage = self.resources.enter(Stage::Filter);
+        self.filter_articles()
+            .map_err(StageFailure::from(ParserState::ErrorFilteringFailed))?;
+        self.stage_finished("filter", started);
+        drop(stage);
+        let started = Instant::now();
+        let _stage = self.resources.enter(Stage::Write);
+        self.write_output()
+            .await
+            .map_err(StageFailure::from(ParserState::ErrorWritingFailed))?;
+        self.stage_finished("write", started);
+        Ok(self.article_data.len())
+    }
+
+    fn emit(&self, event: Event) {
+        self.events.emit(event);
+    }
+
+    fn stage_finished(&self, stage: &str, started: Instant) {
+        self.emit(Event::StageFinished {
+            file: &self.file_name,
+            worker: self.id,
+            stage,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    }
+
+    fn record_outcome(
+        &self,
+        status: FileStatus,
+        attempts: u32,
+        articles: usize,
+        error: Option<String>,
+    ) {
+        self.emit(Event::FileFinished {
+            file: &self.file_name,
+            worker: self.id,
+            status,
+            articles,
+        });
+        let outcome = FileOutcome {
+            status,
+            attempts,
+            articles,
+            error,
+        };
+        if self.manifest.record(&self.file_name, outcome).is_err() {
+            self.report_state(ParserState::ErrorWritingFailed);
         }
     }
 
     async fn delete_artifacts(&self) -> Result<bool, Box<dyn std::error::Error>> {
-        fs::remove_file(&self.local_download_filename).await?;
-        fs::remove_file(&self.extracted_filename).await?;
-
+        let mut artifacts = vec![&self.md5_file_name, &self.extracted_filename];
+        // Archives from the input directory belong to the user and are never deleted.
+        if self.input_dir.is_none() {
+            artifacts.push(&self.local_download_filename);
+        }
+        for artifact in artifacts {
+            if Path::new(artifact).exists() {
+                fs::remove_file(artifact).await?;
+            }
+        }
         Ok(true)
     }
 
+    /// An archive whose index is missing is processed again, it may have been interrupted
+    /// between writing the output and committing the index.
     fn check_if_file_is_present(&self) -> bool {
-        Path::new(&self.output_filename).exists()
+        let indexed = match &self.config.source_offsets {
+            Some(directory) => source_index::index_path(directory, &self.file_name).exists(),
+            None => true,
+        };
+        indexed && self.sink.has_output_for(&self.file_name)
     }
 
     fn report_state(&self, state: ParserState) {
-        let _ = self.sender.send(ParserMessage {
-            id: self.id,
-            new_state: state,
-        });
+        report(&self.sender, self.id, state);
     }
 
     async fn download(
         &self,
         client: &Client,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let mut response = client.get(&self.download_url).send().await?;
+        let response = client.get(&self.download_url).send().await?;
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            self.throttled();
+        }
+        let mut response = response.error_for_status()?;
         let mut dest_file = File::create(&self.local_download_filename).await?;
         let total_download_size = response.content_length().unwrap_or(0);
 
         let mut processed_data = 0;
         let mut last_reported_percentage: u8 = 0;
-        let _ = self.sender.send(ParserMessage {
-            id: self.id,
-            new_state: ParserState::Downloading(0),
-        });
+        self.report_state(ParserState::Downloading(Progress::default()));
         while let Some(chunk) = response.chunk().await? {
             dest_file.write_all(&chunk).await?;
-            processed_data = processed_data + chunk.len();
-            let new_percentage: f32 =
-                100 as f32 * processed_data as f32 / total_download_size as f32;
+            self.resources
+                .add_network(Stage::Download, chunk.len() as u64);
+            if !self.budget.add(chunk.len() as u64) {
+                return Err("the download budget is exhausted".into());
+            }
+            processed_data += chunk.len();
+            let new_percentage: f32 = 100_f32 * processed_data as f32 / total_download_size as f32;
             if new_percentage.floor() > last_reported_percentage as f32 {
                 last_reported_percentage = new_percentage.floor() as u8;
-                self.report_state(ParserState::Downloading(last_reported_percentage));
+                self.report_state(ParserState::Downloading(Progress::new(
+                    last_reported_percentage,
+                    processed_data as u64,
+                )));
             }
         }
-        self.report_state(ParserState::Downloading(100));
+        // tokio writes in the background, without a flush the checksum may see a partial file.
+        dest_file.flush().await?;
+        self.report_state(ParserState::Downloading(Progress::new(
+            100,
+            processed_data as u64,
+        )));
         Ok(())
     }
 
-    async fn check_md5(&self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    /// Raises the download concurrency again after a series of downloads without throttling.
+    fn accepted(&self) {
+        if let Some(limit) = self.throttle.record_accepted() {
+            self.report_state(ParserState::ConcurrencyRaised(limit));
+            self.emit(Event::ConcurrencyRaised {
+                file: &self.file_name,
+                worker: self.id,
+                concurrency: limit,
+            });
+            if self.manifest.set_download_concurrency(limit).is_err() {
+                self.report_state(ParserState::ErrorWritingFailed);
+            }
+        }
+    }
+
+    /// Lowers the download concurrency if the server keeps throttling and remembers the new
+    /// limit in the manifest.
+    fn throttled(&self) {
+        if let Some(limit) = self.throttle.record_throttled() {
+            self.report_state(ParserState::ConcurrencyReduced(limit));
+            self.emit(Event::ConcurrencyReduced {
+                file: &self.file_name,
+                worker: self.id,
+                concurrency: limit,
+            });
+            if self.manifest.set_download_concurrency(limit).is_err() {
+                self.report_state(ParserState::ErrorWritingFailed);
+            }
+        }
+    }
+
+    async fn check_md5(
+        &self,
+        client: &Client,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         self.report_state(ParserState::CheckMd5);
-        let client = Client::new();
+        if self.verified_archives.contains(&self.file_name) {
+            return Ok(true);
+        }
+        let expected_checksum = match &self.input_dir {
+            Some(input_dir) => {
+                let archive_name = format!("{}.gz", self.file_name);
+                match checksum::local_md5(input_dir, &archive_name)? {
+                    Some(checksum) => checksum,
+                    None => {
+                        self.report_state(ParserState::ChecksumMissing);
+                        return Ok(true);
+                    }
+                }
+            }
+            None => match self.expected_from_manifest() {
+                Some(checksum) => checksum,
+                None if self.checksums.is_some()
+                    && self.config.checksum_policy == ChecksumPolicy::Skip =>
+                {
+                    self.report_state(ParserState::ChecksumMissing);
+                    return Ok(true);
+                }
+                None => self.download_md5(client).await?,
+            },
+        };
+        let checksum_from_file =
+            checksum::md5_of_file(self.local_download_filename.clone()).await?;
+        Ok(expected_checksum.eq_ignore_ascii_case(&checksum_from_file))
+    }
+
+    /// The checksum from --checksum-manifest. None if there is no manifest or the archive is
+    /// missing from it.
+    fn expected_from_manifest(&self) -> Option<String> {
+        let checksums = self.checksums.as_ref()?;
+        checksums
+            .get(&format!("{}.gz", self.file_name))
+            .map(|checksum| checksum.to_string())
+    }
+
+    /// With --checksum-policy fail, refuses an archive without a checksum before it is
+    /// downloaded. Another attempt would not find one either, so this is not retried.
+    fn ensure_checksum_known(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.config.checksum_policy != ChecksumPolicy::Fail
+            || self.verified_archives.contains(&self.file_name)
+        {
+            return Ok(());
+        }
+        let archive_name = format!("{}.gz", self.file_name);
+        match &self.input_dir {
+            Some(input_dir) if checksum::local_md5(input_dir, &archive_name)?.is_none() => {
+                Err(format!("{} has no md5 file and no MD5SUMS entry", archive_name).into())
+            }
+            None if self.checksums.is_some() && self.expected_from_manifest().is_none() => {
+                Err(format!("{} is not in the checksum manifest", archive_name).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn download_md5(
+        &self,
+        client: &Client,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         let mut response = client
             .get(format!("{}.md5", self.download_url))
             .send()
-            .await?;
+            .await?
+            .error_for_status()?;
         let mut dest_file = File::create(&self.md5_file_name).await?;
         while let Some(chunk) = response.chunk().await? {
             dest_file.write_all(&chunk).await?;
+            self.budget.add(chunk.len() as u64);
+            self.resources
+                .add_network(Stage::Checksum, chunk.len() as u64);
         }
+        dest_file.flush().await?;
         let checksum_from_control = std::fs::read_to_string(&self.md5_file_name)?;
-        let checksum_from_file = hash_file(self.local_download_filename.clone());
-        Ok(checksum_from_control.trim() == checksum_from_file.md5_hash.trim())
+        Ok(checksum::md5_from_control_file(&checksum_from_control).to_string())
     }
 
+    /// Decompresses the archive into the extracted file chunk by chunk, so neither of them is
+    /// held in memory as a whole.
     async fn extract(&self) -> Result<(), std::io::Error> {
-        self.report_state(ParserState::Extracting(0));
-        let gz_file = tokio::fs::File::open(&self.local_download_filename).await?;
-        let br = BufReader::new(gz_file);
-        self.report_state(ParserState::Extracting(10));
-        let mut gz = GzipDecoder::new(br);
-        let mut xml_data = String::new();
-        let _ = gz.read_to_string(&mut xml_data).await;
-        self.report_state(ParserState::Extracting(90));
-        tokio::fs::write(&self.extracted_filename, &xml_data).await?;
-        self.report_state(ParserState::Extracting(100));
+        self.report_state(ParserState::Extracting(Progress::default()));
+        let archive = File::open(&self.local_download_filename).await?;
+        let archive_size = archive.metadata().await?.len().max(1);
+        let mut gz = GzipDecoder::new(CountingReader::new(tokio::io::BufReader::new(archive)));
+        let mut extracted = File::create(&self.extracted_filename).await?;
+        let mut size = 0;
+        let mut chunk = vec![0; EXTRACT_CHUNK_SIZE];
+        let mut last_reported_percentage = 0;
+        loop {
+            let n = gz.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            extracted.write_all(&chunk[..n]).await?;
+            size += n as u64;
+            let percentage = (100 * gz.get_ref().count() / archive_size).min(99) as u8;
+            if percentage > last_reported_percentage {
+                last_reported_percentage = percentage;
+                self.report_state(ParserState::Extracting(Progress::new(percentage, size)));
+            }
+        }
+        extracted.flush().await?;
+        self.report_state(ParserState::Extracting(Progress::new(100, size)));
         Ok(())
     }
 
-    async fn process(&mut self) -> Result<usize, fmt::Error> {
-        self.report_state(ParserState::Processing(0));
-        let xml_data = tokio::fs::read_to_string(&self.extracted_filename)
-            .await
-            .unwrap();
-        let opts = ParsingOptions {
-            allow_dtd: true,
-            nodes_limit: u32::MAX,
+    async fn process(&mut self) -> Result<usize, BackendError> {
+        self.report_state(ParserState::Processing(Progress::default()));
+        // The quick-xml backend reads the file record by record, roxmltree still reads all of it,
+        // see XmlBackend::parse_reader.
+        let extracted = std::fs::File::open(&self.extracted_filename)?;
+        let size = extracted.metadata()?.len();
+        let mut reader = std::io::BufReader::new(extracted);
+        let mut report_progress = |percentage, records: usize| {
+            self.report_state(ParserState::Processing(Progress::new(
+                percentage,
+                records as u64,
+            )))
         };
-        let doc = roxmltree::Document::parse_with_options(&xml_data, opts).unwrap();
-        let mut last_reported_percentage: u8 = 0;
-        let mut processed_articles = 0;
-        let itter = doc
-            .root()
-            .descendants()
-            .filter(|n| n.tag_name().name() == "PubmedArticle");
-
-        let total_n_articles = itter.clone().count();
-        for pubmed_article in itter {
-            let article = self.process_one_pubmed_article(pubmed_article);
-            if article.is_valid() {
-                self.article_data.push(article);
-            }
-            processed_articles += 1;
-            let new_percentage =
-                (100.0 * processed_articles as f32 / total_n_articles as f32).floor() as u8;
-            if new_percentage > last_reported_percentage {
-                last_reported_percentage = new_percentage;
-                self.report_state(ParserState::Processing(last_reported_percentage));
-            }
-        }
+        let mut warnings = vec![];
+        let articles = self.backend.parse_reader(
+            &mut reader,
+            size,
+            &self.extraction,
+            &mut report_progress,
+            &mut |warning| warnings.push(warning),
+        )?;
+        self.report_warnings(&warnings);
+        self.report_oversized(&warnings);
+        self.article_data = articles;
         Ok(self.article_data.len())
     }
 
-    pub fn process_one_pubmed_article(&self, pubmed_article: Node) -> Article {
-        let mut article = Article::new();
-        for child in pubmed_article.descendants() {
-            match child.tag_name().name() {
-                "Article" => {
-                    article.set_from_article_data(child);
+    /// Reports the records the backend truncated or left out for --max-record-bytes.
+    fn report_oversized(&self, warnings: &[ParseWarning]) {
+        let oversized = warnings
+            .iter()
+            .filter(|w| w.kind == WarningKind::OversizedRecord)
+            .count();
+        let Some(limit) = self.config.max_record_bytes.filter(|_| oversized > 0) else {
+            return;
+        };
+        self.report_state(ParserState::OversizedRecords(oversized));
+        let action = match self.config.oversized_records {
+            OversizedPolicy::Truncate => "truncated",
+            OversizedPolicy::Skip => "skipped",
+        };
+        let reason = format!(
+            "{} records above {} bytes were {}",
+            oversized, limit, action
+        );
+        self.emit(Event::Warning {
+            file: &self.file_name,
+            worker: self.id,
+            reason: &reason,
+        });
+    }
+
+    /// Keeps the relevant articles. With a relevance model, the model decides instead of the
+    /// keywords; the keyword hits are recorded either way. Boilerplate is removed first, so it
+    /// neither matches keywords nor ends up in the results.
+    fn filter_articles(&mut self) -> Result<(), ModelError> {
+        if let Some(boilerplate) = &self.boilerplate {
+            for article in &mut self.article_data {
+                boilerplate.strip(article);
+            }
+        }
+        self.article_data = dedup_versions(
+            std::mem::take(&mut self.article_data),
+            self.config.pmid_versions,
+        );
+        let hits = self.filter.count_hits(&self.article_data);
+        let topic_matches = self.topics.count_matches(&self.article_data);
+        let n_articles = self.article_data.len();
+        match &self.model {
+            Some(model) => {
+                let mut relevant = vec![];
+                for article in std::mem::take(&mut self.article_data) {
+                    let text = format!(
+                        "{}\n{}",
+                        article.title,
+                        article.abstract_text(
+                            self.config.filter_abstract,
+                            self.config.other_abstract_language.as_deref()
+                        )
+                    );
+                    if model.score(&text)? >= self.config.relevance_threshold {
+                        relevant.push(article);
+                    }
                 }
-                "PubmedData" => article.set_from_pubmed_data(child),
-                _ => {}
+                self.article_data = relevant;
+            }
+            None => {
+                let filter = &self.filter;
+                self.article_data.retain(|a| filter.is_relevant(a));
             }
         }
-        article
+        let (seed, rate) = (self.config.seed, self.config.sample_rate);
+        self.article_data
+            .retain(|a| rng::is_sampled(seed, &a.pmid, rate));
+        if self.config.export_abstract != AbstractSource::Main {
+            let language = self.config.other_abstract_language.as_deref();
+            for article in &mut self.article_data {
+                article.paper_abstract = article
+                    .abstract_text(self.config.export_abstract, language)
+                    .into_owned();
+            }
+        }
+        self.heatmap
+            .record(&self.file_name, n_articles, self.article_data.len(), hits);
+        self.topics
+            .record(&self.file_name, &topic_matches, &self.article_data);
+        Ok(())
     }
 
-    fn filter_articles(&mut self) {
-        self.article_data.retain(|a| a.is_article_relevant());
+    /// Logs every warning and tells the logger once about every kind that occurred.
+    fn report_warnings(&self, warnings: &[ParseWarning]) {
+        let mut kinds = vec![];
+        for warning in warnings {
+            self.warnings
+                .record(&self.file_name, &warning.pmid, warning.kind);
+            if !kinds.contains(&warning.kind) {
+                kinds.push(warning.kind);
+            }
+        }
+        for kind in kinds {
+            self.report_state(ParserState::Warning(kind));
+        }
     }
 
-    async fn write_output(&self) -> bool {
+    async fn write_output(&mut self) -> Result<(), SinkError> {
+        self.release_order.wait_turn(&self.file_name).await;
         self.report_state(ParserState::WritingFile);
-        let articles_json = serde_json::to_string_pretty(&self.article_data).unwrap();
-        let mut file = File::create(&self.output_filename).await.unwrap();
-        file.write_all(articles_json.as_bytes()).await.unwrap();
-        let _ = tokio::fs::remove_dir(self.temp_dir.clone()).await;
+        // Sinks block until the records are on disk, a merged one for up to a flush interval, so
+        // they are called on the blocking pool instead of holding up a worker of the runtime.
+        let sink = self.sink.clone();
+        let file_name = self.file_name.clone();
+        let index = match &self.config.source_offsets {
+            Some(directory) => Some(source_index::stage_offsets(
+                directory,
+                &self.file_name,
+                &self.article_data,
+            )?),
+            None => None,
+        };
+        let articles = std::mem::take(&mut self.article_data);
+        let (articles, written) = tokio::task::spawn_blocking(move || {
+            let written = sink.write(&file_name, &articles);
+            (articles, written)
+        })
+        .await?;
+        self.article_data = articles;
+        if let Err(e) = written {
+            if let Some(index) = index {
+                let _ = index.discard();
+            }
+            return Err(e);
+        }
+        if let Some(index) = index {
+            index.commit()?;
+        }
         self.report_state(ParserState::FinishedInputFile(self.article_data.len()));
-        true
+        Ok(())
     }
 }
+
+/// Counts the bytes that were consumed from a buffered reader, e.g. the compressed bytes a
+/// decoder has read so far.
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, count: 0 }
+    }
+
+    fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        this.count += (buf.filled().len() - before) as u64;
+        result
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for CountingReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.count += amt as u64;
+        Pin::new(&mut this.inner).consume(amt);
+    }
+}
+
+/// A new work directory for the parser in --temp-dir or the system's temporary directory.
+fn create_work_dir(config: &Config, id: u32) -> std::io::Result<WorkDir> {
+    let temp_root = match &config.temp_dir {
+        Some(temp_dir) => PathBuf::from(temp_dir),
+        None => std::env::temp_dir(),
+    };
+    WorkDir::create(&temp_root, &format!("dir{}", id))
+}
+
+/// The keyword filter of the run, also used by the outputs that count keyword hits.
+pub fn keyword_filter(config: &Config) -> KeywordFilter {
+    KeywordFilter::new(config.keywords.clone()).with_abstract(
+        config.filter_abstract,
+        config.other_abstract_language.clone(),
+    )
+}
+
+/// The delay before the given retry, doubling from the first delay up to MAX_RETRY_DELAY_MS.
+fn retry_delay_ms(first_delay_ms: u64, attempt: u32) -> u64 {
+    2_u64
+        .checked_pow(attempt.saturating_sub(1))
+        .map_or(u64::MAX, |factor| first_delay_ms.saturating_mul(factor))
+        .min(MAX_RETRY_DELAY_MS)
+}
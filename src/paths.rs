@@ -0,0 +1,104 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Makes a path absolute and resolves `.` and `..` without touching the file system, so paths
+/// that do not exist yet can be compared as well.
+pub fn normalize(path: &str) -> std::io::Result<PathBuf> {
+    let path = Path::new(path);
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    Ok(normalized)
+}
+
+pub fn normalize_string(path: &str) -> std::io::Result<String> {
+    Ok(normalize(path)?.to_string_lossy().to_string())
+}
+
+/// A path the run reads or writes, together with the flag it was given with.
+pub struct RunPath {
+    pub flag: &'static str,
+    pub path: PathBuf,
+}
+
+impl RunPath {
+    pub fn new(flag: &'static str, path: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            flag,
+            path: normalize(path)?,
+        })
+    }
+
+    /// Fails if this path is the directory `outer` or lies inside of it.
+    pub fn ensure_outside(&self, outer: &RunPath, reason: &str) -> Result<(), String> {
+        if !self.path.starts_with(&outer.path) {
+            return Ok(());
+        }
+        let relation = if self.path == outer.path {
+            "is the same as"
+        } else {
+            "lies inside of"
+        };
+        Err(format!(
+            "{} {} {} {} {}. {}, choose separate directories.",
+            self.flag,
+            self.path.display(),
+            relation,
+            outer.flag,
+            outer.path.display(),
+            reason
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_resolves_dots_without_the_file_system() {
+        for (path, expected) in [
+            ("/data/./out", "/data/out"),
+            ("/data/in/../out/", "/data/out"),
+            ("/data/out/..", "/data"),
+        ] {
+            assert_eq!(normalize(path).unwrap(), PathBuf::from(expected));
+        }
+        assert!(normalize("out").unwrap().is_absolute());
+    }
+
+    #[test]
+    fn ensure_outside_compares_whole_path_components() {
+        let outer = RunPath::new("--output-path", "/data/out").unwrap();
+        for (path, relation) in [
+            ("/data/out", Some("is the same as")),
+            ("/data/out/tmp", Some("lies inside of")),
+            ("/data/in/../out/tmp", Some("lies inside of")),
+            // Siblings that only share a prefix of the name.
+            ("/data/out2", None),
+            ("/data/outside/tmp", None),
+            ("/data", None),
+        ] {
+            let inner = RunPath::new("--temp-dir", path).unwrap();
+            let result = inner.ensure_outside(&outer, "Temporary files would be mixed in");
+            match relation {
+                Some(relation) => {
+                    let error = result.unwrap_err();
+                    assert!(error.contains(relation), "{}: {}", path, error);
+                }
+                None => assert!(result.is_ok(), "{}", path),
+            }
+        }
+    }
+}
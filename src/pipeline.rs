@@ -0,0 +1,293 @@
+//! Composes sources, filters, enrichers and sinks into a pipeline, for services that ingest
+//! articles from their own feeds:
+//!
+//! ```no_run
+//! use hcse_parser::pipeline::{JsonLinesSink, Pipeline, XmlFiles};
+//!
+//! let stats = Pipeline::new(XmlFiles::new(vec!["pubmed24n0001.xml".into()]))
+//!     .filter(|article: &hcse_parser::Article| article.title.contains("tumor"))
+//!     .enrich(|article: &mut hcse_parser::Article| {
+//!         article.title = article.title.trim().to_string();
+//!         Ok(())
+//!     })
+//!     .sink(JsonLinesSink::new(std::io::stdout()))
+//!     .run()?;
+//! eprintln!("{} of {} articles written", stats.written, stats.read);
+//! # Ok::<(), hcse_parser::pipeline::PipelineError>(())
+//! ```
+//!
+//! The source, the filters and enrichers and the sink run on their own threads and hand batches
+//! to each other through bounded channels, so a slow sink holds back the source instead of
+//! letting the batches pile up in memory.
+use crate::article::Article;
+use crate::file_system::{FileSystem, OsFileSystem};
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+
+pub type PipelineError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The number of batches that may wait between two stages, see `Pipeline::capacity`.
+const DEFAULT_CAPACITY: usize = 4;
+
+/// Produces the articles in batches, e.g. one batch per file. None ends the pipeline.
+pub trait Source: Send {
+    fn next_batch(&mut self) -> Option<Result<Vec<Article>, PipelineError>>;
+}
+
+/// Decides which articles are kept.
+pub trait Filter: Send {
+    fn keep(&mut self, article: &Article) -> bool;
+}
+
+/// Adds to or changes an article that passed the filters before it. An error stops the pipeline.
+pub trait Enricher: Send {
+    fn enrich(&mut self, article: &mut Article) -> Result<(), PipelineError>;
+}
+
+/// Receives the batches after all filters and enrichers. Empty batches are not passed on.
+pub trait Sink: Send {
+    fn write(&mut self, batch: Vec<Article>) -> Result<(), PipelineError>;
+
+    /// Called once after the last batch, e.g. to flush a writer.
+    fn finish(&mut self) -> Result<(), PipelineError> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&Article) -> bool + Send> Filter for F {
+    fn keep(&mut self, article: &Article) -> bool {
+        self(article)
+    }
+}
+
+impl<F: FnMut(&mut Article) -> Result<(), PipelineError> + Send> Enricher for F {
+    fn enrich(&mut self, article: &mut Article) -> Result<(), PipelineError> {
+        self(article)
+    }
+}
+
+/// Collects all articles in memory.
+impl Sink for Vec<Article> {
+    fn write(&mut self, mut batch: Vec<Article>) -> Result<(), PipelineError> {
+        self.append(&mut batch);
+        Ok(())
+    }
+}
+
+/// Parses PubMed xml files from disk, one batch per file.
+pub struct XmlFiles {
+    paths: std::vec::IntoIter<String>,
+    file_system: Arc<dyn FileSystem>,
+}
+
+impl XmlFiles {
+    pub fn new(paths: Vec<String>) -> Self {
+        Self {
+            paths: paths.into_iter(),
+            file_system: Arc::new(OsFileSystem),
+        }
+    }
+
+    /// Reads the files from another file system, e.g. a `MemoryFileSystem` in tests.
+    pub fn file_system(mut self, file_system: Arc<dyn FileSystem>) -> Self {
+        self.file_system = file_system;
+        self
+    }
+}
+
+impl Source for XmlFiles {
+    fn next_batch(&mut self) -> Option<Result<Vec<Article>, PipelineError>> {
+        let path = self.paths.next()?;
+        let batch = self
+            .file_system
+            .read_to_string(Path::new(&path))
+            .map_err(|e| PipelineError::from(format!("could not read {}: {}", path, e)))
+            .and_then(|xml| crate::parse_pubmed_xml(&xml));
+        Some(batch)
+    }
+}
+
+/// Takes the batches from an iterator, e.g. of efetch responses parsed with `parse_pubmed_xml`.
+pub struct Batches<I>(pub I);
+
+impl<I: Iterator<Item = Result<Vec<Article>, PipelineError>> + Send> Source for Batches<I> {
+    fn next_batch(&mut self) -> Option<Result<Vec<Article>, PipelineError>> {
+        self.0.next()
+    }
+}
+
+/// Writes one json object per article and line.
+pub struct JsonLinesSink<W: Write + Send> {
+    writer: std::io::BufWriter<W>,
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: std::io::BufWriter::new(writer),
+        }
+    }
+}
+
+impl<W: Write + Send> Sink for JsonLinesSink<W> {
+    fn write(&mut self, batch: Vec<Article>) -> Result<(), PipelineError> {
+        for article in &batch {
+            serde_json::to_writer(&mut self.writer, article)?;
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), PipelineError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// The number of articles that went in and came out of a pipeline.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineStats {
+    pub batches: usize,
+    pub read: usize,
+    pub written: usize,
+}
+
+enum Step {
+    Filter(Box<dyn Filter>),
+    Enrich(Box<dyn Enricher>),
+}
+
+/// A source with the filters and enrichers that are applied to its articles, in the order they
+/// were added.
+pub struct Pipeline<S: Source> {
+    source: S,
+    steps: Vec<Step>,
+    capacity: usize,
+}
+
+/// A pipeline with a sink, ready to run.
+pub struct CompletePipeline<S: Source, K: Sink> {
+    pipeline: Pipeline<S>,
+    sink: K,
+}
+
+impl<S: Source> Pipeline<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            steps: vec![],
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+
+    pub fn filter(mut self, filter: impl Filter + 'static) -> Self {
+        self.steps.push(Step::Filter(Box::new(filter)));
+        self
+    }
+
+    pub fn enrich(mut self, enricher: impl Enricher + 'static) -> Self {
+        self.steps.push(Step::Enrich(Box::new(enricher)));
+        self
+    }
+
+    /// The number of batches that may wait in front of a stage before the stage in front of it
+    /// blocks. At least one.
+    pub fn capacity(mut self, batches: usize) -> Self {
+        self.capacity = batches.max(1);
+        self
+    }
+
+    pub fn sink<K: Sink>(self, sink: K) -> CompletePipeline<S, K> {
+        CompletePipeline {
+            pipeline: self,
+            sink,
+        }
+    }
+}
+
+impl<S: Source, K: Sink> CompletePipeline<S, K> {
+    /// Runs the pipeline until the source is exhausted or a stage fails, and returns the sink.
+    pub fn run_into(self) -> Result<(PipelineStats, K), PipelineError> {
+        let Pipeline {
+            mut source,
+            mut steps,
+            capacity,
+        } = self.pipeline;
+        let mut sink = self.sink;
+        let (read_sender, read_receiver) = sync_channel(capacity);
+        let (kept_sender, kept_receiver) = sync_channel(capacity);
+        std::thread::scope(|scope| {
+            let source = scope.spawn(move || read(&mut source, read_sender));
+            let steps = scope.spawn(move || transform(&mut steps, read_receiver, kept_sender));
+            let written = write(&mut sink, kept_receiver);
+            // When a stage fails, it drops its end of the channels and the stages around it stop
+            // at their next send or receive. The first error in pipeline order is reported.
+            let (batches, read) = source.join().map_err(|_| "the source panicked")??;
+            steps
+                .join()
+                .map_err(|_| "a filter or enricher panicked")??;
+            Ok((
+                PipelineStats {
+                    batches,
+                    read,
+                    written: written?,
+                },
+                sink,
+            ))
+        })
+    }
+
+    pub fn run(self) -> Result<PipelineStats, PipelineError> {
+        Ok(self.run_into()?.0)
+    }
+}
+
+fn read(
+    source: &mut impl Source,
+    sender: SyncSender<Vec<Article>>,
+) -> Result<(usize, usize), PipelineError> {
+    let (mut batches, mut articles) = (0, 0);
+    while let Some(batch) = source.next_batch() {
+        let batch = batch?;
+        batches += 1;
+        articles += batch.len();
+        if sender.send(batch).is_err() {
+            break;
+        }
+    }
+    Ok((batches, articles))
+}
+
+fn transform(
+    steps: &mut [Step],
+    receiver: Receiver<Vec<Article>>,
+    sender: SyncSender<Vec<Article>>,
+) -> Result<(), PipelineError> {
+    for mut batch in receiver {
+        for step in steps.iter_mut() {
+            match step {
+                Step::Filter(filter) => batch.retain(|article| filter.keep(article)),
+                Step::Enrich(enricher) => {
+                    for article in batch.iter_mut() {
+                        enricher.enrich(article)?;
+                    }
+                }
+            }
+        }
+        if !batch.is_empty() && sender.send(batch).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn write(sink: &mut impl Sink, receiver: Receiver<Vec<Article>>) -> Result<usize, PipelineError> {
+    let mut written = 0;
+    for batch in receiver {
+        written += batch.len();
+        sink.write(batch)?;
+    }
+    sink.finish()?;
+    Ok(written)
+}
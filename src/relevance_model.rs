@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+pub type ModelError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A classifier that scores how relevant an article is, as an alternative to the keyword lists.
+pub trait RelevanceModel: Send + Sync {
+    /// The probability that the text is relevant, between 0 and 1.
+    fn score(&self, text: &str) -> Result<f32, ModelError>;
+}
+
+/// Loads a user supplied ONNX text classifier. The model receives a `[1, input_size]` f32 tensor
+/// with a hashed bag of words of the text: every lowercase alphanumeric token increments the
+/// entry at `fnv1a(token) % input_size`, and the vector is normalized to unit length. Its output
+/// is either a single probability or the probabilities of the classes `[irrelevant, relevant]`.
+#[cfg(feature = "onnx")]
+pub fn load_model(path: &str, input_size: usize) -> Result<Arc<dyn RelevanceModel>, ModelError> {
+    Ok(Arc::new(onnx::OnnxModel::load(path, input_size)?))
+}
+
+#[cfg(not(feature = "onnx"))]
+pub fn load_model(_path: &str, _input_size: usize) -> Result<Arc<dyn RelevanceModel>, ModelError> {
+    Err("this build does not support relevance models, rebuild with --features onnx".into())
+}
+
+#[cfg(feature = "onnx")]
+mod onnx {
+    use super::{ModelError, RelevanceModel};
+    use crate::run_info::fnv1a;
+    use tract_onnx::prelude::*;
+
+    pub struct OnnxModel {
+        plan: TypedRunnableModel<TypedModel>,
+        input_size: usize,
+    }
+
+    impl OnnxModel {
+        pub fn load(path: &str, input_size: usize) -> Result<Self, ModelError> {
+            let plan = tract_onnx::onnx()
+                .model_for_path(path)?
+                .with_input_fact(0, f32::fact([1, input_size]).into())?
+                .into_optimized()?
+                .into_runnable()?;
+            Ok(Self { plan, input_size })
+        }
+
+        fn features(&self, text: &str) -> Vec<f32> {
+            let mut features = vec![0.0; self.input_size];
+            for token in text
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|t| !t.is_empty())
+            {
+                let index = fnv1a(token.to_lowercase().as_bytes()) % self.input_size as u64;
+                features[index as usize] += 1.0;
+            }
+            let norm = features.iter().map(|f| f * f).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                features.iter_mut().for_each(|f| *f /= norm);
+            }
+            features
+        }
+    }
+
+    impl RelevanceModel for OnnxModel {
+        fn score(&self, text: &str) -> Result<f32, ModelError> {
+            let input: Tensor =
+                tract_ndarray::Array2::from_shape_vec((1, self.input_size), self.features(text))?
+                    .into();
+            let outputs = self.plan.run(tvec!(input.into()))?;
+            let scores = outputs[0].to_array_view::<f32>()?;
+            // With two classes the last entry is the probability of the relevant class.
+            scores
+                .iter()
+                .last()
+                .copied()
+                .ok_or_else(|| "the model returned no score".into())
+        }
+    }
+}
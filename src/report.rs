@@ -0,0 +1,295 @@
+use chrono::DateTime;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+pub type ReportError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+    /// The events file of a run, written with --events-file.
+    events_file: String,
+
+    /// The number of columns of the timeline.
+    #[arg(long, default_value_t = 100)]
+    width: usize,
+
+    /// Also write the timeline as a html page to this file.
+    #[arg(long)]
+    html: Option<String>,
+}
+
+/// The stages in pipeline order, with the letter that marks them in the timeline and their color
+/// in the html page. Only downloads wait on the network, all other stages use the cpu and disk.
+const STAGES: [(&str, char, &str); 6] = [
+    ("download", 'D', "#4e79a7"),
+    ("checksum", 'C', "#f28e2b"),
+    ("extract", 'X', "#e15759"),
+    ("process", 'P', "#76b7b2"),
+    ("filter", 'F', "#59a14f"),
+    ("write", 'W', "#edc948"),
+];
+
+/// The fields of an event line that the report needs. All other events are ignored.
+#[derive(Deserialize)]
+struct EventLine {
+    timestamp: String,
+    event: String,
+    worker: Option<u32>,
+    stage: Option<String>,
+    duration_ms: Option<u64>,
+}
+
+/// One finished stage of one worker, in milliseconds since the first stage started.
+struct Span {
+    worker: u32,
+    stage: usize,
+    start: f64,
+    end: f64,
+}
+
+struct Timeline {
+    spans: Vec<Span>,
+    duration: f64,
+    workers: Vec<u32>,
+}
+
+pub fn run(args: &ReportArgs) -> Result<(), ReportError> {
+    let contents = std::fs::read_to_string(&args.events_file)?;
+    let timeline = read_timeline(&contents)?;
+    print!("{}", render_text(&timeline, args.width.max(10)));
+    if let Some(path) = &args.html {
+        std::fs::write(path, render_html(&timeline))?;
+        println!("Wrote the timeline to {}", path);
+    }
+    Ok(())
+}
+
+fn read_timeline(contents: &str) -> Result<Timeline, ReportError> {
+    let mut finished = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: EventLine = serde_json::from_str(line)
+            .map_err(|e| format!("line {} is not an event: {}", index + 1, e))?;
+        if event.event != "stage_finished" {
+            continue;
+        }
+        let (Some(worker), Some(stage), Some(duration_ms)) =
+            (event.worker, event.stage, event.duration_ms)
+        else {
+            continue;
+        };
+        let Some(stage) = STAGES.iter().position(|(name, _, _)| *name == stage) else {
+            continue;
+        };
+        let end = DateTime::parse_from_rfc3339(&event.timestamp)
+            .map_err(|e| format!("line {}: {}", index + 1, e))?
+            .timestamp_millis() as f64;
+        finished.push((worker, stage, end - duration_ms as f64, end));
+    }
+    if finished.is_empty() {
+        return Err("the events file contains no finished stages".into());
+    }
+    let first = finished
+        .iter()
+        .map(|(_, _, start, _)| *start)
+        .fold(f64::MAX, f64::min);
+    let spans: Vec<Span> = finished
+        .into_iter()
+        .map(|(worker, stage, start, end)| Span {
+            worker,
+            stage,
+            start: start - first,
+            end: end - first,
+        })
+        .collect();
+    let duration = spans.iter().map(|s| s.end).fold(1.0, f64::max);
+    let mut workers: Vec<u32> = spans.iter().map(|s| s.worker).collect();
+    workers.sort();
+    workers.dedup();
+    Ok(Timeline {
+        spans,
+        duration,
+        workers,
+    })
+}
+
+/// The milliseconds spent in every stage, summed over all workers.
+fn stage_totals(timeline: &Timeline) -> [f64; STAGES.len()] {
+    let mut totals = [0.0; STAGES.len()];
+    for span in &timeline.spans {
+        totals[span.stage] += span.end - span.start;
+    }
+    totals
+}
+
+/// Whether the workers mostly waited for downloads or mostly computed.
+fn verdict(totals: &[f64; STAGES.len()]) -> String {
+    let busy: f64 = totals.iter().sum();
+    let network = totals[0] / busy.max(1.0) * 100.0;
+    if network >= 50.0 {
+        format!(
+            "The run is network-bound: {:.0}% of the busy time was spent downloading.",
+            network
+        )
+    } else {
+        format!(
+            "The run is cpu-bound: {:.0}% of the busy time was spent after the download.",
+            100.0 - network
+        )
+    }
+}
+
+/// One row per worker, every column shows the stage that took up most of its time slice.
+fn render_text(timeline: &Timeline, width: usize) -> String {
+    let slice = timeline.duration / width as f64;
+    let mut lines = vec![format!(
+        "Wall-clock time {:.1}s, {} workers, one column is {:.0}ms",
+        timeline.duration / 1000.0,
+        timeline.workers.len(),
+        slice
+    )];
+    for worker in &timeline.workers {
+        let mut row = String::with_capacity(width);
+        for column in 0..width {
+            let (from, to) = (column as f64 * slice, (column + 1) as f64 * slice);
+            let mut covered = [0.0; STAGES.len()];
+            for span in timeline.spans.iter().filter(|s| s.worker == *worker) {
+                covered[span.stage] += (span.end.min(to) - span.start.max(from)).max(0.0);
+            }
+            let (stage, time) = covered
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap_or((0, 0.0));
+            row.push(if time > 0.0 { STAGES[stage].1 } else { '.' });
+        }
+        lines.push(format!("worker {:>3} |{}|", worker, row));
+    }
+    let legend: Vec<String> = STAGES
+        .iter()
+        .map(|(name, letter, _)| format!("{} {}", letter, name))
+        .collect();
+    lines.push(format!("{}, . idle", legend.join(", ")));
+    lines.push(String::new());
+    let totals = stage_totals(timeline);
+    let busy: f64 = totals.iter().sum();
+    lines.push(format!("{:<10} {:>10} {:>6}", "stage", "total", "share"));
+    for (index, (name, _, _)) in STAGES.iter().enumerate() {
+        lines.push(format!(
+            "{:<10} {:>9.1}s {:>5.0}%",
+            name,
+            totals[index] / 1000.0,
+            totals[index] / busy.max(1.0) * 100.0
+        ));
+    }
+    lines.push(verdict(&totals));
+    lines.join("\n") + "\n"
+}
+
+fn render_html(timeline: &Timeline) -> String {
+    let mut rows = BTreeMap::new();
+    for span in &timeline.spans {
+        let (name, _, color) = STAGES[span.stage];
+        rows.entry(span.worker)
+            .or_insert_with(String::new)
+            .push_str(&format!(
+                "<div class=\"span\" title=\"{} {:.0}ms\" style=\"left:{:.3}%;width:{:.3}%;\
+                 background:{}\"></div>",
+                name,
+                span.end - span.start,
+                span.start / timeline.duration * 100.0,
+                ((span.end - span.start) / timeline.duration * 100.0).max(0.05),
+                color
+            ));
+    }
+    let rows: Vec<String> = rows
+        .into_iter()
+        .map(|(worker, spans)| {
+            format!(
+                "<div class=\"row\"><span class=\"label\">worker {}</span>\
+                 <div class=\"track\">{}</div></div>",
+                worker, spans
+            )
+        })
+        .collect();
+    let legend: Vec<String> = STAGES
+        .iter()
+        .map(|(name, _, color)| format!("<span style=\"background:{}\">{}</span>", color, name))
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Stage timeline</title>\
+         <style>body{{font-family:sans-serif}}.row{{display:flex;align-items:center;\
+         margin:2px 0}}.label{{width:7em}}.track{{position:relative;flex:1;height:1.2em;\
+         background:#eee}}.span{{position:absolute;top:0;bottom:0}}.legend span{{\
+         padding:0 .5em;margin-right:.5em}}</style></head><body>\n<p>Wall-clock time {:.1}s. \
+         {}</p>\n<p class=\"legend\">{}</p>\n{}\n</body></html>\n",
+        timeline.duration / 1000.0,
+        verdict(&stage_totals(timeline)),
+        legend.join(""),
+        rows.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    /// Two files of worker 0 and one of worker 1, with events the report does not use in between.
+    const EVENTS: &str = r#"{"timestamp":"2024-01-01T00:00:00+00:00","event":"file_started","file":"a.xml","worker":0}
+{"timestamp":"2024-01-01T00:00:03+00:00","event":"stage_finished","file":"a.xml","worker":0,"stage":"download","duration_ms":3000}
+{"timestamp":"2024-01-01T00:00:04+00:00","event":"stage_finished","file":"a.xml","worker":0,"stage":"process","duration_ms":1000}
+
+{"timestamp":"2024-01-01T00:00:05+00:00","event":"stage_finished","file":"b.xml","worker":1,"stage":"download","duration_ms":1000}
+{"timestamp":"2024-01-01T00:00:06+00:00","event":"stage_finished","file":"b.xml","worker":1,"stage":"unknown","duration_ms":1000}
+{"timestamp":"2024-01-01T00:00:08+00:00","event":"stage_finished","file":"c.xml","worker":0,"stage":"write","duration_ms":2000}
+{"timestamp":"2024-01-01T00:00:08+00:00","event":"file_finished","file":"c.xml","worker":0,"status":"succeeded","articles":3}
+"#;
+
+    #[test]
+    fn the_report_sums_the_stages_of_an_events_file() {
+        let directory = TempDir::new("report").unwrap();
+        let events_file = directory.path().join("events.ndjson");
+        std::fs::write(&events_file, EVENTS).unwrap();
+        let contents = std::fs::read_to_string(&events_file).unwrap();
+
+        let timeline = read_timeline(&contents).unwrap();
+        assert_eq!(timeline.workers, [0, 1]);
+        assert_eq!(timeline.duration, 8000.0);
+        assert_eq!(
+            stage_totals(&timeline),
+            [4000.0, 0.0, 0.0, 1000.0, 0.0, 2000.0]
+        );
+        let text = render_text(&timeline, 8);
+        assert!(text.contains("worker   0 |DDDP..WW|"), "{}", text);
+        assert!(text.contains("worker   1 |....D...|"), "{}", text);
+        assert!(text.contains("download         4.0s    57%"), "{}", text);
+        assert!(text.contains("The run is network-bound: 57%"), "{}", text);
+
+        let html = directory.path().join("timeline.html");
+        run(&ReportArgs {
+            events_file: events_file.to_string_lossy().to_string(),
+            width: 8,
+            html: Some(html.to_string_lossy().to_string()),
+        })
+        .unwrap();
+        assert!(std::fs::read_to_string(html).unwrap().contains("worker 1"));
+    }
+
+    #[test]
+    fn the_report_refuses_files_without_finished_stages_or_with_broken_lines() {
+        let started = EVENTS.lines().next().unwrap();
+        assert!(read_timeline(started).is_err());
+        let error = read_timeline(&format!("{}{{\"timestamp\"", EVENTS))
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().starts_with("line 9 is not an event"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The stages of the pipeline that resources are accounted to.
+#[derive(Clone, Copy, Debug)]
+pub enum Stage {
+    Download,
+    Checksum,
+    Extract,
+    Process,
+    Filter,
+    Write,
+}
+
+const STAGE_NAMES: [&str; 6] = [
+    "download", "checksum", "extract", "process", "filter", "write",
+];
+
+/// Usage that falls into a sample without any active stage, e.g. the startup.
+const OTHER: &str = "other";
+
+#[derive(Serialize, Default, Clone, Debug)]
+pub struct StageUsage {
+    pub cpu_seconds: f64,
+    /// Bytes read from and written to storage, as counted by the kernel.
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    pub network_bytes: u64,
+}
+
+/// The resources of the whole run, for capacity planning.
+#[derive(Serialize, Debug)]
+pub struct ResourceSummary {
+    pub peak_rss_bytes: u64,
+    pub cpu_seconds: f64,
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    pub network_bytes: u64,
+    pub stages: BTreeMap<&'static str, StageUsage>,
+}
+
+/// The counters of the process at one point in time.
+#[derive(Clone, Copy, Default)]
+struct Sample {
+    cpu_seconds: f64,
+    read_bytes: u64,
+    written_bytes: u64,
+}
+
+/// Samples /proc in the background and splits the growth of the cpu time and the storage io
+/// between the stages the parsers are in at that moment. Network bytes are counted exactly by
+/// the stages that download.
+pub struct ResourceMonitor {
+    active: [AtomicUsize; 6],
+    network: [AtomicU64; 6],
+    stages: Mutex<BTreeMap<&'static str, StageUsage>>,
+    last: Mutex<Sample>,
+    stop: AtomicBool,
+    sampler: Mutex<Option<JoinHandle<()>>>,
+    /// The resident memory above which a warning is printed, see `check_rss`.
+    rss_warning_bytes: Option<u64>,
+    above_rss_warning: AtomicBool,
+}
+
+/// Marks a stage as active until it is dropped.
+pub struct StageGuard {
+    monitor: Arc<ResourceMonitor>,
+    stage: Stage,
+}
+
+impl Drop for StageGuard {
+    fn drop(&mut self) {
+        self.monitor.active[self.stage as usize].fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ResourceMonitor {
+    /// Starts sampling. Without /proc, only the network bytes are counted and the memory is not
+    /// watched.
+    pub fn start(rss_warning_bytes: Option<u64>) -> Arc<Self> {
+        let monitor = Arc::new(Self {
+            active: Default::default(),
+            network: Default::default(),
+            stages: Mutex::new(BTreeMap::new()),
+            last: Mutex::new(read_sample().unwrap_or_default()),
+            stop: AtomicBool::new(false),
+            sampler: Mutex::new(None),
+            rss_warning_bytes,
+            above_rss_warning: AtomicBool::new(false),
+        });
+        if read_sample().is_some() {
+            let sampling = monitor.clone();
+            let handle = std::thread::spawn(move || {
+                while !sampling.stop.load(Ordering::SeqCst) {
+                    std::thread::sleep(SAMPLE_INTERVAL);
+                    sampling.account();
+                    sampling.check_rss();
+                }
+            });
+            *monitor.sampler.lock().unwrap() = Some(handle);
+        }
+        monitor
+    }
+
+    pub fn enter(self: &Arc<Self>, stage: Stage) -> StageGuard {
+        self.active[stage as usize].fetch_add(1, Ordering::SeqCst);
+        StageGuard {
+            monitor: self.clone(),
+            stage,
+        }
+    }
+
+    pub fn add_network(&self, stage: Stage, bytes: u64) {
+        self.network[stage as usize].fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Splits the growth since the last sample between the active stages, weighted by the
+    /// number of parsers in each.
+    fn account(&self) {
+        let Some(sample) = read_sample() else {
+            return;
+        };
+        let previous = std::mem::replace(&mut *self.last.lock().unwrap(), sample);
+        let active: Vec<usize> = self
+            .active
+            .iter()
+            .map(|a| a.load(Ordering::SeqCst))
+            .collect();
+        let total: usize = active.iter().sum();
+        let mut stages = self.stages.lock().unwrap();
+        let mut add = |name: &'static str, share: f64| {
+            let usage = stages.entry(name).or_default();
+            usage.cpu_seconds += (sample.cpu_seconds - previous.cpu_seconds).max(0.0) * share;
+            usage.read_bytes +=
+                (sample.read_bytes.saturating_sub(previous.read_bytes) as f64 * share) as u64;
+            usage.written_bytes +=
+                (sample.written_bytes.saturating_sub(previous.written_bytes) as f64 * share) as u64;
+        };
+        if total == 0 {
+            add(OTHER, 1.0);
+            return;
+        }
+        for (index, count) in active.iter().enumerate().filter(|(_, c)| **c > 0) {
+            add(STAGE_NAMES[index], *count as f64 / total as f64);
+        }
+    }
+
+    /// Warns once when the resident memory grows above the threshold, and again if it grows
+    /// above it once more after it went down, e.g. after the parsers were recycled.
+    fn check_rss(&self) {
+        let (Some(threshold), Some(rss)) = (self.rss_warning_bytes, rss_bytes()) else {
+            return;
+        };
+        let above = rss > threshold;
+        if above && !self.above_rss_warning.swap(true, Ordering::SeqCst) {
+            eprintln!(
+                "WARNING: the process uses {} MB of memory, more than the {} MB of \
+                 --rss-warning-mb. Consider --recycle-after-files if it keeps growing.",
+                rss / (1024 * 1024),
+                threshold / (1024 * 1024)
+            );
+        } else if !above {
+            self.above_rss_warning.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Stops the sampling and returns the usage of the run. None if /proc is not available.
+    pub fn finish(&self) -> Option<ResourceSummary> {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.sampler.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+        let sample = read_sample()?;
+        self.account();
+        let mut stages = self.stages.lock().unwrap().clone();
+        for (index, name) in STAGE_NAMES.iter().enumerate() {
+            let bytes = self.network[index].load(Ordering::Relaxed);
+            if bytes > 0 {
+                stages.entry(name).or_default().network_bytes = bytes;
+            }
+        }
+        Some(ResourceSummary {
+            peak_rss_bytes: peak_rss_bytes().unwrap_or(0),
+            cpu_seconds: sample.cpu_seconds,
+            read_bytes: sample.read_bytes,
+            written_bytes: sample.written_bytes,
+            network_bytes: stages.values().map(|s| s.network_bytes).sum(),
+            stages,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_sample() -> Option<Sample> {
+    // The fields after the process name, which may contain spaces, start with the state.
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+    // Some containers hide the io counters, the cpu time is still worth reporting then.
+    let io = std::fs::read_to_string("/proc/self/io").unwrap_or_default();
+    let io_counter = |name: &str| {
+        io.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0)
+    };
+    Some(Sample {
+        cpu_seconds: (utime + stime) as f64 / ticks_per_second,
+        read_bytes: io_counter("read_bytes:"),
+        written_bytes: io_counter("write_bytes:"),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_sample() -> Option<Sample> {
+    None
+}
+
+/// The high water mark of the resident set size.
+fn peak_rss_bytes() -> Option<u64> {
+    status_bytes("VmHWM:")
+}
+
+/// The current resident set size.
+fn rss_bytes() -> Option<u64> {
+    status_bytes("VmRSS:")
+}
+
+/// A memory size from /proc/self/status, which lists them in kB.
+fn status_bytes(field: &str) -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes: u64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix(field))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,116 @@
+use crate::run_info::fnv1a;
+
+/// SplitMix64, a small and fully specified generator. The tool does not depend on the algorithms
+/// of a random number crate, so a subset published with a seed can be regenerated exactly by
+/// collaborators with any version of this tool.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniform float in [0, 1) built from the upper 53 bits.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Fisher-Yates shuffle, drawing the swap index as `next_u64() % (i + 1)` from the last element
+/// down to the second.
+pub fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = SplitMix64::new(seed);
+    for i in (1..items.len()).rev() {
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Parses a sample rate, a fraction between 0 and 1.
+pub fn parse_sample_rate(rate: &str) -> Result<f64, String> {
+    let rate: f64 = rate
+        .trim()
+        .parse()
+        .map_err(|_| format!("{} is not a number", rate))?;
+    match (0.0..=1.0).contains(&rate) {
+        true => Ok(rate),
+        false => Err(format!("{} is not between 0 and 1", rate)),
+    }
+}
+
+/// Whether the record with this key belongs to the sample. The decision only depends on the seed
+/// and the key (the PMID), not on which worker sees the record or in which order.
+pub fn is_sampled(seed: u64, key: &str, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    SplitMix64::new(seed ^ fnv1a(key.as_bytes())).next_f64() < rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitmix64_matches_the_reference_sequence() {
+        let mut rng = SplitMix64::new(0);
+        let values: Vec<u64> = (0..3).map(|_| rng.next_u64()).collect();
+        assert_eq!(
+            values,
+            [0xe220a8397b1dcdaf, 0x6e789e6aa1b965f4, 0x06c45d188009454f]
+        );
+    }
+
+    #[test]
+    fn shuffle_depends_only_on_the_seed() {
+        let shuffled = |seed| {
+            let mut items: Vec<u32> = (0..20).collect();
+            shuffle(&mut items, seed);
+            items
+        };
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+        let mut sorted = shuffled(7);
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+        let mut empty: [u32; 0] = [];
+        shuffle(&mut empty, 7);
+    }
+
+    #[test]
+    fn parse_sample_rate_accepts_fractions_from_0_to_1() {
+        for (rate, expected) in [
+            ("0", Ok(0.0)),
+            (" 0.25 ", Ok(0.25)),
+            ("1", Ok(1.0)),
+            ("1.5", Err("1.5 is not between 0 and 1")),
+            ("-0.1", Err("-0.1 is not between 0 and 1")),
+            ("half", Err("half is not a number")),
+        ] {
+            assert_eq!(parse_sample_rate(rate), expected.map_err(String::from));
+        }
+    }
+
+    #[test]
+    fn sampling_is_decided_by_the_seed_and_the_key() {
+        let keys: Vec<String> = (0..1000).map(|pmid| pmid.to_string()).collect();
+        let sample =
+            |seed, rate| -> Vec<bool> { keys.iter().map(|k| is_sampled(seed, k, rate)).collect() };
+        assert_eq!(sample(1, 0.3), sample(1, 0.3));
+        assert_ne!(sample(1, 0.3), sample(2, 0.3));
+        assert!(sample(1, 1.0).iter().all(|s| *s));
+        assert!(sample(1, 0.0).iter().all(|s| !*s));
+        let sampled = sample(1, 0.3).iter().filter(|s| **s).count();
+        assert!((250..350).contains(&sampled), "{} of 1000 sampled", sampled);
+    }
+}
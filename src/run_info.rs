@@ -0,0 +1,67 @@
+use crate::config::Config;
+use serde::Serialize;
+
+/// The first line every run prints, so runs can be identified in log aggregation.
+#[derive(Serialize)]
+pub struct StartupBanner<'a> {
+    pub event: &'static str,
+    pub run_id: &'a str,
+    pub version: &'static str,
+    pub config_hash: String,
+    pub filter_hash: &'a str,
+    /// The --config file the settings were read from, if any.
+    pub config_file: Option<&'a str>,
+    pub config: &'a Config,
+    pub first_file: Option<&'a str>,
+    pub last_file: Option<&'a str>,
+    pub file_count: usize,
+    pub sinks: Vec<String>,
+}
+
+impl StartupBanner<'_> {
+    pub fn print(&self) {
+        if let Ok(line) = serde_json::to_string(self) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// A short id that is unique enough to tell concurrent runs on one machine apart.
+pub fn new_run_id() -> String {
+    let seed = format!(
+        "{}-{}",
+        chrono::Local::now().timestamp_nanos_opt().unwrap_or(0),
+        std::process::id()
+    );
+    fnv1a_hex(seed.as_bytes())[..6].to_string()
+}
+
+/// A 64 bit FNV-1a hash. Unlike the std hashers it is stable across builds, so it can be used in
+/// file names and manifests.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn fnv1a_hex(data: &[u8]) -> String {
+    format!("{:016x}", fnv1a(data))
+}
+
+/// The process title of a run, e.g. `hcse 42% 1a2b3c`. The percentage comes first, so when Linux
+/// cuts the title at 15 bytes only the end of the run id is lost.
+pub fn process_title(run_id: &str, percentage: u64) -> String {
+    format!("hcse {}% {}", percentage, run_id)
+}
+
+/// Sets the process name that is shown by ps and top. Linux truncates it to 15 bytes, see
+/// process_title. Writing /proc/self/comm renames the main thread, even when called from another
+/// thread.
+pub fn set_process_title(title: &str) {
+    if cfg!(target_os = "linux") {
+        let _ = std::fs::write("/proc/self/comm", title);
+    }
+}
@@ -0,0 +1,140 @@
+use clap::ValueEnum;
+
+/// How the input files are spread over the threads.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// All parsers share one tokio runtime and take their files from one queue.
+    Shared,
+    /// Every thread is pinned to a core and owns a fixed shard of the files. Requires the
+    /// `thread-per-core` feature.
+    ThreadPerCore,
+}
+
+/// A set of cpu cores, given on the command line as a list like `0-3,8`.
+#[derive(Clone, Debug)]
+pub struct CpuSet(pub Vec<usize>);
+
+/// Parses a cpu list and checks that every cpu exists, so pinning cannot fail or panic later.
+pub fn parse_cpu_list(list: &str) -> Result<CpuSet, String> {
+    let mut cpus = vec![];
+    for part in list.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let invalid = |_| format!("invalid cpu list entry `{}`", part);
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let first: usize = first.parse().map_err(invalid)?;
+                let last: usize = last.parse().map_err(invalid)?;
+                if first > last {
+                    return Err(format!("the cpu range `{}` is reversed", part));
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(part.parse().map_err(invalid)?),
+        }
+    }
+    if cpus.is_empty() {
+        return Err("the cpu list is empty".to_string());
+    }
+    let limit = cpu_limit();
+    if let Some(cpu) = cpus.iter().find(|cpu| **cpu >= limit) {
+        return Err(format!(
+            "cpu {} does not exist, this machine has cpus 0 to {}",
+            cpu,
+            limit - 1
+        ));
+    }
+    Ok(CpuSet(cpus))
+}
+
+/// The number of cpus that can be pinned to: the online ones, but never more than a cpu_set_t
+/// holds.
+#[cfg(target_os = "linux")]
+fn cpu_limit() -> usize {
+    let online = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    let set_size = libc::CPU_SETSIZE as usize;
+    match usize::try_from(online) {
+        Ok(online) if online > 0 => online.min(set_size),
+        _ => set_size,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_limit() -> usize {
+    usize::MAX
+}
+
+/// Sets the niceness of the calling thread. Threads started afterwards inherit it, so this has to
+/// be called before the runtime is built.
+#[cfg(target_os = "linux")]
+pub fn set_niceness(niceness: i32) -> std::io::Result<()> {
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Restricts the calling thread to the given cores. Threads it starts afterwards inherit the
+/// affinity, so it is called on the threads of the runtime rather than on the main thread.
+#[cfg(target_os = "linux")]
+pub fn pin_to_cpus(cpus: &CpuSet) -> std::io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for cpu in &cpus.0 {
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_niceness(_niceness: i32) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "setting the niceness is only supported on linux",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_to_cpus(_cpus: &CpuSet) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "cpu pinning is only supported on linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cpu_list_accepts_single_cpus_and_ranges() {
+        // Only cpu 0 exists on every machine.
+        for (list, expected) in [
+            ("0", vec![0]),
+            (" 0 ,", vec![0]),
+            ("0-0", vec![0]),
+            ("0,0", vec![0, 0]),
+        ] {
+            assert_eq!(parse_cpu_list(list).unwrap().0, expected, "{:?}", list);
+        }
+    }
+
+    #[test]
+    fn parse_cpu_list_rejects_invalid_lists() {
+        for (list, reason) in [
+            ("", "is empty"),
+            (" , ", "is empty"),
+            ("x", "invalid cpu list entry `x`"),
+            ("0-x", "invalid cpu list entry `0-x`"),
+            ("-1", "invalid cpu list entry `-1`"),
+            ("1-0", "is reversed"),
+            ("0,100000", "cpu 100000 does not exist"),
+        ] {
+            let error = parse_cpu_list(list).unwrap_err();
+            assert!(error.contains(reason), "{:?}: {}", list, error);
+        }
+    }
+}
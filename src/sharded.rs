@@ -0,0 +1,96 @@
+use crate::contact::ContactArgs;
+use crate::parser::{ParserMessage, RunContext};
+use crate::scheduling::CpuSet;
+use std::sync::mpsc::Sender;
+
+pub type ShardError = Box<dyn std::error::Error + Send + Sync>;
+
+#[cfg(feature = "thread-per-core")]
+pub fn ensure_supported() -> Result<(), ShardError> {
+    Ok(())
+}
+
+#[cfg(not(feature = "thread-per-core"))]
+pub fn ensure_supported() -> Result<(), ShardError> {
+    Err(
+        "this build does not support the thread-per-core mode, rebuild with --features \
+         thread-per-core"
+            .into(),
+    )
+}
+
+/// Processes the queue of the context with one thread per shard. Every thread owns a fixed
+/// share of the files, its own runtime, http client, heatmap and topic statistics, so the
+/// threads only meet in the manifest and the sink. The statistics of the shards are merged into
+/// the context once all threads are done.
+#[cfg(feature = "thread-per-core")]
+pub fn process_files(
+    context: &RunContext,
+    contact: &ContactArgs,
+    n_shards: usize,
+    cpus: Option<&CpuSet>,
+    sender: &Sender<ParserMessage>,
+) -> Result<(), ShardError> {
+    use crate::heatmap::KeywordHeatmap;
+    use crate::parser::Parser;
+    use crate::topics::TopicStats;
+    use crate::work_queue::WorkQueue;
+    use std::sync::Arc;
+
+    let files: Vec<String> = std::iter::from_fn(|| context.queue.next_file()).collect();
+    let cores = match cpus {
+        Some(cpus) => cpus.0.clone(),
+        None => (0..std::thread::available_parallelism().map_or(1, |n| n.get())).collect(),
+    };
+    let mut threads = vec![];
+    for shard in 0..n_shards {
+        let mut shard_context = context.clone();
+        // Files are dealt round robin, so every shard gets a similar mix of old and new files.
+        shard_context.queue = Arc::new(WorkQueue::new(
+            files
+                .iter()
+                .skip(shard)
+                .step_by(n_shards)
+                .cloned()
+                .collect(),
+        ));
+        shard_context.heatmap = Arc::new(KeywordHeatmap::new(context.config.keywords.clone()));
+        shard_context.topics = Arc::new(TopicStats::new(&context.config.topics));
+        let client = contact.client_builder().build()?;
+        let sender = sender.clone();
+        let core = cores[shard % cores.len()];
+        let thread = std::thread::Builder::new()
+            .name(format!("shard-{}", shard))
+            .spawn(move || -> Result<RunContext, ShardError> {
+                if let Err(error) = crate::scheduling::pin_to_cpus(&CpuSet(vec![core])) {
+                    eprintln!("Could not pin shard {} to cpu {}: {}", shard, core, error);
+                }
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?;
+                let mut parser = Parser::initialize(&shard_context, &sender, shard as u32);
+                runtime.block_on(parser.try_restart(&client));
+                Ok(shard_context)
+            })?;
+        threads.push(thread);
+    }
+    for thread in threads {
+        let shard_context = thread
+            .join()
+            .map_err(|_| "a shard thread panicked".to_string())??;
+        context.heatmap.absorb(&shard_context.heatmap);
+        context.topics.absorb(&shard_context.topics);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "thread-per-core"))]
+pub fn process_files(
+    _context: &RunContext,
+    _contact: &ContactArgs,
+    _n_shards: usize,
+    _cpus: Option<&CpuSet>,
+    _sender: &Sender<ParserMessage>,
+) -> Result<(), ShardError> {
+    ensure_supported()
+}
@@ -0,0 +1,60 @@
+use crate::article::Article;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The columns of an offsets index.
+const HEADER: &str = "pmid\tversion\toffset\tlength\n";
+
+/// Where the index of an archive is kept, e.g. `pubmed24n0001.xml.gz.offsets.tsv` for
+/// `pubmed24n0001.xml`.
+pub fn index_path(directory: &str, file_name: &str) -> PathBuf {
+    Path::new(directory).join(format!("{}.gz.offsets.tsv", file_name))
+}
+
+/// An index that is on disk under another name and only replaces the index of the archive once
+/// it is committed. It is staged before the output of the archive is written and committed after,
+/// so an archive never counts as done without its index.
+pub struct StagedIndex {
+    partial: PathBuf,
+    path: PathBuf,
+}
+
+impl StagedIndex {
+    pub fn commit(self) -> std::io::Result<()> {
+        std::fs::rename(self.partial, self.path)
+    }
+
+    /// Removes the staged index, e.g. because the output could not be written.
+    pub fn discard(self) -> std::io::Result<()> {
+        std::fs::remove_file(self.partial)
+    }
+}
+
+/// Writes where the records of the kept articles are in their archive, see `index_path`. Offset
+/// and length are bytes of the decompressed archive, so a raw record can be read again later by
+/// decompressing up to offset + length, without keeping a copy of the xml. An existing index of
+/// the archive is replaced when the returned index is committed.
+pub fn stage_offsets(
+    directory: &str,
+    file_name: &str,
+    articles: &[Article],
+) -> std::io::Result<StagedIndex> {
+    let path = index_path(directory, file_name);
+    let mut index = String::from(HEADER);
+    for article in articles {
+        if let Some(range) = &article.source_range {
+            index.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                article.pmid,
+                article.pmid_version,
+                range.start,
+                range.len()
+            ));
+        }
+    }
+    let partial = path.with_extension("tsv.partial");
+    let mut file = std::fs::File::create(&partial)?;
+    file.write_all(index.as_bytes())?;
+    file.sync_data()?;
+    Ok(StagedIndex { partial, path })
+}
@@ -0,0 +1,34 @@
+use crate::article::WarningKind;
+use crate::manifest::Tally;
+use crate::resources::ResourceSummary;
+use crate::topics::TopicSummary;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The outcome of a run, written as json at the end so scripts do not have to parse the manifest.
+#[derive(Serialize)]
+pub struct RunSummary<'a> {
+    pub run_id: &'a str,
+    pub config_hash: String,
+    pub filter_hash: &'a str,
+    /// The input files of this run, a resumed run only counts the files it picked up.
+    pub files: Tally,
+    /// Files that were parsed without any valid article, which usually points to a parsing
+    /// problem rather than to an empty file. Files whose articles were all filtered out are not
+    /// listed.
+    pub files_without_valid_articles: Vec<String>,
+    pub topics: Vec<TopicSummary>,
+    /// The number of records per kind of warning, see --warnings-file for the records.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub warnings: BTreeMap<WarningKind, usize>,
+    /// Peak memory, cpu time and bytes moved, overall and per stage. Only available on linux.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceSummary>,
+}
+
+impl RunSummary<'_> {
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+}
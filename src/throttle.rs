@@ -0,0 +1,158 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// The number of downloads in a row without throttling after which the limit is raised by one.
+const RECOVERY_DOWNLOADS: u32 = 20;
+
+/// Limits the number of concurrent downloads. When the server answers with 429 Too Many Requests
+/// more than `threshold` times, the limit is halved, down to a single download at a time. Once the
+/// server accepts RECOVERY_DOWNLOADS downloads in a row, the limit grows by one again, up to
+/// `max_limit`, so a throttled run does not stay slow after the server recovered.
+pub struct DownloadThrottle {
+    permits: Arc<Semaphore>,
+    limit: AtomicUsize,
+    max_limit: usize,
+    throttled: AtomicU32,
+    accepted: AtomicU32,
+    threshold: u32,
+}
+
+impl DownloadThrottle {
+    pub fn new(limit: usize, max_limit: usize, threshold: u32) -> Self {
+        let max_limit = max_limit.max(1);
+        let limit = limit.clamp(1, max_limit);
+        Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            limit: AtomicUsize::new(limit),
+            max_limit,
+            throttled: AtomicU32::new(0),
+            accepted: AtomicU32::new(0),
+            threshold,
+        }
+    }
+
+    /// Waits until one more download is allowed. The download may run as long as the permit is
+    /// held.
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        self.permits.clone().acquire_owned().await.ok()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::SeqCst)
+    }
+
+    /// Counts a download the server accepted. Returns the new limit if it was raised.
+    pub fn record_accepted(&self) -> Option<usize> {
+        let accepted = self.accepted.fetch_add(1, Ordering::SeqCst) + 1;
+        if accepted < RECOVERY_DOWNLOADS {
+            return None;
+        }
+        self.accepted.store(0, Ordering::SeqCst);
+        let old_limit = self.limit();
+        if old_limit >= self.max_limit
+            || self
+                .limit
+                .compare_exchange(old_limit, old_limit + 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+        {
+            return None;
+        }
+        self.permits.add_permits(1);
+        Some(old_limit + 1)
+    }
+
+    /// Counts a throttled request. Returns the new limit if it was lowered.
+    pub fn record_throttled(&self) -> Option<usize> {
+        self.accepted.store(0, Ordering::SeqCst);
+        let throttled = self.throttled.fetch_add(1, Ordering::SeqCst) + 1;
+        if throttled <= self.threshold {
+            return None;
+        }
+        self.throttled.store(0, Ordering::SeqCst);
+        let old_limit = self.limit();
+        let new_limit = (old_limit / 2).max(1);
+        if new_limit == old_limit
+            || self
+                .limit
+                .compare_exchange(old_limit, new_limit, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+        {
+            return None;
+        }
+        // Permits that are in use cannot be taken away, so they are collected as they return.
+        let permits = self.permits.clone();
+        let surplus = (old_limit - new_limit) as u32;
+        tokio::spawn(async move {
+            if let Ok(permits) = permits.acquire_many_owned(surplus).await {
+                permits.forget();
+            }
+        });
+        Some(new_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn the_limit_is_clamped_to_one_and_the_maximum() {
+        for (limit, max_limit, expected) in [(0, 4, 1), (8, 4, 4), (3, 4, 3), (2, 0, 1)] {
+            assert_eq!(DownloadThrottle::new(limit, max_limit, 0).limit(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn throttling_above_the_threshold_halves_the_limit_down_to_one() {
+        let throttle = DownloadThrottle::new(8, 8, 2);
+        assert_eq!(throttle.record_throttled(), None);
+        assert_eq!(throttle.record_throttled(), None);
+        assert_eq!(throttle.record_throttled(), Some(4));
+        assert_eq!(throttle.limit(), 4);
+        let mut changes = vec![];
+        for _ in 0..9 {
+            changes.extend(throttle.record_throttled());
+        }
+        assert_eq!(changes, [2, 1]);
+        assert_eq!(throttle.limit(), 1);
+    }
+
+    #[tokio::test]
+    async fn accepted_downloads_raise_the_limit_up_to_the_maximum() {
+        let throttle = DownloadThrottle::new(1, 2, 0);
+        for _ in 1..RECOVERY_DOWNLOADS {
+            assert_eq!(throttle.record_accepted(), None);
+        }
+        assert_eq!(throttle.record_accepted(), Some(2));
+        for _ in 0..RECOVERY_DOWNLOADS {
+            assert_eq!(throttle.record_accepted(), None);
+        }
+        assert_eq!(throttle.limit(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_throttled_download_restarts_the_recovery() {
+        let throttle = DownloadThrottle::new(1, 2, 5);
+        for _ in 1..RECOVERY_DOWNLOADS {
+            throttle.record_accepted();
+        }
+        throttle.record_throttled();
+        assert_eq!(throttle.record_accepted(), None);
+        assert_eq!(throttle.limit(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_lowered_limit_takes_back_the_permits() {
+        let throttle = DownloadThrottle::new(4, 4, 0);
+        assert_eq!(throttle.record_throttled(), Some(2));
+        // The surplus permits are collected by a task.
+        tokio::task::yield_now().await;
+        let first = throttle.acquire().await;
+        let second = throttle.acquire().await;
+        assert!(first.is_some() && second.is_some());
+        let third = tokio::time::timeout(Duration::from_millis(50), throttle.acquire()).await;
+        assert!(third.is_err(), "more downloads than the limit");
+    }
+}
@@ -0,0 +1,138 @@
+use crate::article::Article;
+use crate::filter::KeywordFilter;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// A named group of keywords, e.g. `immunotherapy=checkpoint,CAR-T`. Topics break the statistics
+/// of a run down by subject, they do not change which articles are kept.
+#[derive(Serialize, Clone, Debug)]
+pub struct Topic {
+    pub name: String,
+    pub keywords: Vec<String>,
+}
+
+pub fn parse_topic(value: &str) -> Result<Topic, String> {
+    let Some((name, keywords)) = value.split_once('=') else {
+        return Err("expected name=keyword,keyword".to_string());
+    };
+    let keywords: Vec<String> = keywords
+        .split(',')
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect();
+    if name.trim().is_empty() || keywords.is_empty() {
+        return Err("a topic needs a name and at least one keyword".to_string());
+    }
+    Ok(Topic {
+        name: name.trim().to_string(),
+        keywords,
+    })
+}
+
+#[derive(Serialize, Default, Clone, Copy, Debug)]
+pub struct TopicCounts {
+    /// Written articles that match the topic.
+    pub kept: usize,
+    /// Articles that match the topic but were dropped by the filter or the sampling.
+    pub rejected: usize,
+    /// The size of the kept articles as json.
+    pub output_bytes: usize,
+}
+
+#[derive(Serialize)]
+pub struct TopicSummary {
+    pub name: String,
+    pub keywords: Vec<String>,
+    #[serde(flatten)]
+    pub counts: TopicCounts,
+}
+
+/// The counts of every topic per input file. An article matches a topic by the same rule as the
+/// keyword filter: its title and its abstract each contain one of the keywords.
+pub struct TopicStats {
+    topics: Vec<(Topic, KeywordFilter)>,
+    files: Mutex<BTreeMap<String, Vec<TopicCounts>>>,
+}
+
+impl TopicStats {
+    pub fn new(topics: &[Topic]) -> Self {
+        Self {
+            topics: topics
+                .iter()
+                .map(|t| (t.clone(), KeywordFilter::new(t.keywords.clone())))
+                .collect(),
+            files: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.topics.is_empty()
+    }
+
+    /// The number of articles that match each topic.
+    pub fn count_matches(&self, articles: &[Article]) -> Vec<usize> {
+        self.topics
+            .iter()
+            .map(|(_, filter)| articles.iter().filter(|a| filter.is_relevant(a)).count())
+            .collect()
+    }
+
+    /// Records the counts of one file, given the matches before filtering and the kept articles.
+    /// A retried file replaces its earlier counts.
+    pub fn record(&self, file_name: &str, matches_before: &[usize], kept: &[Article]) {
+        if self.is_empty() {
+            return;
+        }
+        let counts = self
+            .topics
+            .iter()
+            .zip(matches_before)
+            .map(|((_, filter), &matched)| {
+                let kept: Vec<&Article> = kept.iter().filter(|a| filter.is_relevant(a)).collect();
+                TopicCounts {
+                    kept: kept.len(),
+                    rejected: matched.saturating_sub(kept.len()),
+                    output_bytes: kept
+                        .iter()
+                        .map(|a| serde_json::to_vec(a).map(|j| j.len()).unwrap_or(0))
+                        .sum(),
+                }
+            })
+            .collect();
+        self.files
+            .lock()
+            .unwrap()
+            .insert(file_name.to_string(), counts);
+    }
+
+    /// Moves the counts of another instance with the same topics, e.g. the one of a shard, into
+    /// this one.
+    #[cfg(feature = "thread-per-core")]
+    pub fn absorb(&self, other: &TopicStats) {
+        let mut files = self.files.lock().unwrap();
+        files.append(&mut other.files.lock().unwrap());
+    }
+
+    /// The counts of every topic over all files.
+    pub fn summary(&self) -> Vec<TopicSummary> {
+        let files = self.files.lock().unwrap();
+        self.topics
+            .iter()
+            .enumerate()
+            .map(|(index, (topic, _))| {
+                let mut counts = TopicCounts::default();
+                for file_counts in files.values() {
+                    counts.kept += file_counts[index].kept;
+                    counts.rejected += file_counts[index].rejected;
+                    counts.output_bytes += file_counts[index].output_bytes;
+                }
+                TopicSummary {
+                    name: topic.name.clone(),
+                    keywords: topic.keywords.clone(),
+                    counts,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,57 @@
+use crate::article::WarningKind;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+#[derive(Serialize)]
+struct WarningLine<'a> {
+    timestamp: String,
+    file: &'a str,
+    pmid: &'a str,
+    kind: WarningKind,
+}
+
+/// Counts the warnings of the run and, with a path, appends every single one as a json object per
+/// line, so the records behind a warning can be looked up.
+pub struct WarningLog {
+    file: Option<Mutex<File>>,
+    counts: Mutex<BTreeMap<WarningKind, usize>>,
+}
+
+impl WarningLog {
+    pub fn open(path: Option<&str>) -> std::io::Result<Self> {
+        let file = match path {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            file,
+            counts: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn record(&self, file: &str, pmid: &str, kind: WarningKind) {
+        *self.counts.lock().unwrap().entry(kind).or_default() += 1;
+        let Some(log) = &self.file else {
+            return;
+        };
+        let line = WarningLine {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            file,
+            pmid,
+            kind,
+        };
+        if let Ok(mut line) = serde_json::to_string(&line) {
+            line.push('\n');
+            let _ = log.lock().unwrap().write_all(line.as_bytes());
+        }
+    }
+
+    pub fn counts(&self) -> BTreeMap<WarningKind, usize> {
+        self.counts.lock().unwrap().clone()
+    }
+}
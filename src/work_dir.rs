@@ -0,0 +1,36 @@
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+
+/// The scratch directory of one parser for downloads, checksums and extracted files. It is
+/// removed with everything in it when the parser is dropped, which also happens when a stage
+/// panics or the task of the parser is cancelled.
+pub struct WorkDir {
+    path: PathBuf,
+}
+
+impl WorkDir {
+    /// Creates a new directory with a unique name that starts with `prefix` inside of `root`.
+    pub fn create(root: &Path, prefix: &str) -> std::io::Result<Self> {
+        // The name is taken from a TempDir, without the dot that separates its random suffix.
+        let unique = TempDir::new_in(root, prefix)?;
+        let name = unique.path().file_name().unwrap_or_default();
+        let path = root.join(name.to_string_lossy().replace(".", ""));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    /// The path of a file inside of the directory.
+    pub fn file(&self, name: &str) -> String {
+        self.path.join(name).to_string_lossy().to_string()
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_dir_all(&self.path) {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("Could not remove {}: {}", self.path.display(), error);
+            }
+        }
+    }
+}
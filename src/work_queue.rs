@@ -0,0 +1,326 @@
+use crate::config::Source;
+use crate::metrics::Channel;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// A list of input files that is shared between all parsers. Every call to next_file hands out
+/// each file exactly once, in the order the list was created with.
+pub struct WorkQueue {
+    files: Vec<String>,
+    next: AtomicUsize,
+}
+
+/// Moves the priority files to the front, in the order they are given. The other files keep their
+/// order. Returns the priority files that are not part of the list.
+pub fn prioritize(files: &mut Vec<String>, priority: &[String]) -> Vec<String> {
+    let mut missing = vec![];
+    let mut front = vec![];
+    for file_name in priority {
+        match files.iter().position(|f| f == file_name) {
+            Some(position) => front.push(files.remove(position)),
+            None if !front.contains(file_name) => missing.push(file_name.clone()),
+            None => {}
+        }
+    }
+    front.append(files);
+    *files = front;
+    missing
+}
+
+/// Where the archive of an input file is read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// Downloaded from this directory of the mirror.
+    Feed(Source),
+    /// Read from this local directory.
+    Directory(String),
+}
+
+impl Origin {
+    /// The baseline comes before the update files, which are published on top of it.
+    fn rank(&self) -> u8 {
+        match self {
+            Origin::Feed(Source::Updatefiles) => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// The origin of every input file of a run.
+pub struct Origins {
+    files: HashMap<String, Origin>,
+    /// The first feed or directory, for files that were not planned in this run, e.g. corrupt
+    /// outputs of an earlier run that are regenerated.
+    default: Origin,
+}
+
+impl Origins {
+    pub fn get(&self, file_name: &str) -> &Origin {
+        self.files.get(file_name).unwrap_or(&self.default)
+    }
+}
+
+/// The sequence number of an archive, e.g. 1220 for `pubmed24n1220.xml`.
+fn sequence_number(file_name: &str) -> Option<u32> {
+    let (_, number) = file_name.rsplit_once('n')?;
+    number.strip_suffix(".xml")?.parse().ok()
+}
+
+/// Merges the files of several feeds or input directories into one list. A single root keeps
+/// its order. Several roots are ordered like the releases build on each other: the baseline
+/// first, then the update files by sequence number, so a record that is updated later is also
+/// processed later. A file name that occurs in several roots is only taken from the first one;
+/// the names that were left out are returned as well.
+pub fn merge_roots(roots: Vec<(Origin, Vec<String>)>) -> (Vec<String>, Origins, Vec<String>) {
+    let default = match roots.first() {
+        Some((origin, _)) => origin.clone(),
+        None => Origin::Feed(Source::Baseline),
+    };
+    let several = roots.len() > 1;
+    let mut planned: Vec<(String, Origin)> = roots
+        .into_iter()
+        .flat_map(|(origin, files)| files.into_iter().map(move |f| (f, origin.clone())))
+        .collect();
+    if several {
+        // A stable sort, so duplicates stay in the order of their roots.
+        planned.sort_by_key(|(file_name, origin)| (origin.rank(), sequence_number(file_name)));
+    }
+    let mut files = Vec::with_capacity(planned.len());
+    let mut origins = HashMap::with_capacity(planned.len());
+    let mut duplicates = vec![];
+    for (file_name, origin) in planned {
+        if origins.contains_key(&file_name) {
+            duplicates.push(file_name);
+            continue;
+        }
+        origins.insert(file_name.clone(), origin);
+        files.push(file_name);
+    }
+    let origins = Origins {
+        files: origins,
+        default,
+    };
+    (files, origins, duplicates)
+}
+
+/// Keeps the outputs of several feeds or input directories in the order of merge_roots, although
+/// the parsers work on the files in parallel: a parser only writes a file once every file before
+/// it is finished, so a record that is updated later is also written later. Files are handed out
+/// in the same order, so the first unfinished file never waits. A parser that stops before its
+/// queue is empty has to finish the files it did not take, see finish_remaining, or the parsers
+/// that wait for them never write.
+pub struct ReleaseOrder {
+    positions: HashMap<String, usize>,
+    finished: Mutex<Vec<bool>>,
+    /// The position of the first file that is not finished.
+    first_open: watch::Sender<usize>,
+}
+
+impl ReleaseOrder {
+    pub fn new(files: &[String]) -> Self {
+        Self {
+            positions: files
+                .iter()
+                .enumerate()
+                .map(|(position, file_name)| (file_name.clone(), position))
+                .collect(),
+            finished: Mutex::new(vec![false; files.len()]),
+            first_open: watch::channel(0).0,
+        }
+    }
+
+    /// For a single feed or input directory, whose files are written as they are finished.
+    pub fn unordered() -> Self {
+        Self::new(&[])
+    }
+
+    /// Waits until all files before this one are finished. Files that are not part of the order
+    /// do not wait.
+    pub async fn wait_turn(&self, file_name: &str) {
+        let Some(&position) = self.positions.get(file_name) else {
+            return;
+        };
+        let mut first_open = self.first_open.subscribe();
+        // The sender lives as long as self, so waiting cannot fail.
+        let _ = first_open.wait_for(|first| *first >= position).await;
+    }
+
+    /// Marks a file as finished, whether it was written, skipped or failed.
+    pub fn finish(&self, file_name: &str) {
+        let Some(&position) = self.positions.get(file_name) else {
+            return;
+        };
+        let mut finished = self.finished.lock().unwrap();
+        finished[position] = true;
+        let mut first_open = *self.first_open.borrow();
+        while finished.get(first_open) == Some(&true) {
+            first_open += 1;
+        }
+        self.first_open.send_replace(first_open);
+    }
+
+    /// Takes the files that are left in the queue and marks them as finished without processing
+    /// them, for a parser that stops early, e.g. because the download budget is exhausted.
+    pub fn finish_remaining(&self, queue: &WorkQueue) {
+        while let Some(file_name) = queue.next_file() {
+            self.finish(&file_name);
+        }
+    }
+}
+
+impl WorkQueue {
+    pub fn new(files: Vec<String>) -> Self {
+        Channel::WorkQueue
+            .metrics()
+            .sent(files.len(), Duration::ZERO);
+        Self {
+            files,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn next_file(&self) -> Option<String> {
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        let file = self.files.get(index).cloned();
+        if file.is_some() {
+            Channel::WorkQueue.metrics().received(Duration::ZERO);
+        }
+        file
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(files: &[&str]) -> Vec<String> {
+        files.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn merge_roots_orders_several_roots_by_release_and_drops_duplicates() {
+        let baseline = Origin::Feed(Source::Baseline);
+        let updates = Origin::Feed(Source::Updatefiles);
+        let local = Origin::Directory("archives".into());
+        let (files, origins, duplicates) = merge_roots(vec![
+            (
+                updates.clone(),
+                names(&["pubmed24n1220.xml", "pubmed24n1219.xml"]),
+            ),
+            (
+                baseline.clone(),
+                names(&["pubmed24n0002.xml", "pubmed24n0001.xml"]),
+            ),
+            // Already in the baseline, so the local copy is left out.
+            (local, names(&["pubmed24n0001.xml"])),
+        ]);
+        assert_eq!(
+            files,
+            names(&[
+                "pubmed24n0001.xml",
+                "pubmed24n0002.xml",
+                "pubmed24n1219.xml",
+                "pubmed24n1220.xml"
+            ])
+        );
+        assert_eq!(duplicates, names(&["pubmed24n0001.xml"]));
+        assert_eq!(origins.get("pubmed24n0001.xml"), &baseline);
+        assert_eq!(origins.get("pubmed24n1219.xml"), &updates);
+        // Files that were not planned fall back to the first root.
+        assert_eq!(origins.get("pubmed24n9999.xml"), &updates);
+    }
+
+    #[test]
+    fn merge_roots_keeps_the_order_of_a_single_root() {
+        let files = names(&["pubmed24n0002.xml", "pubmed24n0001.xml", "custom.xml"]);
+        let (merged, _, duplicates) =
+            merge_roots(vec![(Origin::Directory("archives".into()), files.clone())]);
+        assert_eq!(merged, files);
+        assert!(duplicates.is_empty());
+    }
+
+    #[tokio::test]
+    async fn release_order_waits_for_files_that_finish_out_of_order() {
+        let order = ReleaseOrder::new(&names(&["a.xml", "b.xml", "c.xml"]));
+        let wait = Duration::from_millis(50);
+        order.wait_turn("a.xml").await;
+        order.finish("c.xml");
+        order.finish("b.xml");
+        // c.xml and b.xml are done, but a.xml is still open.
+        assert!(tokio::time::timeout(wait, order.wait_turn("c.xml"))
+            .await
+            .is_err());
+        order.finish("a.xml");
+        tokio::time::timeout(wait, order.wait_turn("c.xml"))
+            .await
+            .expect("all files before c.xml are finished");
+        // Files outside the order never wait.
+        tokio::time::timeout(wait, ReleaseOrder::unordered().wait_turn("a.xml"))
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn prioritize_moves_the_priority_files_to_the_front_in_their_order() {
+        // The files, the priority files, the order and the priority files that are missing.
+        type Case<'a> = (&'a [&'a str], &'a [&'a str], &'a [&'a str], &'a [&'a str]);
+        let cases: [Case; 4] = [
+            (
+                &["a", "b", "c", "d"],
+                &["c", "a"],
+                &["c", "a", "b", "d"],
+                &[],
+            ),
+            (&["a", "b", "c"], &[], &["a", "b", "c"], &[]),
+            // Files outside the run are reported and do not change the order.
+            (
+                &["a", "b", "c"],
+                &["x", "b", "y"],
+                &["b", "a", "c"],
+                &["x", "y"],
+            ),
+            // A priority file that is given twice is taken once and not reported as missing.
+            (&["a", "b", "c"], &["c", "c"], &["c", "a", "b"], &[]),
+        ];
+        for (files, priority, expected, missing) in cases {
+            let mut queued = names(files);
+            assert_eq!(prioritize(&mut queued, &names(priority)), names(missing));
+            assert_eq!(
+                queued,
+                names(expected),
+                "{:?} first in {:?}",
+                priority,
+                files
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn a_stopped_shard_releases_the_files_it_did_not_take() {
+        let files = names(&["a.xml", "b.xml", "c.xml", "d.xml"]);
+        let order = ReleaseOrder::new(&files);
+        // Dealt round robin like the shards of the thread-per-core mode.
+        let first_shard = WorkQueue::new(names(&["a.xml", "c.xml"]));
+        let second_shard = WorkQueue::new(names(&["b.xml", "d.xml"]));
+
+        let taken = first_shard.next_file().unwrap();
+        order.finish(&taken);
+        // The first shard stops here, with c.xml still in its queue.
+        order.finish_remaining(&first_shard);
+        assert_eq!(first_shard.next_file(), None);
+
+        let taken = second_shard.next_file().unwrap();
+        order.finish(&taken);
+        let last = second_shard.next_file().unwrap();
+        tokio::time::timeout(Duration::from_secs(1), order.wait_turn(&last))
+            .await
+            .expect("the second shard waits for a file that is never processed");
+    }
+}
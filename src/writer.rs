@@ -0,0 +1,102 @@
+use crate::metrics::Channel;
+use crate::output::SinkError;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::time::{Duration, Instant};
+
+/// How many input files can wait for the writer before the parsers block on handing over theirs.
+const CHANNEL_CAPACITY: usize = 16;
+/// The most input files that are written as one batch.
+const MAX_BATCH_SIZE: usize = 64;
+
+/// Writes the records of many input files into one merged destination.
+pub trait BatchWriter: Send + 'static {
+    type Records: Send + 'static;
+
+    /// Writes the records of a batch of input files and makes them durable, e.g. with fsync or a
+    /// committed transaction, before returning.
+    fn write_batch(&mut self, batch: Vec<(String, Self::Records)>) -> Result<(), SinkError>;
+}
+
+struct WriteRequest<R> {
+    file_name: String,
+    records: R,
+    done: Sender<Result<(), String>>,
+}
+
+/// A thread that owns a merged output, so the parsers never write to it themselves. Parsers hand
+/// over their records through a bounded channel and wait until the batch they ended up in is on
+/// disk. Everything that queued up while the writer was busy goes into the next batch, so a
+/// merged output needs far fewer syncs than there are input files. With a flush interval, the
+/// writer also waits that long for more records before it writes a batch, so a crash loses at
+/// most the records of one interval.
+pub struct WriterThread<R> {
+    sender: SyncSender<WriteRequest<R>>,
+}
+
+impl<R: Send + 'static> WriterThread<R> {
+    pub fn spawn<W: BatchWriter<Records = R>>(writer: W, flush_interval: Duration) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        std::thread::spawn(move || run(writer, receiver, flush_interval));
+        Self { sender }
+    }
+
+    /// Returns once the records are written, or with the error of the batch they were part of.
+    pub fn write(&self, file_name: &str, records: R) -> Result<(), SinkError> {
+        let (done, result) = mpsc::channel();
+        let waiting = Instant::now();
+        self.sender
+            .send(WriteRequest {
+                file_name: file_name.to_string(),
+                records,
+                done,
+            })
+            .map_err(|_| "the writer thread has stopped")?;
+        let metrics = Channel::Writer.metrics();
+        metrics.sent(1, waiting.elapsed());
+        let waiting = Instant::now();
+        let result = result.recv().map_err(|_| "the writer thread has stopped")?;
+        metrics.replied(waiting.elapsed());
+        result.map_err(|error| error.into())
+    }
+}
+
+/// Runs until all WriterThread handles are dropped.
+fn run<W: BatchWriter>(
+    mut writer: W,
+    receiver: Receiver<WriteRequest<W::Records>>,
+    flush_interval: Duration,
+) {
+    let metrics = Channel::Writer.metrics();
+    let mut waiting = Instant::now();
+    while let Ok(first) = receiver.recv() {
+        metrics.received(waiting.elapsed());
+        let deadline = Instant::now() + flush_interval;
+        let mut requests = vec![first];
+        while requests.len() < MAX_BATCH_SIZE {
+            waiting = Instant::now();
+            let next = match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => receiver.recv_timeout(left),
+                _ => receiver.try_recv().map_err(|_| RecvTimeoutError::Timeout),
+            };
+            // The rest of a flush interval without records is collecting, not waiting.
+            match next {
+                Ok(request) => {
+                    metrics.received(waiting.elapsed());
+                    requests.push(request)
+                }
+                Err(_) => break,
+            }
+        }
+        let mut replies = Vec::with_capacity(requests.len());
+        let mut batch = Vec::with_capacity(requests.len());
+        for request in requests {
+            replies.push(request.done);
+            batch.push((request.file_name, request.records));
+        }
+        let result = writer.write_batch(batch).map_err(|e| e.to_string());
+        for done in replies {
+            let _ = done.send(result.clone());
+        }
+        waiting = Instant::now();
+    }
+}
@@ -0,0 +1,523 @@
+use crate::article::{Article, ArticleField, Extraction, OversizedPolicy, WarningKind};
+use clap::ValueEnum;
+use roxmltree::{Node, ParsingOptions};
+use serde::Serialize;
+use std::io::BufRead;
+use std::sync::Arc;
+
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum XmlBackendKind {
+    /// Builds the whole document tree in memory.
+    Roxmltree,
+    /// Streams through the document. Requires the `quick-xml` feature.
+    QuickXml,
+}
+
+/// A warning about one record, see WarningKind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseWarning {
+    pub kind: WarningKind,
+    pub pmid: String,
+}
+
+/// The elements a PubmedArticle is expected to contain.
+const PUBMED_ARTICLE_CHILDREN: [&str; 2] = ["MedlineCitation", "PubmedData"];
+
+/// Reports the warnings of a complete record and keeps it if it is valid or the extraction asks
+/// for all records, and if it fits into the size limit.
+fn complete_article(
+    mut article: Article,
+    articles: &mut Vec<Article>,
+    extraction: &Extraction,
+    warn: &mut dyn FnMut(ParseWarning),
+) {
+    if !article.title.is_empty() && article.doi.is_empty() {
+        article.warnings.push(WarningKind::MissingDoi);
+    }
+    let keep = (article.is_valid() || extraction.keep_invalid)
+        && fits_record_limit(&mut article, extraction);
+    for kind in std::mem::take(&mut article.warnings) {
+        warn(ParseWarning {
+            kind,
+            pmid: article.pmid.clone(),
+        });
+    }
+    if keep {
+        articles.push(article);
+    }
+}
+
+/// Applies the record size limit of the extraction. Returns false if the record is left out.
+fn fits_record_limit(article: &mut Article, extraction: &Extraction) -> bool {
+    let Some(limit) = extraction.max_record_bytes else {
+        return true;
+    };
+    if article.text_size() <= limit {
+        return true;
+    }
+    article.warnings.push(WarningKind::OversizedRecord);
+    match extraction.oversized_records {
+        OversizedPolicy::Skip => false,
+        OversizedPolicy::Truncate => {
+            let abstract_size = article.paper_abstract.len();
+            let fits = article.truncate_to(limit);
+            if article.paper_abstract.len() < abstract_size {
+                article.warnings.push(WarningKind::TruncatedAbstract);
+            }
+            fits
+        }
+    }
+}
+
+/// Turns the contents of one PubMed xml file into the valid articles it contains. Both backends
+/// have to produce the same articles, so they can be compared and swapped freely. Progress is
+/// reported as the percentage and the number of records read so far.
+pub trait XmlBackend: Send + Sync {
+    fn parse(
+        &self,
+        xml_data: &str,
+        extraction: &Extraction,
+        progress: &mut dyn FnMut(u8, usize),
+        warn: &mut dyn FnMut(ParseWarning),
+    ) -> Result<Vec<Article>, BackendError>;
+
+    /// Parses a document of `size` bytes from a reader, e.g. an extracted file. Backends that
+    /// stream override this, the default reads the whole document into memory first.
+    fn parse_reader(
+        &self,
+        reader: &mut dyn BufRead,
+        _size: u64,
+        extraction: &Extraction,
+        progress: &mut dyn FnMut(u8, usize),
+        warn: &mut dyn FnMut(ParseWarning),
+    ) -> Result<Vec<Article>, BackendError> {
+        let mut xml_data = String::new();
+        reader.read_to_string(&mut xml_data)?;
+        self.parse(&xml_data, extraction, progress, warn)
+    }
+}
+
+pub fn create_backend(kind: XmlBackendKind) -> Result<Arc<dyn XmlBackend>, BackendError> {
+    match kind {
+        XmlBackendKind::Roxmltree => Ok(Arc::new(RoxmltreeBackend {})),
+        XmlBackendKind::QuickXml => create_quick_xml_backend(),
+    }
+}
+
+#[cfg(feature = "quick-xml")]
+fn create_quick_xml_backend() -> Result<Arc<dyn XmlBackend>, BackendError> {
+    Ok(Arc::new(quick::QuickXmlBackend {}))
+}
+
+#[cfg(not(feature = "quick-xml"))]
+fn create_quick_xml_backend() -> Result<Arc<dyn XmlBackend>, BackendError> {
+    Err(
+        "this build does not support the quick-xml backend, rebuild with --features quick-xml"
+            .into(),
+    )
+}
+
+/// Builds the tree of the whole document, so a file is held in memory completely, also when it
+/// is read with parse_reader.
+pub struct RoxmltreeBackend {}
+
+impl RoxmltreeBackend {
+    /// Only walks down to the elements the extraction needs, e.g. the ReferenceList is never
+    /// visited.
+    fn process_one_pubmed_article(pubmed_article: Node, extraction: &Extraction) -> Article {
+        let mut article = Article::new();
+        article.source_range = Some(pubmed_article.range());
+        let unknown = pubmed_article
+            .children()
+            .filter(|child| child.is_element())
+            .any(|child| !PUBMED_ARTICLE_CHILDREN.contains(&child.tag_name().name()));
+        if unknown {
+            article.warnings.push(WarningKind::UnknownElement);
+        }
+        for child in pubmed_article.children() {
+            match child.tag_name().name() {
+                "MedlineCitation" => {
+                    article.set_pmid_version(child);
+                    Self::process_medline_citation(&mut article, child, extraction);
+                }
+                "PubmedData" => article.set_from_pubmed_data(child),
+                _ => {}
+            }
+        }
+        article
+    }
+
+    fn process_medline_citation(article: &mut Article, citation: Node, extraction: &Extraction) {
+        for child in citation.children() {
+            match child.tag_name().name() {
+                "Article" => article.set_from_article_data(child, extraction),
+                "OtherAbstract" if extraction.other_abstracts => article.add_other_abstract(child),
+                "MeshHeadingList" if extraction.has(ArticleField::Mesh) => {
+                    article.set_mesh_terms(child)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl XmlBackend for RoxmltreeBackend {
+    fn parse(
+        &self,
+        xml_data: &str,
+        extraction: &Extraction,
+        progress: &mut dyn FnMut(u8, usize),
+        warn: &mut dyn FnMut(ParseWarning),
+    ) -> Result<Vec<Article>, BackendError> {
+        let opts = ParsingOptions {
+            allow_dtd: true,
+            nodes_limit: u32::MAX,
+        };
+        let doc = roxmltree::Document::parse_with_options(xml_data, opts)?;
+        let mut articles = vec![];
+        let mut last_reported_percentage: u8 = 0;
+        let mut processed_articles = 0;
+        let itter = doc
+            .root()
+            .descendants()
+            .filter(|n| n.tag_name().name() == "PubmedArticle");
+
+        let total_n_articles = itter.clone().count();
+        for pubmed_article in itter {
+            let article = RoxmltreeBackend::process_one_pubmed_article(pubmed_article, extraction);
+            complete_article(article, &mut articles, extraction, warn);
+            processed_articles += 1;
+            let new_percentage =
+                (100.0 * processed_articles as f32 / total_n_articles as f32).floor() as u8;
+            if new_percentage > last_reported_percentage {
+                last_reported_percentage = new_percentage;
+                progress(last_reported_percentage, processed_articles);
+            }
+        }
+        Ok(articles)
+    }
+}
+
+#[cfg(feature = "quick-xml")]
+mod quick {
+    use super::{BackendError, ParseWarning, PUBMED_ARTICLE_CHILDREN};
+    use crate::article::{
+        abstract_section, pmid_version, year_from_date, Article, ArticleField, Author, Extraction,
+        Journal, OtherAbstract, WarningKind,
+    };
+    use quick_xml::events::{BytesStart, Event};
+    use quick_xml::Reader;
+    use std::io::BufRead;
+
+    /// Streams through the document and keeps only the article that is currently being read in
+    /// memory. Elements are identified by their name and the name of their parent, which mirrors
+    /// the tree walk of the roxmltree backend.
+    pub struct QuickXmlBackend {}
+
+    #[derive(Default)]
+    struct StreamState {
+        path: Vec<String>,
+        article: Option<Article>,
+        /// Where the start tag of the current PubmedArticle begins.
+        article_offset: usize,
+        /// The depth of the element whose text is currently collected.
+        capture_depth: Option<usize>,
+        text: String,
+        attribute: Option<String>,
+        abstract_sections: Vec<String>,
+        other_abstract_sections: Vec<String>,
+        other_abstract_language: String,
+        author: Author,
+        year: String,
+        medline_date: String,
+    }
+
+    impl StreamState {
+        /// The name of the n-th ancestor of the current element, 1 being the parent.
+        fn ancestor(&self, n: usize) -> &str {
+            match self.path.len().checked_sub(n + 1) {
+                Some(index) => &self.path[index],
+                None => "",
+            }
+        }
+
+        fn parent(&self) -> &str {
+            self.ancestor(1)
+        }
+
+        fn is_captured(&self) -> bool {
+            matches!(
+                (self.parent(), self.ancestor(0)),
+                ("Article", "ArticleTitle")
+                    | ("MedlineCitation", "PMID")
+                    | ("Article", "Language")
+                    | ("Abstract", "AbstractText")
+                    | ("OtherAbstract", "AbstractText")
+                    | ("Author", "LastName")
+                    | ("Author", "ForeName")
+                    | ("Author", "CollectiveName")
+                    | ("AffiliationInfo", "Affiliation")
+                    | ("Journal", "ISSN")
+                    | ("Journal", "Title")
+                    | ("JournalIssue", "Volume")
+                    | ("JournalIssue", "Issue")
+                    | ("PubDate", "Year")
+                    | ("PubDate", "MedlineDate")
+                    | ("Pagination", "MedlinePgn")
+                    | ("MeshHeading", "DescriptorName")
+                    | ("ArticleIdList", "ArticleId")
+            )
+        }
+
+        /// Subtrees the extraction does not need, they are skipped without being parsed.
+        fn is_skipped(&self, element: &BytesStart, extraction: &Extraction) -> bool {
+            match (self.ancestor(0), element.local_name().as_ref()) {
+                ("PubmedData", b"ReferenceList") => true,
+                ("Article", b"AuthorList") => !extraction.has(ArticleField::Authors),
+                ("Article", b"Journal") => {
+                    !extraction.has(ArticleField::Journal) && !extraction.has(ArticleField::Date)
+                }
+                ("MedlineCitation", b"MeshHeadingList") => !extraction.has(ArticleField::Mesh),
+                ("MedlineCitation", b"OtherAbstract") => !extraction.other_abstracts,
+                _ => false,
+            }
+        }
+
+        fn start(&mut self, element: &BytesStart) -> Result<(), BackendError> {
+            let name = String::from_utf8_lossy(element.local_name().as_ref()).to_string();
+            match name.as_str() {
+                "PubmedArticle" => self.article = Some(Article::new()),
+                "Abstract" => self.abstract_sections.clear(),
+                "OtherAbstract" => {
+                    self.other_abstract_sections.clear();
+                    self.other_abstract_language = match element.try_get_attribute("Language")? {
+                        Some(attribute) => attribute.unescape_value()?.to_string(),
+                        None => String::new(),
+                    };
+                }
+                "Author" => self.author = Author::default(),
+                "PubDate" => {
+                    self.year.clear();
+                    self.medline_date.clear();
+                }
+                _ => {}
+            }
+            if self.ancestor(0) == "PubmedArticle"
+                && !PUBMED_ARTICLE_CHILDREN.contains(&name.as_str())
+            {
+                if let Some(article) = self.article.as_mut() {
+                    if !article.warnings.contains(&WarningKind::UnknownElement) {
+                        article.warnings.push(WarningKind::UnknownElement);
+                    }
+                }
+            }
+            self.path.push(name);
+            if self.article.is_some() && self.capture_depth.is_none() && self.is_captured() {
+                self.capture_depth = Some(self.path.len());
+                self.text.clear();
+                let attribute_name = match self.ancestor(0) {
+                    "AbstractText" => "Label",
+                    "PMID" => "Version",
+                    _ => "IdType",
+                };
+                self.attribute = match element.try_get_attribute(attribute_name)? {
+                    Some(attribute) => Some(attribute.unescape_value()?.to_string()),
+                    None => None,
+                };
+            }
+            Ok(())
+        }
+
+        /// Handles the end of the current element. Returns the article once a PubmedArticle is
+        /// complete.
+        fn end(&mut self, extraction: &Extraction) -> Option<Article> {
+            if self.capture_depth == Some(self.path.len()) {
+                self.capture_depth = None;
+                self.set_captured_text(extraction);
+            }
+            let name = self.path.pop().unwrap_or_default();
+            let parent = self.path.last().map(|n| n.as_str()).unwrap_or("");
+            let article = self.article.as_mut()?;
+            match (parent, name.as_str()) {
+                ("Article", "Abstract") => {
+                    article.paper_abstract = self.abstract_sections.join("\n");
+                }
+                ("MedlineCitation", "OtherAbstract") => {
+                    article.other_abstracts.push(OtherAbstract {
+                        language: std::mem::take(&mut self.other_abstract_language),
+                        text: self.other_abstract_sections.join("\n"),
+                    });
+                }
+                ("AuthorList", "Author") if extraction.has(ArticleField::Authors) => {
+                    article.authors.push(std::mem::take(&mut self.author));
+                }
+                ("JournalIssue", "PubDate") if extraction.has(ArticleField::Date) => {
+                    let date = if self.year.is_empty() {
+                        &self.medline_date
+                    } else {
+                        &self.year
+                    };
+                    article.publication_year = year_from_date(date);
+                }
+                (_, "PubmedArticle") => return self.article.take(),
+                _ => {}
+            }
+            None
+        }
+
+        fn set_captured_text(&mut self, extraction: &Extraction) {
+            let text = std::mem::take(&mut self.text);
+            let name = self.ancestor(0).to_string();
+            let parent = self.parent().to_string();
+            let in_pubmed_data = self.ancestor(2) == "PubmedData";
+            let Some(article) = self.article.as_mut() else {
+                return;
+            };
+            let journal = extraction.has(ArticleField::Journal);
+            match (parent.as_str(), name.as_str()) {
+                ("Article", "ArticleTitle") => {
+                    if !article.title.is_empty() {
+                        article.warnings.push(WarningKind::MultipleTitles);
+                    }
+                    article.title = text;
+                }
+                ("MedlineCitation", "PMID") => {
+                    article.pmid_version = pmid_version(self.attribute.as_deref())
+                }
+                ("Article", "Language") if extraction.has(ArticleField::Language) => {
+                    article.languages.push(text)
+                }
+                ("Abstract", "AbstractText") => self
+                    .abstract_sections
+                    .push(abstract_section(self.attribute.as_deref(), text)),
+                ("OtherAbstract", "AbstractText") => self
+                    .other_abstract_sections
+                    .push(abstract_section(self.attribute.as_deref(), text)),
+                ("Author", "LastName") => self.author.last_name = text,
+                ("Author", "CollectiveName") if self.author.last_name.is_empty() => {
+                    self.author.last_name = text
+                }
+                ("Author", "ForeName") => self.author.fore_name = text,
+                ("AffiliationInfo", "Affiliation") => self.author.affiliations.push(text),
+                ("Journal", "ISSN") if journal => {
+                    article.journal.get_or_insert_with(Journal::default).issn = text
+                }
+                ("Journal", "Title") if journal => {
+                    article.journal.get_or_insert_with(Journal::default).title = text
+                }
+                ("JournalIssue", "Volume") if journal => {
+                    article.journal.get_or_insert_with(Journal::default).volume = text
+                }
+                ("JournalIssue", "Issue") if journal => {
+                    article.journal.get_or_insert_with(Journal::default).issue = text
+                }
+                ("Pagination", "MedlinePgn") if journal => {
+                    article.journal.get_or_insert_with(Journal::default).pages = text
+                }
+                ("PubDate", "Year") => self.year = text,
+                ("PubDate", "MedlineDate") => self.medline_date = text,
+                ("MeshHeading", "DescriptorName")
+                    if extraction.has(ArticleField::Mesh) && !text.is_empty() =>
+                {
+                    article.mesh_terms.push(text)
+                }
+                ("ArticleIdList", "ArticleId") if in_pubmed_data => {
+                    match self.attribute.as_deref() {
+                        Some("doi") => article.doi = text,
+                        Some("pubmed") => article.pmid = text,
+                        Some("pmc") => article.pmc = text,
+                        Some("pii") => article.pii = text,
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    impl super::XmlBackend for QuickXmlBackend {
+        fn parse(
+            &self,
+            xml_data: &str,
+            extraction: &Extraction,
+            progress: &mut dyn FnMut(u8, usize),
+            warn: &mut dyn FnMut(ParseWarning),
+        ) -> Result<Vec<Article>, BackendError> {
+            let size = xml_data.len() as u64;
+            self.parse_reader(&mut xml_data.as_bytes(), size, extraction, progress, warn)
+        }
+
+        /// Only the record that is currently read and the articles that are kept are held in
+        /// memory, not the document.
+        fn parse_reader(
+            &self,
+            input: &mut dyn BufRead,
+            size: u64,
+            extraction: &Extraction,
+            progress: &mut dyn FnMut(u8, usize),
+            warn: &mut dyn FnMut(ParseWarning),
+        ) -> Result<Vec<Article>, BackendError> {
+            let mut reader = Reader::from_reader(input);
+            reader.trim_text(false);
+            let mut state = StreamState::default();
+            let mut articles = vec![];
+            let mut last_reported_percentage: u8 = 0;
+            let mut records = 0;
+            let total_size = size.max(1) as usize;
+            let mut buffer = vec![];
+            let mut skipped = vec![];
+            loop {
+                buffer.clear();
+                let offset = reader.buffer_position();
+                let completed = match reader.read_event_into(&mut buffer)? {
+                    Event::Start(element) if state.is_skipped(&element, extraction) => {
+                        skipped.clear();
+                        reader.read_to_end_into(element.name(), &mut skipped)?;
+                        None
+                    }
+                    Event::Start(element) => {
+                        if element.local_name().as_ref() == b"PubmedArticle" {
+                            state.article_offset = offset;
+                        }
+                        state.start(&element)?;
+                        None
+                    }
+                    Event::Empty(element) => {
+                        state.start(&element)?;
+                        state.end(extraction)
+                    }
+                    Event::End(_) => state.end(extraction),
+                    Event::Text(text) => {
+                        if state.capture_depth.is_some() {
+                            state.text.push_str(&text.unescape()?);
+                        }
+                        None
+                    }
+                    Event::CData(data) => {
+                        if state.capture_depth.is_some() {
+                            state.text.push_str(&String::from_utf8_lossy(&data));
+                        }
+                        None
+                    }
+                    Event::Eof => break,
+                    _ => None,
+                };
+                if let Some(mut article) = completed {
+                    article.source_range = Some(state.article_offset..reader.buffer_position());
+                    super::complete_article(article, &mut articles, extraction, warn);
+                    records += 1;
+                    let new_percentage =
+                        (100 * reader.buffer_position() / total_size).min(100) as u8;
+                    if new_percentage > last_reported_percentage {
+                        last_reported_percentage = new_percentage;
+                        progress(last_reported_percentage, records);
+                    }
+                }
+            }
+            Ok(articles)
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! The abstract that --filter-abstract and --export-abstract choose, see Article::abstract_text.
+use hcse_parser::article::AbstractSource;
+use hcse_parser::{parse_pubmed_xml, Article};
+
+fn article(other_abstracts: &str) -> Article {
+    let xml = format!(
+        r#"<PubmedArticleSet>
+  <PubmedArticle>
+    <MedlineCitation>
+      <PMID Version="1">100</PMID>
+      <Article>
+        <ArticleTitle>A tumor study</ArticleTitle>
+        <Abstract><AbstractText>Cancer is common.</AbstractText></Abstract>
+      </Article>
+      {}
+    </MedlineCitation>
+    <PubmedData>
+      <ArticleIdList><ArticleId IdType="doi">10.1000/tumor</ArticleId></ArticleIdList>
+    </PubmedData>
+  </PubmedArticle>
+</PubmedArticleSet>"#,
+        other_abstracts
+    );
+    parse_pubmed_xml(&xml).unwrap().remove(0)
+}
+
+const TRANSLATIONS: &str = r#"
+      <OtherAbstract Type="Publisher" Language="ger"><AbstractText>Krebs ist häufig.</AbstractText></OtherAbstract>
+      <OtherAbstract Type="Publisher" Language="fre"><AbstractText>Le cancer est fréquent.</AbstractText></OtherAbstract>"#;
+
+#[test]
+fn main_abstract_ignores_other_abstracts() {
+    let article = article(TRANSLATIONS);
+    assert_eq!(
+        article.abstract_text(AbstractSource::Main, None),
+        "Cancer is common."
+    );
+    assert_eq!(
+        article.abstract_text(AbstractSource::Main, Some("fre")),
+        "Cancer is common."
+    );
+}
+
+#[test]
+fn other_abstract_is_the_first_one_or_the_one_in_the_language() {
+    let article = article(TRANSLATIONS);
+    assert_eq!(
+        article.abstract_text(AbstractSource::Other, None),
+        "Krebs ist häufig."
+    );
+    assert_eq!(
+        article.abstract_text(AbstractSource::Other, Some("fre")),
+        "Le cancer est fréquent."
+    );
+}
+
+#[test]
+fn other_abstract_falls_back_to_the_main_abstract() {
+    assert_eq!(
+        article("").abstract_text(AbstractSource::Other, None),
+        "Cancer is common."
+    );
+    assert_eq!(
+        article(TRANSLATIONS).abstract_text(AbstractSource::Other, Some("spa")),
+        "Cancer is common."
+    );
+}
+
+#[test]
+fn all_abstracts_are_joined_in_order() {
+    let article = article(TRANSLATIONS);
+    assert_eq!(
+        article.abstract_text(AbstractSource::All, None),
+        "Cancer is common.\nKrebs ist häufig.\nLe cancer est fréquent."
+    );
+    assert_eq!(
+        article.abstract_text(AbstractSource::All, Some("ger")),
+        "Cancer is common.\nKrebs ist häufig."
+    );
+}
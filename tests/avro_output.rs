@@ -0,0 +1,69 @@
+//! The avro output has to be readable by the reference implementation and hold the same articles
+//! as the jsonl output of the same archive.
+mod common;
+
+use common::run;
+use hcse_parser::Article;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+
+/// Parses the fixture archive with all fields and returns the single output file.
+fn parse_fixture(directory: &Path, format: &str) -> PathBuf {
+    let run_directory = directory.join(format);
+    std::fs::create_dir_all(run_directory.join("out")).unwrap();
+    let input = directory.join("fixture/baseline");
+    run(
+        &run_directory,
+        &[
+            "--input-dir",
+            input.to_str().unwrap(),
+            "--start",
+            "1",
+            "--end",
+            "1",
+            "-p",
+            "1",
+            "--fields",
+            "authors,journal,date,language,mesh",
+            "--output-format",
+            format,
+            "--output-path",
+            "out",
+            "--temp-dir",
+            run_directory.join("tmp").to_str().unwrap(),
+        ],
+    );
+    let mut outputs: Vec<PathBuf> = std::fs::read_dir(run_directory.join("out"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(outputs.len(), 1, "{:?}", outputs);
+    outputs.pop().unwrap()
+}
+
+#[test]
+fn avro_output_round_trips_through_apache_avro() {
+    let directory = TempDir::new("avro_output").unwrap();
+    run(
+        directory.path(),
+        &["gen-fixture", "--articles", "200", "--relevant-rate", "0.5"],
+    );
+
+    let jsonl = parse_fixture(directory.path(), "jsonl");
+    let expected: Vec<Article> = BufReader::new(File::open(jsonl).unwrap())
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+        .collect();
+    assert!(!expected.is_empty());
+    assert!(expected.iter().any(|a| a.journal.is_some()));
+    assert!(expected.iter().any(|a| !a.authors.is_empty()));
+
+    let avro = parse_fixture(directory.path(), "avro");
+    let reader = apache_avro::Reader::new(File::open(avro).unwrap()).unwrap();
+    let articles: Vec<Article> = reader
+        .map(|value| apache_avro::from_value(&value.unwrap()).unwrap())
+        .collect();
+    assert_eq!(articles, expected);
+}
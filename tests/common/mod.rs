@@ -0,0 +1,21 @@
+//! Helpers for the tests that run the binary.
+use std::path::Path;
+use std::process::Command;
+
+const PARSER: &str = env!("CARGO_BIN_EXE_hcse_parser");
+
+/// Runs the parser in the directory and returns what it printed to stdout.
+pub fn run(directory: &Path, args: &[&str]) -> String {
+    let output = Command::new(PARSER)
+        .current_dir(directory)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
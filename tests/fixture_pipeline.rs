@@ -0,0 +1,107 @@
+//! Runs the parser over a generated fixture, which tells how many articles it has to keep.
+mod common;
+
+use common::run;
+use hcse_parser::Article;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tempdir::TempDir;
+
+const KEYWORDS: [&str; 2] = ["cancer", "tumor"];
+
+/// Generates a fixture of two archives and returns the number of relevant articles it reports.
+fn generate(directory: &Path, seed: &str) -> usize {
+    let summary = run(
+        directory,
+        &[
+            "gen-fixture",
+            "--files",
+            "2",
+            "--articles",
+            "300",
+            "--edge-case-rate",
+            "0.2",
+            "--seed",
+            seed,
+        ],
+    );
+    let (counts, _) = summary.split_once(" relevant for ").unwrap();
+    counts.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+fn archives(directory: &Path) -> BTreeMap<String, Vec<u8>> {
+    std::fs::read_dir(directory.join("fixture/baseline"))
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            (name, std::fs::read(path).unwrap())
+        })
+        .collect()
+}
+
+#[test]
+fn same_seed_gives_identical_archives() {
+    let first = TempDir::new("fixture_first").unwrap();
+    let second = TempDir::new("fixture_second").unwrap();
+    let other_seed = TempDir::new("fixture_other_seed").unwrap();
+    generate(first.path(), "7");
+    generate(second.path(), "7");
+    generate(other_seed.path(), "8");
+
+    let archives_of_first = archives(first.path());
+    assert_eq!(archives_of_first.len(), 5, "{:?}", archives_of_first.keys());
+    assert_eq!(archives_of_first, archives(second.path()));
+    assert_ne!(archives_of_first, archives(other_seed.path()));
+}
+
+#[test]
+fn parser_keeps_the_relevant_articles_of_the_fixture() {
+    let directory = TempDir::new("fixture_pipeline").unwrap();
+    let relevant = generate(directory.path(), "7");
+    assert!(relevant > 0);
+
+    std::fs::create_dir(directory.path().join("out")).unwrap();
+    let temp_dir = directory.path().join("tmp");
+    run(
+        directory.path(),
+        &[
+            "--input-dir",
+            "fixture/baseline",
+            "--start",
+            "1",
+            "--end",
+            "2",
+            "-p",
+            "1",
+            "--output-format",
+            "jsonl",
+            "--output-path",
+            "out",
+            "--temp-dir",
+            temp_dir.to_str().unwrap(),
+        ],
+    );
+
+    let mut articles: Vec<Article> = vec![];
+    for entry in std::fs::read_dir(directory.path().join("out")).unwrap() {
+        let content = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        for line in content.lines() {
+            articles.push(serde_json::from_str(line).unwrap());
+        }
+    }
+    assert_eq!(articles.len(), relevant);
+    for article in &articles {
+        assert!(!article.doi.is_empty(), "{:?}", article);
+        let title = article.title.to_lowercase();
+        let paper_abstract = article.paper_abstract.to_lowercase();
+        assert!(
+            KEYWORDS.iter().any(|keyword| title.contains(keyword))
+                && KEYWORDS
+                    .iter()
+                    .any(|keyword| paper_abstract.contains(keyword)),
+            "{:?}",
+            article
+        );
+    }
+}
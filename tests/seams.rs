@@ -0,0 +1,138 @@
+//! The efetch client and the pipeline with fakes for the network, the clock and the file system.
+use hcse_parser::clock::ManualClock;
+use hcse_parser::efetch::Efetch;
+use hcse_parser::fetcher::FetchError;
+use hcse_parser::file_system::MemoryFileSystem;
+use hcse_parser::pipeline::{Pipeline, XmlFiles};
+use hcse_parser::{Article, ExtractionProfile};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn record(pmid: &str) -> String {
+    format!(
+        "<PubmedArticle><MedlineCitation><PMID Version=\"1\">{pmid}</PMID><Article>\
+         <ArticleTitle>Tumor study {pmid}</ArticleTitle><Abstract><AbstractText>Text.\
+         </AbstractText></Abstract></Article></MedlineCitation><PubmedData><ArticleIdList>\
+         <ArticleId IdType=\"pubmed\">{pmid}</ArticleId><ArticleId IdType=\"doi\">10.5555/{pmid}\
+         </ArticleId></ArticleIdList></PubmedData></PubmedArticle>"
+    )
+}
+
+fn document(pmids: &[&str]) -> String {
+    let records: String = pmids.iter().map(|pmid| record(pmid)).collect();
+    format!("<PubmedArticleSet>{}</PubmedArticleSet>", records)
+}
+
+fn efetch(api_key: Option<&str>) -> Efetch {
+    Efetch {
+        eutils_url: "https://eutils.example.org/entrez/eutils/".to_string(),
+        api_key: api_key.map(str::to_string),
+        email: Some("a+b@example.org".to_string()),
+        batch_size: 2,
+    }
+}
+
+#[tokio::test]
+async fn efetch_batches_and_paces_the_requests() {
+    let requests = Mutex::new(vec![]);
+    let fetcher = |url: &str| -> Result<Vec<u8>, FetchError> {
+        requests.lock().unwrap().push(url.to_string());
+        let url = reqwest::Url::parse(url)?;
+        let (_, ids) = url.query_pairs().find(|(key, _)| key == "id").unwrap();
+        let ids: Vec<&str> = ids.split(',').collect();
+        Ok(document(&ids).into_bytes())
+    };
+    let clock = ManualClock::new();
+    let pmids: Vec<String> = ["1", "2", "3", "4", "5"].map(String::from).to_vec();
+    let mut batches = vec![];
+    efetch(None)
+        .fetch(
+            &pmids,
+            &ExtractionProfile::Standard.extraction(&[]),
+            &fetcher,
+            &clock,
+            &mut |index, articles: Vec<Article>| {
+                let pmids: Vec<String> = articles.into_iter().map(|a| a.pmid).collect();
+                batches.push((index, pmids));
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        batches,
+        vec![
+            (0, vec!["1".to_string(), "2".to_string()]),
+            (1, vec!["3".to_string(), "4".to_string()]),
+            (2, vec!["5".to_string()]),
+        ]
+    );
+    // Two pauses between three requests, which took no time on the manual clock.
+    assert_eq!(clock.elapsed(), 2 * Duration::from_millis(340));
+    let requests = requests.into_inner().unwrap();
+    assert_eq!(
+        requests[0],
+        "https://eutils.example.org/entrez/eutils/efetch.fcgi?db=pubmed&retmode=xml&id=1%2C2\
+         &tool=hcse_parser&email=a%2Bb%40example.org"
+    );
+}
+
+#[tokio::test]
+async fn efetch_stops_at_the_first_failed_request() {
+    let fetcher = |url: &str| -> Result<Vec<u8>, FetchError> {
+        match url.contains("id=1%2C2") {
+            true => Ok(document(&["1", "2"]).into_bytes()),
+            false => Err("503 Service Unavailable".into()),
+        }
+    };
+    let clock = ManualClock::new();
+    let pmids: Vec<String> = ["1", "2", "3", "4"].map(String::from).to_vec();
+    let mut written = 0;
+    let result = efetch(Some("key"))
+        .fetch(
+            &pmids,
+            &ExtractionProfile::Standard.extraction(&[]),
+            &fetcher,
+            &clock,
+            &mut |_, articles: Vec<Article>| {
+                written += articles.len();
+                Ok(())
+            },
+        )
+        .await;
+
+    assert_eq!(result.unwrap_err().to_string(), "503 Service Unavailable");
+    assert_eq!(written, 2);
+    assert_eq!(clock.elapsed(), Duration::from_millis(100));
+}
+
+#[test]
+fn pipeline_reads_the_xml_files_from_the_file_system() {
+    let file_system = MemoryFileSystem::new()
+        .with_file("a.xml", document(&["1", "2"]))
+        .with_file("b.xml", document(&["3"]));
+    let source =
+        XmlFiles::new(vec!["a.xml".into(), "b.xml".into()]).file_system(Arc::new(file_system));
+    let (stats, articles) = Pipeline::new(source)
+        .filter(|article: &Article| article.pmid != "2")
+        .sink(vec![])
+        .run_into()
+        .unwrap();
+
+    assert_eq!((stats.batches, stats.read, stats.written), (2, 3, 2));
+    let pmids: Vec<&str> = articles.iter().map(|a| a.pmid.as_str()).collect();
+    assert_eq!(pmids, ["1", "3"]);
+}
+
+#[test]
+fn pipeline_fails_for_a_missing_file() {
+    let source =
+        XmlFiles::new(vec!["missing.xml".into()]).file_system(Arc::new(MemoryFileSystem::new()));
+    let error = Pipeline::new(source).sink(vec![]).run().unwrap_err();
+    assert!(
+        error.to_string().contains("could not read missing.xml"),
+        "{}",
+        error
+    );
+}
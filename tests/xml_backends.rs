@@ -0,0 +1,106 @@
+//! Both xml backends have to produce the same articles and warnings for the same document.
+#![cfg(feature = "quick-xml")]
+
+use hcse_parser::xml_backend::{create_backend, ParseWarning, XmlBackendKind};
+use hcse_parser::{Article, ExtractionProfile};
+
+const DOCUMENT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE PubmedArticleSet PUBLIC "-//NLM//DTD PubMedArticle, 1st January 2024//EN" "https://dtd.nlm.nih.gov/ncbi/pubmed/out/pubmed_240101.dtd">
+<PubmedArticleSet>
+  <PubmedArticle>
+    <MedlineCitation Status="MEDLINE" Owner="NLM">
+      <PMID Version="2">100</PMID>
+      <Article PubModel="Print">
+        <Journal>
+          <ISSN IssnType="Electronic">1234-5678</ISSN>
+          <JournalIssue CitedMedium="Internet">
+            <Volume>12</Volume>
+            <Issue>3</Issue>
+            <PubDate><Year>2023</Year></PubDate>
+          </JournalIssue>
+          <Title>Journal of Oncology &amp; Tumors</Title>
+        </Journal>
+        <ArticleTitle>A <i>tumor</i> study</ArticleTitle>
+        <Pagination><MedlinePgn>10-20</MedlinePgn></Pagination>
+        <Abstract>
+          <AbstractText Label="BACKGROUND">Cancer is common.</AbstractText>
+          <AbstractText Label="RESULTS">Tumors shrank.</AbstractText>
+        </Abstract>
+        <AuthorList>
+          <Author>
+            <LastName>Doe</LastName>
+            <ForeName>Jane</ForeName>
+            <AffiliationInfo><Affiliation>University</Affiliation></AffiliationInfo>
+          </Author>
+          <Author><CollectiveName>The Study Group</CollectiveName></Author>
+        </AuthorList>
+        <Language>eng</Language>
+        <Language>ger</Language>
+      </Article>
+      <MeshHeadingList>
+        <MeshHeading><DescriptorName UI="D009369">Neoplasms</DescriptorName></MeshHeading>
+      </MeshHeadingList>
+      <OtherAbstract Type="Publisher" Language="ger">
+        <AbstractText>Krebs ist häufig.</AbstractText>
+      </OtherAbstract>
+    </MedlineCitation>
+    <PubmedData>
+      <ArticleIdList>
+        <ArticleId IdType="pubmed">100</ArticleId>
+        <ArticleId IdType="doi">10.1000/tumor</ArticleId>
+        <ArticleId IdType="pmc">PMC100</ArticleId>
+        <ArticleId IdType="pii">S0000</ArticleId>
+      </ArticleIdList>
+    </PubmedData>
+  </PubmedArticle>
+  <PubmedArticle>
+    <MedlineCitation Status="MEDLINE" Owner="NLM">
+      <PMID Version="1">101</PMID>
+      <Article PubModel="Print">
+        <Journal>
+          <JournalIssue><PubDate><MedlineDate>1998 Dec-1999 Jan</MedlineDate></PubDate></JournalIssue>
+        </Journal>
+        <ArticleTitle>No identifier</ArticleTitle>
+      </Article>
+    </MedlineCitation>
+    <PubmedData><ArticleIdList><ArticleId IdType="pubmed">101</ArticleId></ArticleIdList></PubmedData>
+  </PubmedArticle>
+  <PubmedArticle>
+    <MedlineCitation Status="MEDLINE" Owner="NLM">
+      <PMID Version="1">102</PMID>
+      <Article PubModel="Print"><ArticleTitle></ArticleTitle></Article>
+    </MedlineCitation>
+    <PubmedData><ArticleIdList><ArticleId IdType="doi">10.1000/untitled</ArticleId></ArticleIdList></PubmedData>
+    <Unexpected/>
+  </PubmedArticle>
+</PubmedArticleSet>
+"#;
+
+fn parse(kind: XmlBackendKind, profile: ExtractionProfile) -> (Vec<Article>, Vec<ParseWarning>) {
+    let mut warnings = vec![];
+    let articles = create_backend(kind)
+        .unwrap()
+        .parse(
+            DOCUMENT,
+            &profile.extraction(&[]),
+            &mut |_, _| {},
+            &mut |warning| warnings.push(warning),
+        )
+        .unwrap();
+    (articles, warnings)
+}
+
+#[test]
+fn backends_produce_the_same_articles() {
+    for profile in [
+        ExtractionProfile::Minimal,
+        ExtractionProfile::Standard,
+        ExtractionProfile::Full,
+    ] {
+        let (expected, expected_warnings) = parse(XmlBackendKind::Roxmltree, profile);
+        let (actual, actual_warnings) = parse(XmlBackendKind::QuickXml, profile);
+        assert!(!expected.is_empty());
+        assert_eq!(expected, actual, "{:?}", profile);
+        assert_eq!(expected_warnings, actual_warnings, "{:?}", profile);
+    }
+}